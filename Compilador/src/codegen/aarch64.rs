@@ -0,0 +1,301 @@
+use crate::codegen::{constant_array_data, print_function, FrameLayout};
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
+
+// AAPCS64: los ocho primeros argumentos en x0-x7 y el resto en la pila.
+// x9-x11 sirven de registros de trabajo y x16 para direcciones de globales;
+// ninguno se conserva entre llamadas.
+const ARG_REGISTERS: [&str; 8] = ["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7"];
+
+// Sintaxis del ensamblador de GNU para Linux; NASM no ensambla ARM
+pub fn generate_aarch64_asm(program: IRProgram) -> String {
+    let mut output = String::new();
+
+    // String literals
+    if !program.strings.is_empty() {
+        output.push_str(".data\n");
+        for (label, value) in &program.strings {
+            output.push_str(&format!("{}: .asciz \"{}\"\n", label, encode_string_for_gas(value)));
+        }
+        output.push('\n');
+    }
+
+    // Arrays constantes, con la palabra de longitud delante como los del heap
+    if !program.constant_arrays.is_empty() {
+        output.push_str(".section .rodata\n");
+        output.push_str(".balign 8\n");
+        for (label, values) in &program.constant_arrays {
+            output.push_str(&format!("{}: .quad {}\n", label, constant_array_data(values)));
+        }
+        output.push('\n');
+    }
+
+    // Header; los símbolos sin definir son externos sin declararlos
+    output.push_str(".text\n");
+    output.push_str(".globl _start\n\n");
+
+    // Solo un main que retorna valor define el código de salida
+    let main_returns_value = program.functions.iter().any(|f| {
+        f.name == "main" && f.instructions.iter().any(|i| matches!(i, IROp::Return(Some(_))))
+    });
+
+    // Generate functions
+    for func in program.functions {
+        let frame = Frame::new(&func);
+
+        output.push_str(&format!(".type {}, %function\n", func.name));
+        output.push_str(&format!("{}:\n", func.name));
+        output.push_str("    stp x29, x30, [sp, #-16]!\n");
+        output.push_str("    mov x29, sp\n");
+        if frame.size > 0 {
+            output.push_str(&adjust_sp("sub", frame.size));
+        }
+
+        // Los parámetros llegan en x0-x7 y, a partir del noveno, en la pila
+        // por encima del par x29/x30 guardado
+        for (index, param) in func.params.iter().enumerate() {
+            let slot = frame.slot(&IRValue::Local(param.clone()));
+            match ARG_REGISTERS.get(index) {
+                Some(register) => output.push_str(&format!("    str {}, {}\n", register, slot)),
+                None => output.push_str(&format!("    ldr x9, [x29, #{}]\n    str x9, {}\n",
+                                                 16 + (index - ARG_REGISTERS.len()) * 8,
+                                                 slot)),
+            }
+        }
+
+        // Generate instructions
+        for instr in &func.instructions {
+            output.push_str(&generate_instruction(instr, &frame));
+        }
+
+        // Si la ejecución llega al final sin 'return', se retorna 0
+        output.push_str("    mov x0, #0\n");
+        output.push_str(EPILOGUE);
+        output.push_str(&format!(".size {0}, . - {0}\n", func.name));
+        output.push('\n');
+    }
+
+    // Main entry point; exit de la libc vacía stdout antes de salir
+    output.push_str("_start:\n");
+    output.push_str("    bl main\n");
+    if !main_returns_value {
+        output.push_str("    mov x0, #0\n");
+    }
+    output.push_str("    bl exit\n");
+
+    output
+}
+
+const EPILOGUE: &str = "    mov sp, x29\n    ldp x29, x30, [sp], #16\n    ret\n";
+
+// Los huecos de FrameLayout están a N bytes por debajo de x29, pero ldur
+// solo llega a -256; se direccionan desde sp, que queda `size` bytes por
+// debajo de x29, con un desplazamiento positivo. Debajo de los huecos va
+// el área de argumentos salientes.
+struct Frame {
+    layout: FrameLayout,
+    size: usize,
+}
+
+impl Frame {
+    fn new(func: &IRFunction) -> Self {
+        let layout = FrameLayout::new(func);
+        let size = (layout.size() + outgoing_args_size(func)).next_multiple_of(16);
+        Frame { layout, size }
+    }
+
+    fn slot(&self, value: &IRValue) -> String {
+        format!("[sp, #{}]", self.size - self.layout.offset(value))
+    }
+}
+
+// Bytes que necesitan los argumentos que no caben en registros
+fn outgoing_args_size(func: &IRFunction) -> usize {
+    func.instructions
+        .iter()
+        .filter_map(|instr| match instr {
+            IROp::Call(_, args, _) => Some(args.len().saturating_sub(ARG_REGISTERS.len()) * 8),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// El inmediato de add/sub tiene 12 bits
+fn adjust_sp(mnemonic: &str, bytes: usize) -> String {
+    if bytes < 4096 {
+        format!("    {} sp, sp, #{}\n", mnemonic, bytes)
+    } else {
+        format!("{}    {} sp, sp, x9\n", load_immediate("x9", bytes as i64), mnemonic)
+    }
+}
+
+// mov solo admite un inmediato de 16 bits (o su complemento); el resto se
+// construye por trozos de 16 bits con movz/movk
+fn load_immediate(register: &str, value: i64) -> String {
+    if (-65536..65536).contains(&value) {
+        return format!("    mov {}, #{}\n", register, value);
+    }
+    let bits = value as u64;
+    let mut output = format!("    movz {}, #{}\n", register, bits & 0xffff);
+    for shift in [16, 32, 48] {
+        let chunk = (bits >> shift) & 0xffff;
+        if chunk != 0 {
+            output.push_str(&format!("    movk {}, #{}, lsl #{}\n", register, chunk, shift));
+        }
+    }
+    output
+}
+
+// .asciz añade el 0 final; comillas, barras y bytes no imprimibles van
+// como escapes octales
+fn encode_string_for_gas(s: &str) -> String {
+    s.bytes()
+        .map(|byte| {
+            if (0x20..0x7f).contains(&byte) && byte != b'"' && byte != b'\\' {
+                (byte as char).to_string()
+            } else {
+                format!("\\{:03o}", byte)
+            }
+        })
+        .collect()
+}
+
+fn load_register(register: &str, value: &IRValue, frame: &Frame) -> String {
+    match value {
+        IRValue::Const(n) => load_immediate(register, *n),
+        IRValue::Local(_) | IRValue::Temp(_) => format!("    ldr {}, {}\n", register, frame.slot(value)),
+        IRValue::Global(name) => {
+            format!("    adrp x16, {0}\n    ldr {1}, [x16, :lo12:{0}]\n", name, register)
+        }
+        // La dirección de un string o un array constante, relativa a pc
+        IRValue::Data(label) => {
+            format!("    adrp {1}, {0}\n    add {1}, {1}, :lo12:{0}\n", label, register)
+        }
+    }
+}
+
+fn store_register(register: &str, target: &IRValue, frame: &Frame) -> String {
+    match target {
+        IRValue::Local(_) | IRValue::Temp(_) => format!("    str {}, {}\n", register, frame.slot(target)),
+        IRValue::Global(name) => {
+            format!("    adrp x16, {0}\n    str {1}, [x16, :lo12:{0}]\n", name, register)
+        }
+        IRValue::Const(_) | IRValue::Data(_) => unreachable!("destino de asignación no válido: {}", target),
+    }
+}
+
+// Operación de tres registros: x9 = x9 <op> x10
+fn binary_instruction(
+    code: &str,
+    result: &IRValue,
+    left: &IRValue,
+    right: &IRValue,
+    frame: &Frame,
+) -> String {
+    format!("{}{}{}{}",
+            load_register("x9", left, frame),
+            load_register("x10", right, frame),
+            code,
+            store_register("x9", result, frame))
+}
+
+// cset deja 1 o 0 según la condición de la comparación
+fn compare_instruction(
+    condition: &str,
+    result: &IRValue,
+    left: &IRValue,
+    right: &IRValue,
+    frame: &Frame,
+) -> String {
+    binary_instruction(&format!("    cmp x9, x10\n    cset x9, {}\n", condition), result, left, right, frame)
+}
+
+// Los argumentos de pila se escriben en el área reservada en el fondo
+// del frame, así que sp ya está alineado a 16 en el `bl`
+fn call_instruction(name: &str, args: &[IRValue], result: Option<&IRValue>, frame: &Frame) -> String {
+    let mut output = String::new();
+
+    for (index, arg) in args.iter().enumerate().skip(ARG_REGISTERS.len()) {
+        output.push_str(&load_register("x9", arg, frame));
+        output.push_str(&format!("    str x9, [sp, #{}]\n", (index - ARG_REGISTERS.len()) * 8));
+    }
+    for (register, arg) in ARG_REGISTERS.iter().zip(args) {
+        output.push_str(&load_register(register, arg, frame));
+    }
+
+    output.push_str(&format!("    bl {}\n", name));
+    if let Some(result) = result {
+        output.push_str(&store_register("x0", result, frame));
+    }
+    output
+}
+
+fn generate_instruction(instr: &IROp, frame: &Frame) -> String {
+    match instr {
+        IROp::Add(result, left, right) => binary_instruction("    add x9, x9, x10\n", result, left, right, frame),
+        IROp::Sub(result, left, right) => binary_instruction("    sub x9, x9, x10\n", result, left, right, frame),
+        IROp::Mul(result, left, right) => binary_instruction("    mul x9, x9, x10\n", result, left, right, frame),
+        IROp::Div(result, left, right) => binary_instruction("    sdiv x9, x9, x10\n", result, left, right, frame),
+        IROp::Mod(result, left, right) => {
+            // No hay instrucción de resto: a - (a / b) * b
+            binary_instruction("    sdiv x11, x9, x10\n    msub x9, x11, x10, x9\n", result, left, right, frame)
+        }
+        IROp::Shl(result, left, right) => binary_instruction("    lsl x9, x9, x10\n", result, left, right, frame),
+        IROp::Sar(result, left, right) => binary_instruction("    asr x9, x9, x10\n", result, left, right, frame),
+        IROp::Shr(result, left, right) => binary_instruction("    lsr x9, x9, x10\n", result, left, right, frame),
+        IROp::And(result, left, right) => binary_instruction("    and x9, x9, x10\n", result, left, right, frame),
+        IROp::Or(result, left, right) => binary_instruction("    orr x9, x9, x10\n", result, left, right, frame),
+        IROp::CmpEq(result, left, right) => compare_instruction("eq", result, left, right, frame),
+        IROp::CmpNe(result, left, right) => compare_instruction("ne", result, left, right, frame),
+        IROp::CmpLt(result, left, right) => compare_instruction("lt", result, left, right, frame),
+        IROp::CmpLe(result, left, right) => compare_instruction("le", result, left, right, frame),
+        IROp::CmpGt(result, left, right) => compare_instruction("gt", result, left, right, frame),
+        IROp::CmpGe(result, left, right) => compare_instruction("ge", result, left, right, frame),
+        IROp::Assign(target, source) => {
+            format!("{}{}", load_register("x9", source, frame), store_register("x9", target, frame))
+        }
+        IROp::Select(result, cond, then_value, else_value) => {
+            format!("{}{}{}    cmp x9, #0\n    csel x9, x10, x11, ne\n{}",
+                    load_register("x9", cond, frame),
+                    load_register("x10", then_value, frame),
+                    load_register("x11", else_value, frame),
+                    store_register("x9", result, frame))
+        }
+        IROp::Print(value, kind) => {
+            call_instruction(print_function(*kind), std::slice::from_ref(value), None, frame)
+        }
+        IROp::Call(name, args, result) => call_instruction(name, args, result.as_ref(), frame),
+        IROp::Alloc(result, size) => {
+            call_instruction("array_new", &[IRValue::Const(*size as i64)], Some(result), frame)
+        }
+        IROp::ArrayGet(result, array, index) => {
+            // Los elementos empiezan tras la palabra de longitud
+            format!("{}{}    add x9, x9, x10, lsl #3\n    ldr x9, [x9, #8]\n{}",
+                    load_register("x9", array, frame),
+                    load_register("x10", index, frame),
+                    store_register("x9", result, frame))
+        }
+        IROp::ArraySet(array, index, value) => {
+            format!("{}{}{}    add x9, x9, x10, lsl #3\n    str x11, [x9, #8]\n",
+                    load_register("x9", array, frame),
+                    load_register("x10", index, frame),
+                    load_register("x11", value, frame))
+        }
+        IROp::Label(name) => {
+            format!("{}:\n", name)
+        }
+        IROp::Jump(label) => {
+            format!("    b {}\n", label)
+        }
+        IROp::JumpIfZero(value, label) => {
+            format!("{}    cbz x9, {}\n", load_register("x9", value, frame), label)
+        }
+        IROp::JumpIfNotZero(value, label) => {
+            format!("{}    cbnz x9, {}\n", load_register("x9", value, frame), label)
+        }
+        IROp::Return(Some(value)) => {
+            format!("{}{}", load_register("x0", value, frame), EPILOGUE)
+        }
+        IROp::Return(None) => EPILOGUE.to_string(),
+    }
+}
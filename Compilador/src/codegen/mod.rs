@@ -1,12 +1,524 @@
-use crate::ir::IRProgram;
-use target_lexicon::OperatingSystem;
+use crate::ir::liveness::{in_frame, live_after};
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue, PrintKind};
+use crate::optimizer::OptLevel;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use target_lexicon::{Architecture, OperatingSystem};
 
+pub mod aarch64;
 pub mod windows;
 pub mod unix;
 
-pub fn generate_code(ir: IRProgram, os: OperatingSystem) -> String {
-    match os {
-        OperatingSystem::Windows => windows::generate_windows_asm(ir),
-        _ => unix::generate_unix_asm(ir),
+// Codifica un string como operandos de `db` para NASM: los tramos
+// imprimibles van entre comillas y el resto (comillas, saltos de línea,
+// bytes no ASCII) como valores numéricos. Termina en 0 para el runtime C.
+pub fn encode_string_for_nasm(s: &str) -> String {
+    let mut parts = Vec::new();
+    let mut run = String::new();
+
+    for byte in s.bytes() {
+        if (0x20..0x7f).contains(&byte) && byte != b'"' {
+            run.push(byte as char);
+        } else {
+            if !run.is_empty() {
+                parts.push(format!("\"{}\"", run));
+                run.clear();
+            }
+            parts.push(byte.to_string());
+        }
+    }
+    if !run.is_empty() {
+        parts.push(format!("\"{}\"", run));
+    }
+    parts.push("0".to_string());
+
+    parts.join(",")
+}
+
+// Operandos de `dq` para un array constante: longitud y elementos
+pub fn constant_array_data(values: &[i64]) -> String {
+    std::iter::once(values.len() as i64)
+        .chain(values.iter().copied())
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Disposición del frame de una función: cada parámetro, local y temporal
+// ocupa un hueco [rbp - N*8]. Dos valores cuyos rangos de vida no se
+// solapan pueden compartir hueco.
+pub struct FrameLayout {
+    slots: HashMap<IRValue, usize>,
+    slot_count: usize,
+    base: &'static str,
+}
+
+impl FrameLayout {
+    pub fn new(function: &IRFunction) -> Self {
+        // Valores en orden de primera aparición, con los parámetros delante
+        let mut values: Vec<IRValue> = Vec::new();
+        let params = function.params.iter().map(|p| IRValue::Local(p.clone()));
+        let mentioned = function.instructions.iter().flat_map(|instr| {
+            instr.defined().into_iter().chain(instr.used()).filter(|v| in_frame(v)).cloned()
+        });
+        for value in params.chain(mentioned) {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+
+        // Interferencias: lo que se escribe choca con todo lo que sigue vivo
+        // tras la instrucción; los parámetros llegan todos a la vez y chocan
+        // entre sí y con lo vivo al entrar.
+        let mut interference: HashMap<&IRValue, HashSet<&IRValue>> = HashMap::new();
+        let mut add_edge = |a, b| {
+            if a != b {
+                interference.entry(a).or_default().insert(b);
+                interference.entry(b).or_default().insert(a);
+            }
+        };
+        let live_after = live_after(function);
+        for (instr, live) in function.instructions.iter().zip(&live_after) {
+            if let Some(defined) = instr.defined().filter(|v| in_frame(v)) {
+                for value in live {
+                    add_edge(defined, value);
+                }
+            }
+        }
+        let live_at_entry: Vec<&IRValue> = match function.instructions.first() {
+            Some(first) => live_after[0]
+                .iter()
+                .filter(|v| first.defined() != Some(*v))
+                .chain(first.used().into_iter().filter(|v| in_frame(v)))
+                .collect(),
+            None => Vec::new(),
+        };
+        let param_count = function.params.len();
+        for param in &values[..param_count] {
+            for other in values[..param_count].iter().chain(live_at_entry.iter().copied()) {
+                add_edge(param, other);
+            }
+        }
+
+        // Coloreado voraz: cada valor toma el primer hueco libre entre los
+        // de sus vecinos ya asignados
+        let mut slots: HashMap<IRValue, usize> = HashMap::new();
+        let mut slot_count = 0;
+        for value in &values {
+            let taken: HashSet<usize> = interference
+                .get(value)
+                .into_iter()
+                .flatten()
+                .filter_map(|neighbour| slots.get(*neighbour).copied())
+                .collect();
+            let slot = (0..).find(|slot| !taken.contains(slot)).unwrap();
+            slot_count = slot_count.max(slot + 1);
+            slots.insert(value.clone(), slot);
+        }
+
+        FrameLayout { slots, slot_count, base: "rbp" }
+    }
+
+    // Los huecos pasan a estar por debajo de rsp, en la zona roja de
+    // System V, para funciones que no montan frame
+    pub fn in_red_zone(self) -> Self {
+        FrameLayout { base: "rsp", ..self }
+    }
+
+    // Operando de memoria de un local o temporal
+    pub fn operand(&self, value: &IRValue) -> String {
+        format!("[{} - {}]", self.base, self.offset(value))
+    }
+
+    // Bytes por debajo de la base donde está el hueco del valor
+    pub fn offset(&self, value: &IRValue) -> usize {
+        (self.slots[value] + 1) * 8
+    }
+
+    // Bytes a reservar con `sub rsp`
+    pub fn size(&self) -> usize {
+        self.slot_count * 8
+    }
+}
+
+pub fn print_function(kind: PrintKind) -> &'static str {
+    match kind {
+        PrintKind::Int => "print_int",
+        PrintKind::Bool => "print_bool",
+        PrintKind::String => "print_string",
+        PrintKind::Char => "print_char",
+    }
+}
+
+// Funciones llamadas que no define el programa: las aporta el runtime
+pub fn external_functions(program: &IRProgram) -> BTreeSet<&str> {
+    let mut names = BTreeSet::new();
+    for func in &program.functions {
+        for instr in &func.instructions {
+            match instr {
+                IROp::Print(_, kind) => {
+                    names.insert(print_function(*kind));
+                }
+                IROp::Alloc(..) => {
+                    names.insert("array_new");
+                }
+                IROp::Call(name, _, _) if !program.functions.iter().any(|f| &f.name == name) => {
+                    names.insert(name.as_str());
+                }
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+// `pie` solo afecta a unix: el backend de Windows ya direcciona relativo a
+// rip y el de ARM64 relativo a pc. ARM64 solo genera para Linux.
+pub fn generate_code(
+    ir: IRProgram,
+    architecture: Architecture,
+    os: OperatingSystem,
+    opt_level: OptLevel,
+    pie: bool,
+) -> String {
+    match (architecture, os) {
+        (Architecture::Aarch64(_), _) => aarch64::generate_aarch64_asm(ir),
+        (_, OperatingSystem::Windows) => windows::generate_windows_asm(ir),
+        _ => unix::generate_unix_asm(ir, opt_level, pie),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aarch64, encode_string_for_nasm, unix, windows, FrameLayout};
+    use crate::ir::liveness::{in_frame, live_after};
+    use crate::ir::{IRFunction, IRValue};
+    use crate::optimizer::{OptLevel, Optimizer};
+    use crate::link::assemble_and_link;
+    use crate::runtime::generate_runtime;
+    use crate::testing::{build_ir, function};
+    use std::process::Command;
+    use target_lexicon::{Architecture, OperatingSystem};
+
+    const SOURCES: &[&str] = &[
+        "fn f(a: int, b: int) -> int { let c = a * 2 + b * 3; let d = c - a; return d * d + b; }",
+        "fn f(n: int) -> int { let t = 0; for (let i = 0; i < n; i = i + 1) { let sq = i * i; t = t + sq; } return t; }",
+        "fn f(a: int, b: int, c: int) -> int { if (a > b) { return c; } return (a + b) * (b + c) * (a + c); }",
+        "fn g(x: int) -> int { return x; } fn f(a: int, b: int) -> int { return g(a + 1) + g(b + 2) + a; }",
+    ];
+
+    fn layouts() -> Vec<(IRFunction, FrameLayout)> {
+        let mut functions = Vec::new();
+        for source in SOURCES {
+            for level in [OptLevel::O0, OptLevel::O2] {
+                let mut program = build_ir(source);
+                Optimizer::with_level(level).optimize(&mut program);
+                for function in program.functions {
+                    let layout = FrameLayout::new(&function);
+                    functions.push((function, layout));
+                }
+            }
+        }
+        functions
+    }
+
+    // Lo que se escribe no puede pisar el hueco de un valor que sigue vivo
+    #[test]
+    fn live_values_never_share_a_slot() {
+        for (function, layout) in layouts() {
+            let live_after = live_after(&function);
+            for (instr, live) in function.instructions.iter().zip(&live_after) {
+                let Some(defined) = instr.defined().filter(|v| in_frame(v)) else {
+                    continue;
+                };
+                for value in live.iter().filter(|value| *value != defined) {
+                    assert_ne!(
+                        layout.offset(defined),
+                        layout.offset(value),
+                        "{} y {} en {}",
+                        defined,
+                        value,
+                        function.name
+                    );
+                }
+            }
+            let params: Vec<usize> =
+                function.params.iter().map(|p| layout.offset(&IRValue::Local(p.clone()))).collect();
+            for (i, offset) in params.iter().enumerate() {
+                assert!(!params[..i].contains(offset), "parámetros en el mismo hueco en {}", function.name);
+            }
+            assert!(function
+                .instructions
+                .iter()
+                .flat_map(|instr| instr.used().into_iter().chain(instr.defined()))
+                .filter(|v| in_frame(v))
+                .all(|value| layout.offset(value) <= layout.size()));
+        }
+    }
+
+    #[test]
+    fn values_with_disjoint_lifetimes_share_slots() {
+        let sums: String = (0..20).map(|i| format!("print(x + {});", i)).collect();
+        let mut program = build_ir(&format!("fn f(x: int) {{ {} }}", sums));
+        Optimizer::with_level(OptLevel::O1).optimize(&mut program);
+        let function = &program.functions[0];
+        let layout = FrameLayout::new(function);
+        // x y como mucho un temporal a la vez
+        assert_eq!(layout.size(), 2 * 8);
+        assert!(function.instructions.len() >= 20);
+    }
+
+    // Las hojas pequeñas usan la zona roja bajo rsp en vez de montar frame
+    #[test]
+    fn leaf_functions_omit_the_frame_pointer_at_o2() {
+        let source = "fn add(a: int, b: int) -> int { return a + b; } \
+                      fn main() { print(add(1, 2)); }";
+        let asm = |level: OptLevel| {
+            let mut program = build_ir(source);
+            Optimizer::with_level(level).optimize(&mut program);
+            let asm = unix::generate_unix_asm(program, level, false);
+            let start = asm.find("add:").unwrap();
+            let end = asm.find("add.end:").unwrap();
+            (asm[start..end].to_string(), asm[end..].to_string())
+        };
+
+        let (add, rest) = asm(OptLevel::O2);
+        assert!(!add.contains("push rbp") && !add.contains("rbp") && !add.contains("sub rsp"));
+        assert!(add.contains("mov [rsp - 8], rdi") && add.contains("mov [rsp - 16], rsi"));
+        // main llama a print, así que sigue con frame
+        assert!(rest.contains("main:\n    push rbp"));
+
+        let (add, _) = asm(OptLevel::O1);
+        assert!(add.contains("push rbp\n    mov rbp, rsp"));
+    }
+
+    // `c ? a : b` entre valores ya calculados no salta a partir de -O2
+    #[test]
+    fn simple_ternaries_use_conditional_moves_at_o2() {
+        let source = "fn f(c: bool, a: int, b: int) -> int { return c ? a : b; } \
+                      fn g(c: int) -> int { return c > 0 ? 1 : 2; }";
+        let generate = |level: OptLevel| {
+            let mut program = build_ir(source);
+            Optimizer::with_level(level).optimize(&mut program);
+            [
+                unix::generate_unix_asm(program.clone(), level, false),
+                windows::generate_windows_asm(program.clone()),
+                aarch64::generate_aarch64_asm(program),
+            ]
+        };
+
+        let [unix, windows, aarch64] = generate(OptLevel::O2);
+        assert_eq!(unix.matches("cmovne").count(), 2);
+        assert_eq!(windows.matches("cmovne").count(), 2);
+        assert_eq!(aarch64.matches("csel").count(), 2);
+        assert!(!unix.contains("    je "));
+        assert!(!aarch64.contains("cbz"));
+
+        for asm in generate(OptLevel::O1) {
+            assert!(!asm.contains("cmov") && !asm.contains("csel"));
+        }
+    }
+
+    // Una función que llega al final sin 'return' devuelve 0: el código de
+    // salida de main nunca es lo que quedara en el registro
+    #[test]
+    fn falling_off_the_end_zeroes_the_return_register() {
+        let program = build_ir("fn f() { print(1); } fn main() { f(); }");
+        let x86 = "    xor rax, rax\n    mov rsp, rbp\n    pop rbp\n";
+        let backends = [
+            (unix::generate_unix_asm(program.clone(), OptLevel::O0, false), x86),
+            (windows::generate_windows_asm(program.clone()), x86),
+            (aarch64::generate_aarch64_asm(program), "    mov x0, #0\n    mov sp, x29\n    ldp x29, x30, [sp], #16\n"),
+        ];
+        for (asm, epilogue) in backends {
+            let start = asm.find("\nf:\n").unwrap();
+            let body = &asm[start..start + asm[start..].find("    ret\n").unwrap()];
+            assert!(body.ends_with(epilogue), "{}", body);
+        }
+    }
+
+    // El valor de main llega a exit como código de salida; sin valor se sale con 0
+    #[test]
+    fn main_return_value_is_the_exit_code() {
+        let asm = unix::generate_unix_asm(build_ir("fn main(): int { return 42; }"), OptLevel::O0, false);
+        assert!(asm.contains("_start:\n    call main\n    mov rdi, rax\n    call exit\n"), "{}", asm);
+        let void = unix::generate_unix_asm(build_ir("fn main() { print(1); }"), OptLevel::O0, false);
+        assert!(void.contains("_start:\n    call main\n    mov rdi, 0\n    call exit\n"), "{}", void);
+
+        // Ejecutarlo necesita nasm y un compilador de C; si faltan, basta con
+        // lo anterior
+        let dir = std::env::temp_dir().join(format!("compilador_exit_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(path("exit.asm"), asm).unwrap();
+        std::fs::write(path("exit_runtime.c"), generate_runtime(OperatingSystem::Linux)).unwrap();
+        let linked = assemble_and_link(
+            &path("exit.asm"),
+            &path("exit_runtime.c"),
+            &path("exit"),
+            Architecture::X86_64,
+            OperatingSystem::Linux,
+            false,
+        );
+        let status = linked.map(|executable| Command::new(executable).status().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        match status {
+            Ok(status) => assert_eq!(status.code(), Some(42)),
+            Err(err) => assert!(err.to_string().contains("No se encontró"), "{}", err),
+        }
+    }
+
+    #[test]
+    fn strings_are_encoded_as_printable_runs_and_bytes() {
+        assert_eq!(encode_string_for_nasm("a\n\"b, c"), "\"a\",10,34,\"b, c\",0");
+        assert_eq!(encode_string_for_nasm(""), "0");
+        assert_eq!(encode_string_for_nasm("\t\0é"), "9,0,195,169,0");
+
+        let program = build_ir("fn main() { print(\"a\\n\\\"b, c\"); }");
+        let asm = unix::generate_unix_asm(program.clone(), OptLevel::O0, false);
+        assert!(asm.contains("str_0: db \"a\",10,34,\"b, c\",0\n"), "{}", asm);
+        assert!(windows::generate_windows_asm(program).contains("str_0: db \"a\",10,34,\"b, c\",0\n"));
+    }
+
+    // System V: seis argumentos en registros, el resto en la pila en orden
+    // inverso, y el resultado en rax
+    #[test]
+    fn calls_follow_the_system_v_convention() {
+        let source = "fn add(a: int, b: int) -> int { return a + b; } \
+                      fn g(a: int, b: int, c: int, d: int, e: int, f: int, h: int, i: int) -> int { return a + i; } \
+                      fn main() -> int { let y = add(2, 3); print(y); return g(1, 2, 3, 4, 5, 6, 7, 8); }";
+        let asm = unix::generate_unix_asm(build_ir(source), OptLevel::O0, false);
+        let main = &asm[asm.find("\nmain:\n").unwrap()..asm.find("main.end:").unwrap()];
+        let lines = |lines: &[&str]| lines.iter().map(|line| format!("    {}\n", line)).collect::<String>();
+        let two = lines(&["mov rdi, 2", "mov rsi, 3", "call add", "mov [rbp - 8], rax"]);
+        assert!(main.contains(&two), "{}", main);
+        let eight = lines(&[
+            "mov rax, 8", "push rax", "mov rax, 7", "push rax",
+            "mov rdi, 1", "mov rsi, 2", "mov rdx, 3", "mov rcx, 4", "mov r8, 5", "mov r9, 6",
+            "call g", "add rsp, 16",
+        ]);
+        assert!(main.contains(&eight), "{}", main);
+    }
+
+    #[test]
+    fn pie_calls_go_through_the_plt_and_data_is_rip_relative() {
+        let source = "fn twice(x: int) -> int { return x * 2; } \
+                      fn main() -> int { let s = \"hola\"; print(s); let a = [1, 2, 3]; print(twice(a[2])); return 0; }";
+        let program = || {
+            let mut program = build_ir(source);
+            Optimizer::with_level(OptLevel::O1).optimize(&mut program);
+            program
+        };
+        let pie = unix::generate_unix_asm(program(), OptLevel::O1, true);
+        let calls: Vec<&str> = pie.lines().filter(|line| line.trim_start().starts_with("call ")).collect();
+        assert!(pie.starts_with("default rel\n"), "{}", pie);
+        for call in ["call print_string wrt ..plt", "call print_int wrt ..plt", "call twice wrt ..plt", "call exit wrt ..plt"] {
+            assert!(calls.iter().any(|line| line.trim() == call), "{}:\n{}", call, pie);
+        }
+        // Solo el salto a main desde _start, dentro del propio ejecutable
+        assert_eq!(calls.iter().filter(|line| !line.ends_with(" wrt ..plt")).count(), 1, "{}", pie);
+        assert!(pie.contains(", [rel str_0]"), "{}", pie);
+        assert!(pie.contains("[rel const_array_0]"), "{}", pie);
+
+        let static_asm = unix::generate_unix_asm(program(), OptLevel::O1, false);
+        assert!(!static_asm.contains("default rel") && !static_asm.contains("..plt"), "{}", static_asm);
+
+        let dir = std::env::temp_dir().join(format!("compilador_pie_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(path("pie.asm"), pie).unwrap();
+        std::fs::write(path("pie_runtime.c"), generate_runtime(OperatingSystem::Linux)).unwrap();
+        let linked = assemble_and_link(
+            &path("pie.asm"),
+            &path("pie_runtime.c"),
+            &path("pie"),
+            Architecture::X86_64,
+            OperatingSystem::Linux,
+            true,
+        );
+        let output = linked.map(|executable| Command::new(executable).output().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        match output {
+            Ok(output) => assert_eq!(String::from_utf8_lossy(&output.stdout), "hola\n6\n"),
+            Err(err) => assert!(err.to_string().contains("No se encontró"), "{}", err),
+        }
+    }
+
+    #[test]
+    fn functions_carry_their_elf_type_and_size() {
+        let source = "fn add(a: int, b: int) -> int { return a + b; } \
+                      fn show(x: int) { if (x > 0) { print(x); } } \
+                      fn main() -> int { show(add(1, 2)); return 0; }";
+        for level in [OptLevel::O0, OptLevel::O2] {
+            let asm = unix::generate_unix_asm(build_ir(source), level, false);
+            let lines: Vec<&str> = asm.lines().collect();
+            for (name, visibility) in [("add", "static"), ("show", "static"), ("main", "static"), ("_start", "global")] {
+                let declaration = format!("{0} {1}:function ({1}.end - {1})", visibility, name);
+                let start = lines.iter().position(|line| *line == format!("{}:", name)).unwrap();
+                let end = lines.iter().position(|line| *line == format!("{}.end:", name)).unwrap();
+                // _start se declara en la cabecera, junto a los extern
+                let declared = if name == "_start" { lines.contains(&declaration.as_str()) } else { lines[start - 1] == declaration };
+                assert!(declared, "{}:\n{}", declaration, asm);
+                assert!(start < end, "{}", name);
+                // Entre la etiqueta y su fin no empieza ninguna otra función
+                assert!(!lines[start + 1..end].iter().any(|line| line.contains(":function (")), "{}", name);
+                assert!(lines[end - 1].trim() == "ret" || lines[end - 1].starts_with("    call exit"), "{}", name);
+            }
+        }
+
+        let asm = aarch64::generate_aarch64_asm(build_ir(source));
+        let lines: Vec<&str> = asm.lines().collect();
+        for name in ["add", "show", "main"] {
+            let start = lines.iter().position(|line| *line == format!(".type {}, %function", name)).unwrap();
+            let end = lines.iter().position(|line| *line == format!(".size {0}, . - {0}", name)).unwrap();
+            assert_eq!(lines[start + 1], format!("{}:", name));
+            assert!(!lines[start + 1..end].iter().any(|line| line.starts_with(".type")), "{}", name);
+        }
+    }
+
+    #[test]
+    fn recursive_frames_are_aligned_and_hold_every_slot() {
+        let source = "fn fact(n: int) -> int { if (n <= 1) { return 1; } return n * fact(n - 1) + 0 * (n + n * n); } \
+                      fn main() -> int { print(fact(10)); return 0; }";
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let mut program = build_ir(source);
+            Optimizer::with_level(level).optimize(&mut program);
+            let frame = FrameLayout::new(function(&program, "fact")).size();
+            let asm = unix::generate_unix_asm(program, level, false);
+            let fact = &asm[asm.find("\nfact:\n").unwrap()..asm.find("\nfact.end:").unwrap()];
+            let reserved: usize = fact
+                .lines()
+                .find_map(|line| line.strip_prefix("    sub rsp, "))
+                .unwrap_or_else(|| panic!("{:?} sin frame:\n{}", level, fact))
+                .parse()
+                .unwrap();
+            assert!(fact.contains("    push rbp\n    mov rbp, rsp\n    sub rsp, "), "{}", fact);
+            assert_eq!(reserved % 16, 0, "{:?}", level);
+            assert!(reserved >= frame && frame > 0, "{:?}: {} < {}", level, reserved, frame);
+            // Ningún hueco queda por debajo de lo reservado
+            for offset in fact.split("[rbp - ").skip(1) {
+                let offset: usize = offset[..offset.find(']').unwrap()].parse().unwrap();
+                assert!(offset <= reserved, "{:?}: [rbp - {}] fuera de {}", level, offset, reserved);
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("compilador_fact_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(path("fact.asm"), unix::generate_unix_asm(build_ir(source), OptLevel::O0, false)).unwrap();
+        std::fs::write(path("fact_runtime.c"), generate_runtime(OperatingSystem::Linux)).unwrap();
+        let linked = assemble_and_link(
+            &path("fact.asm"),
+            &path("fact_runtime.c"),
+            &path("fact"),
+            Architecture::X86_64,
+            OperatingSystem::Linux,
+            false,
+        );
+        let output = linked.map(|executable| Command::new(executable).output().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        match output {
+            Ok(output) => assert_eq!(String::from_utf8_lossy(&output.stdout), "3628800\n"),
+            Err(err) => assert!(err.to_string().contains("No se encontró"), "{}", err),
+        }
     }
 }
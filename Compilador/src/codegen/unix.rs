@@ -1,79 +1,291 @@
+use crate::codegen::{constant_array_data, encode_string_for_nasm, external_functions, print_function, FrameLayout};
 use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
+use crate::optimizer::OptLevel;
 
-pub fn generate_unix_asm(program: IRProgram) -> String {
+const ARG_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const RED_ZONE_SIZE: usize = 128;
+
+pub fn generate_unix_asm(program: IRProgram, opt_level: OptLevel, pie: bool) -> String {
     let mut output = String::new();
+
+    // En un ejecutable PIE no hay direcciones absolutas: los accesos a
+    // etiquetas son relativos a rip y las llamadas pasan por la PLT
+    if pie {
+        output.push_str("default rel\n\n");
+    }
+    
+    // String literals
+    if !program.strings.is_empty() {
+        output.push_str("section .data\n");
+        for (label, value) in &program.strings {
+            output.push_str(&format!("{}: db {}\n", label, encode_string_for_nasm(value)));
+        }
+        output.push('\n');
+    }
+    
+    // Arrays constantes, con la palabra de longitud delante como los del heap
+    if !program.constant_arrays.is_empty() {
+        output.push_str("section .rodata\n");
+        for (label, values) in &program.constant_arrays {
+            output.push_str(&format!("{}: dq {}\n", label, constant_array_data(values)));
+        }
+        output.push('\n');
+    }
     
     // Header
     output.push_str("section .text\n");
-    output.push_str("extern print_int\n");
-    output.push_str("global _start\n\n");
+    for name in external_functions(&program) {
+        output.push_str(&format!("extern {}\n", name));
+    }
+    output.push_str("extern exit\n");
+    output.push_str("global _start:function (_start.end - _start)\n\n");
+    
+    // Solo un main que retorna valor define el código de salida
+    let main_returns_value = program.functions.iter().any(|f| {
+        f.name == "main" && f.instructions.iter().any(|i| matches!(i, IROp::Return(Some(_))))
+    });
     
     // Generate functions
     for func in program.functions {
+        let frame = FrameLayout::new(&func);
+        // Las funciones hoja no necesitan frame pointer: sus huecos caben
+        // en la zona roja y se direccionan desde rsp
+        let has_frame = opt_level < OptLevel::O2 || !is_frameless_leaf(&func, &frame);
+        let frame = if has_frame { frame } else { frame.in_red_zone() };
+        
+        // Tipo y tamaño del símbolo en ELF, para que gdb y perf muestren
+        // el nombre de la función; `static` lo deja local como hasta ahora
+        output.push_str(&format!("static {0}:function ({0}.end - {0})\n", func.name));
         output.push_str(&format!("{}:\n", func.name));
-        output.push_str("    push rbp\n");
-        output.push_str("    mov rbp, rsp\n");
+        if has_frame {
+            output.push_str("    push rbp\n");
+            output.push_str("    mov rbp, rsp\n");
+        }
         
-        // Allocate stack space for locals
-        let local_count = func.locals.len() as i64;
-        if local_count > 0 {
-            output.push_str(&format!("    sub rsp, {}\n", local_count * 8));
+        // Locales y temporales; tras `push rbp` la pila está alineada a 16
+        // y el frame se redondea para que lo siga estando en cada llamada
+        if has_frame && frame.size() > 0 {
+            output.push_str(&format!("    sub rsp, {}\n", frame.size().next_multiple_of(16)));
+        }
+        
+        // Los parámetros llegan en registros y, a partir del séptimo, en la
+        // pila por encima de la dirección de retorno
+        for (index, param) in func.params.iter().enumerate() {
+            let slot = frame.operand(&IRValue::Local(param.clone()));
+            match ARG_REGISTERS.get(index) {
+                Some(register) => output.push_str(&format!("    mov {}, {}\n", slot, register)),
+                None => output.push_str(&format!("    mov rax, [rbp + {}]\n    mov {}, rax\n",
+                                                 16 + (index - ARG_REGISTERS.len()) * 8,
+                                                 slot)),
+            }
         }
         
         // Generate instructions
         for instr in func.instructions {
-            output.push_str(&generate_instruction(&instr));
+            output.push_str(&generate_instruction(&instr, &frame, has_frame, pie));
         }
         
-        output.push_str("    mov rsp, rbp\n");
-        output.push_str("    pop rbp\n");
-        output.push_str("    ret\n\n");
+        // Si la ejecución llega al final sin 'return', se retorna 0
+        output.push_str("    xor rax, rax\n");
+        output.push_str(&epilogue(has_frame));
+        output.push_str(&format!("{}.end:\n", func.name));
+        output.push('\n');
     }
     
-    // Main entry point
+    // Main entry point. Se sale con exit de la libc y no con la llamada al
+    // sistema para que vacíe stdout: si no es un terminal, lo impreso
+    // sigue en el buffer
     output.push_str("_start:\n");
     output.push_str("    call main\n");
-    output.push_str("    mov rax, 60\n"); // sys_exit
-    output.push_str("    mov rdi, 0\n");
-    output.push_str("    syscall\n");
+    if main_returns_value {
+        output.push_str("    mov rdi, rax\n"); // el kernel lo trunca a 0-255
+    } else {
+        output.push_str("    mov rdi, 0\n");
+    }
+    if pie {
+        output.push_str("    call exit wrt ..plt\n");
+    } else {
+        output.push_str("    call exit\n");
+    }
+    output.push_str("_start.end:\n");
     
     output
 }
 
-fn generate_instruction(instr: &IROp) -> String {
+// Una función hoja no llama a nadie (print también es una llamada al
+// runtime), así que nada pisa los 128 bytes bajo rsp que System V deja
+// libres. Sus parámetros tienen que llegar todos en registros.
+fn is_frameless_leaf(func: &IRFunction, frame: &FrameLayout) -> bool {
+    frame.size() <= RED_ZONE_SIZE
+        && func.params.len() <= ARG_REGISTERS.len()
+        && func.instructions.iter().all(|instr| {
+            !matches!(instr, IROp::Call(..) | IROp::Print(..) | IROp::Alloc(..))
+        })
+}
+
+fn epilogue(has_frame: bool) -> String {
+    if has_frame {
+        "    mov rsp, rbp\n    pop rbp\n    ret\n".to_string()
+    } else {
+        "    ret\n".to_string()
+    }
+}
+
+// El contador de un desplazamiento variable tiene que ir en cl
+fn shift_instruction(
+    mnemonic: &str,
+    result: &IRValue,
+    left: &IRValue,
+    right: &IRValue,
+    frame: &FrameLayout,
+) -> String {
+    format!("    mov rax, {}\n    mov rcx, {}\n    {} rax, cl\n    mov {}, rax\n",
+            ir_value_to_asm(left, frame),
+            ir_value_to_asm(right, frame),
+            mnemonic,
+            ir_value_to_asm(result, frame))
+}
+
+// El resultado de una comparación es 0 o 1: setcc escribe al y se
+// extiende a 64 bits
+fn compare_instruction(
+    setcc: &str,
+    result: &IRValue,
+    left: &IRValue,
+    right: &IRValue,
+    frame: &FrameLayout,
+) -> String {
+    format!("    mov rax, {}\n    cmp rax, {}\n    {} al\n    movzx rax, al\n    mov {}, rax\n",
+            ir_value_to_asm(left, frame),
+            ir_value_to_asm(right, frame),
+            setcc,
+            ir_value_to_asm(result, frame))
+}
+
+// Convención System V: argumentos enteros en estos registros y el resto
+// en la pila, de derecha a izquierda. En el `call` rsp debe estar alineado
+// a 16; tras el prólogo ya lo está y solo los argumentos de pila lo mueven.
+fn call_instruction(
+    name: &str,
+    args: &[IRValue],
+    result: Option<&IRValue>,
+    frame: &FrameLayout,
+    pie: bool,
+) -> String {
+    let mut output = String::new();
+
+    let stack_args = args.len().saturating_sub(ARG_REGISTERS.len());
+    let padding = (stack_args * 8) % 16;
+    if padding != 0 {
+        output.push_str(&format!("    sub rsp, {}\n", padding));
+    }
+    for arg in args.iter().skip(ARG_REGISTERS.len()).rev() {
+        output.push_str(&load_register("rax", arg, frame));
+        output.push_str("    push rax\n");
+    }
+    for (register, arg) in ARG_REGISTERS.iter().zip(args) {
+        output.push_str(&load_register(register, arg, frame));
+    }
+
+    if pie {
+        output.push_str(&format!("    call {} wrt ..plt\n", name));
+    } else {
+        output.push_str(&format!("    call {}\n", name));
+    }
+    let cleanup = stack_args * 8 + padding;
+    if cleanup != 0 {
+        output.push_str(&format!("    add rsp, {}\n", cleanup));
+    }
+    if let Some(result) = result {
+        output.push_str(&format!("    mov {}, rax\n", ir_value_to_asm(result, frame)));
+    }
+    output
+}
+
+fn generate_instruction(instr: &IROp, frame: &FrameLayout, has_frame: bool, pie: bool) -> String {
     match instr {
         IROp::Add(result, left, right) => {
             format!("    mov rax, {}\n    add rax, {}\n    mov {}, rax\n",
-                    ir_value_to_asm(left),
-                    ir_value_to_asm(right),
-                    ir_value_to_asm(result))
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
         }
         IROp::Sub(result, left, right) => {
             format!("    mov rax, {}\n    sub rax, {}\n    mov {}, rax\n",
-                    ir_value_to_asm(left),
-                    ir_value_to_asm(right),
-                    ir_value_to_asm(result))
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
         }
         IROp::Mul(result, left, right) => {
             format!("    mov rax, {}\n    mov rbx, {}\n    imul rax, rbx\n    mov {}, rax\n",
-                    ir_value_to_asm(left),
-                    ir_value_to_asm(right),
-                    ir_value_to_asm(result))
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
         }
         IROp::Div(result, left, right) => {
             format!("    mov rax, {}\n    mov rbx, {}\n    cqo\n    idiv rbx\n    mov {}, rax\n",
-                    ir_value_to_asm(left),
-                    ir_value_to_asm(right),
-                    ir_value_to_asm(result))
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Mod(result, left, right) => {
+            // idiv deja el cociente en rax y el resto en rdx
+            format!("    mov rax, {}\n    mov rbx, {}\n    cqo\n    idiv rbx\n    mov {}, rdx\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
         }
+        IROp::Shl(result, left, right) => shift_instruction("shl", result, left, right, frame),
+        IROp::Sar(result, left, right) => shift_instruction("sar", result, left, right, frame),
+        IROp::Shr(result, left, right) => shift_instruction("shr", result, left, right, frame),
+        IROp::And(result, left, right) => {
+            format!("    mov rax, {}\n    and rax, {}\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Or(result, left, right) => {
+            format!("    mov rax, {}\n    or rax, {}\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::CmpEq(result, left, right) => compare_instruction("sete", result, left, right, frame),
+        IROp::CmpNe(result, left, right) => compare_instruction("setne", result, left, right, frame),
+        IROp::CmpLt(result, left, right) => compare_instruction("setl", result, left, right, frame),
+        IROp::CmpLe(result, left, right) => compare_instruction("setle", result, left, right, frame),
+        IROp::CmpGt(result, left, right) => compare_instruction("setg", result, left, right, frame),
+        IROp::CmpGe(result, left, right) => compare_instruction("setge", result, left, right, frame),
         IROp::Assign(target, source) => {
-            format!("    mov rax, {}\n    mov {}, rax\n",
-                    ir_value_to_asm(source),
-                    ir_value_to_asm(target))
+            format!("{}    mov {}, rax\n", load_register("rax", source, frame), ir_value_to_asm(target, frame))
+        }
+        IROp::Select(result, cond, then_value, else_value) => {
+            // cmov no admite inmediatos: los dos valores van en registros
+            format!("{}{}    mov rdx, {}\n    cmp rdx, 0\n    cmovne rax, rcx\n    mov {}, rax\n",
+                    load_register("rax", else_value, frame),
+                    load_register("rcx", then_value, frame),
+                    ir_value_to_asm(cond, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Print(value, kind) => {
+            call_instruction(print_function(*kind), std::slice::from_ref(value), None, frame, pie)
         }
-        IROp::Print(value) => {
-            format!("    mov rdi, {}\n    call print_int\n",
-                    ir_value_to_asm(value))
+        IROp::Call(name, args, result) => call_instruction(name, args, result.as_ref(), frame, pie),
+        IROp::Alloc(result, size) => {
+            call_instruction("array_new", &[IRValue::Const(*size as i64)], Some(result), frame, pie)
+        }
+        IROp::ArrayGet(result, array, index) => {
+            // Los elementos empiezan tras la palabra de longitud
+            format!("{}    mov rcx, {}\n    mov rax, [rax + rcx*8 + 8]\n    mov {}, rax\n",
+                    load_register("rax", array, frame),
+                    ir_value_to_asm(index, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::ArraySet(array, index, value) => {
+            format!("{}    mov rcx, {}\n{}    mov [rax + rcx*8 + 8], rdx\n",
+                    load_register("rax", array, frame),
+                    ir_value_to_asm(index, frame),
+                    load_register("rdx", value, frame))
         }
         IROp::Label(name) => {
             format!("{}:\n", name)
@@ -82,36 +294,37 @@ fn generate_instruction(instr: &IROp) -> String {
             format!("    jmp {}\n", label)
         }
         IROp::JumpIfZero(value, label) => {
-            format!("    cmp {}, 0\n    je {}\n",
-                    ir_value_to_asm(value),
+            format!("    mov rax, {}\n    cmp rax, 0\n    je {}\n",
+                    ir_value_to_asm(value, frame),
                     label)
         }
         IROp::JumpIfNotZero(value, label) => {
-            format!("    cmp {}, 0\n    jne {}\n",
-                    ir_value_to_asm(value),
+            format!("    mov rax, {}\n    cmp rax, 0\n    jne {}\n",
+                    ir_value_to_asm(value, frame),
                     label)
         }
         IROp::Return(Some(value)) => {
-            format!("    mov rax, {}\n    mov rsp, rbp\n    pop rbp\n    ret\n",
-                    ir_value_to_asm(value))
-        }
-        IROp::Return(None) => {
-            "    mov rsp, rbp\n    pop rbp\n    ret\n".to_string()
+            format!("{}{}", load_register("rax", value, frame), epilogue(has_frame))
         }
-        _ => String::new(),
+        IROp::Return(None) => epilogue(has_frame),
     }
 }
 
-fn ir_value_to_asm(value: &IRValue) -> String {
+// La dirección de una etiqueta de datos (un string, un array constante)
+// se toma con lea relativo a rip: no necesita reubicación absoluta, así
+// que vale con y sin PIE
+fn load_register(register: &str, value: &IRValue, frame: &FrameLayout) -> String {
     match value {
-        IRValue::Const(n) => n.to_string(),
-        IRValue::Local(name) => format!("[rbp - {}]", get_local_offset(name) * 8),
-        IRValue::Global(name) => format!("[{}]", name),
-        IRValue::Temp(name) => format!("rax"), // Simplified
+        IRValue::Data(label) => format!("    lea {}, [rel {}]\n", register, label),
+        _ => format!("    mov {}, {}\n", register, ir_value_to_asm(value, frame)),
     }
 }
 
-fn get_local_offset(name: &str) -> usize {
-    // This would be managed by the codegen context
-    name.chars().last().unwrap_or('0') as usize - '0' as usize
+fn ir_value_to_asm(value: &IRValue, frame: &FrameLayout) -> String {
+    match value {
+        IRValue::Const(n) => n.to_string(),
+        IRValue::Global(name) => format!("[{}]", name),
+        IRValue::Data(label) => label.clone(),
+        IRValue::Local(_) | IRValue::Temp(_) => frame.operand(value),
+    }
 }
@@ -1,68 +1,295 @@
+use crate::codegen::{constant_array_data, encode_string_for_nasm, external_functions, print_function, FrameLayout};
 use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
 
+// Registros de los cuatro primeros argumentos en la convención Win64
+const ARG_REGISTERS: [&str; 4] = ["rcx", "rdx", "r8", "r9"];
+
+// Espacio que el llamador reserva siempre para que el llamado guarde
+// los argumentos en registro
+const SHADOW_SPACE: usize = 32;
+
 pub fn generate_windows_asm(program: IRProgram) -> String {
     let mut output = String::new();
-    
+
+    // En x64 los accesos a etiquetas son relativos a rip
+    output.push_str("default rel\n\n");
+
+    // String literals
+    if !program.strings.is_empty() {
+        output.push_str("section .data\n");
+        for (label, value) in &program.strings {
+            output.push_str(&format!("{}: db {}\n", label, encode_string_for_nasm(value)));
+        }
+        output.push('\n');
+    }
+
+    // Arrays constantes, con la palabra de longitud delante como los del heap
+    if !program.constant_arrays.is_empty() {
+        output.push_str("section .rdata\n");
+        for (label, values) in &program.constant_arrays {
+            output.push_str(&format!("{}: dq {}\n", label, constant_array_data(values)));
+        }
+        output.push('\n');
+    }
+
     // Header for Windows
     output.push_str("section .text\n");
-    output.push_str("extern printf\n");
+    for name in external_functions(&program) {
+        output.push_str(&format!("extern {}\n", name));
+    }
     output.push_str("global main\n\n");
-    
+
+    // Solo un main que retorna valor define el código de salida
+    let main_returns_value = program.functions.iter().any(|f| {
+        f.name == "main" && f.instructions.iter().any(|i| matches!(i, IROp::Return(Some(_))))
+    });
+
     // Generate functions
     for func in program.functions {
-        output.push_str(&format!("{}:\n", func.name));
+        let frame = FrameLayout::new(&func);
+
+        output.push_str(&format!("{}:\n", symbol_name(&func.name)));
         output.push_str("    push rbp\n");
         output.push_str("    mov rbp, rsp\n");
-        
-        // Windows calling convention
-        // RCX, RDX, R8, R9 for first 4 args, rest on stack
-        
+
+        // Locales y temporales más el área de argumentos salientes; tras
+        // `push rbp` la pila está alineada a 16 y debe seguir así
+        let stack_size = (frame.size() + outgoing_args_size(&func)).next_multiple_of(16);
+        output.push_str(&format!("    sub rsp, {}\n", stack_size));
+
+        // Los parámetros llegan en RCX, RDX, R8, R9 y el resto en la pila,
+        // por encima de la dirección de retorno y el shadow space
+        for (index, param) in func.params.iter().enumerate() {
+            let slot = frame.operand(&IRValue::Local(param.clone()));
+            match ARG_REGISTERS.get(index) {
+                Some(register) => output.push_str(&format!("    mov {}, {}\n", slot, register)),
+                None => output.push_str(&format!("    mov rax, [rbp + {}]\n    mov {}, rax\n",
+                                                 16 + index * 8,
+                                                 slot)),
+            }
+        }
+
         // Generate instructions
         for instr in func.instructions {
-            output.push_str(&generate_instruction(&instr));
+            output.push_str(&generate_instruction(&instr, &frame));
         }
-        
-        output.push_str("    mov rsp, rbp\n");
-        output.push_str("    pop rbp\n");
-        output.push_str("    ret\n\n");
+
+        // Si la ejecución llega al final sin 'return', se retorna 0
+        output.push_str("    xor rax, rax\n");
+        output.push_str(EPILOGUE);
+        output.push('\n');
     }
-    
-    // Main entry point
+
+    // Main entry point, llamado por el runtime de C
     output.push_str("main:\n");
-    output.push_str("    sub rsp, 40\n"); // Shadow space + alignment
+    output.push_str(&format!("    sub rsp, {}\n", SHADOW_SPACE + 8)); // Shadow space + alignment
     output.push_str("    call main_func\n");
-    output.push_str("    add rsp, 40\n");
+    if !main_returns_value {
+        output.push_str("    xor rax, rax\n");
+    }
+    output.push_str(&format!("    add rsp, {}\n", SHADOW_SPACE + 8));
     output.push_str("    ret\n");
-    
+
     output
 }
 
-// Similar implementation to unix.rs but with Windows conventions
-fn generate_instruction(instr: &IROp) -> String {
-    // Implementation similar to unix.rs but adjusted for Windows
-    // For brevity, using the same logic but you'd adjust for Windows specifics
+const EPILOGUE: &str = "    mov rsp, rbp\n    pop rbp\n    ret\n";
+
+// El `main` del programa no puede llamarse así: ese nombre es el punto de
+// entrada que invoca el runtime de C
+fn symbol_name(name: &str) -> &str {
+    if name == "main" {
+        "main_func"
+    } else {
+        name
+    }
+}
+
+// Bytes que necesitan las llamadas de la función en lo alto de la pila:
+// siempre el shadow space y, a partir del quinto, un hueco por argumento
+fn outgoing_args_size(func: &IRFunction) -> usize {
+    let calls = func.instructions.iter().filter_map(|instr| match instr {
+        IROp::Call(_, args, _) => Some(args.len()),
+        IROp::Print(..) | IROp::Alloc(..) => Some(1),
+        _ => None,
+    });
+    match calls.max() {
+        Some(max_args) => SHADOW_SPACE.max(max_args * 8),
+        None => 0,
+    }
+}
+
+// El contador de un desplazamiento variable tiene que ir en cl
+fn shift_instruction(
+    mnemonic: &str,
+    result: &IRValue,
+    left: &IRValue,
+    right: &IRValue,
+    frame: &FrameLayout,
+) -> String {
+    format!("    mov rax, {}\n    mov rcx, {}\n    {} rax, cl\n    mov {}, rax\n",
+            ir_value_to_asm(left, frame),
+            ir_value_to_asm(right, frame),
+            mnemonic,
+            ir_value_to_asm(result, frame))
+}
+
+// El resultado de una comparación es 0 o 1: setcc escribe al y se
+// extiende a 64 bits
+fn compare_instruction(
+    setcc: &str,
+    result: &IRValue,
+    left: &IRValue,
+    right: &IRValue,
+    frame: &FrameLayout,
+) -> String {
+    format!("    mov rax, {}\n    cmp rax, {}\n    {} al\n    movzx rax, al\n    mov {}, rax\n",
+            ir_value_to_asm(left, frame),
+            ir_value_to_asm(right, frame),
+            setcc,
+            ir_value_to_asm(result, frame))
+}
+
+fn call_instruction(name: &str, args: &[IRValue], result: Option<&IRValue>, frame: &FrameLayout) -> String {
+    let mut output = String::new();
+
+    // Los argumentos de pila primero: cargarlos usa rax, que no es de paso
+    for (index, arg) in args.iter().enumerate().skip(ARG_REGISTERS.len()) {
+        output.push_str(&format!("    mov rax, {}\n    mov [rsp + {}], rax\n",
+                                 ir_value_to_asm(arg, frame),
+                                 index * 8));
+    }
+    for (register, arg) in ARG_REGISTERS.iter().zip(args) {
+        output.push_str(&format!("    mov {}, {}\n", register, ir_value_to_asm(arg, frame)));
+    }
+
+    output.push_str(&format!("    call {}\n", symbol_name(name)));
+    if let Some(result) = result {
+        output.push_str(&format!("    mov {}, rax\n", ir_value_to_asm(result, frame)));
+    }
+    output
+}
+
+// Mismas instrucciones que unix.rs; rbx es no volátil en Win64, así que
+// el segundo operando de mul/div va en rcx
+fn generate_instruction(instr: &IROp, frame: &FrameLayout) -> String {
     match instr {
         IROp::Add(result, left, right) => {
             format!("    mov rax, {}\n    add rax, {}\n    mov {}, rax\n",
-                    ir_value_to_asm(left),
-                    ir_value_to_asm(right),
-                    ir_value_to_asm(result))
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Sub(result, left, right) => {
+            format!("    mov rax, {}\n    sub rax, {}\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Mul(result, left, right) => {
+            format!("    mov rax, {}\n    mov rcx, {}\n    imul rax, rcx\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Div(result, left, right) => {
+            format!("    mov rax, {}\n    mov rcx, {}\n    cqo\n    idiv rcx\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Mod(result, left, right) => {
+            // idiv deja el cociente en rax y el resto en rdx
+            format!("    mov rax, {}\n    mov rcx, {}\n    cqo\n    idiv rcx\n    mov {}, rdx\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
         }
-        // ... other instructions
-        _ => String::new(),
+        IROp::Shl(result, left, right) => shift_instruction("shl", result, left, right, frame),
+        IROp::Sar(result, left, right) => shift_instruction("sar", result, left, right, frame),
+        IROp::Shr(result, left, right) => shift_instruction("shr", result, left, right, frame),
+        IROp::And(result, left, right) => {
+            format!("    mov rax, {}\n    and rax, {}\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Or(result, left, right) => {
+            format!("    mov rax, {}\n    or rax, {}\n    mov {}, rax\n",
+                    ir_value_to_asm(left, frame),
+                    ir_value_to_asm(right, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::CmpEq(result, left, right) => compare_instruction("sete", result, left, right, frame),
+        IROp::CmpNe(result, left, right) => compare_instruction("setne", result, left, right, frame),
+        IROp::CmpLt(result, left, right) => compare_instruction("setl", result, left, right, frame),
+        IROp::CmpLe(result, left, right) => compare_instruction("setle", result, left, right, frame),
+        IROp::CmpGt(result, left, right) => compare_instruction("setg", result, left, right, frame),
+        IROp::CmpGe(result, left, right) => compare_instruction("setge", result, left, right, frame),
+        IROp::Assign(target, source) => {
+            format!("    mov rax, {}\n    mov {}, rax\n",
+                    ir_value_to_asm(source, frame),
+                    ir_value_to_asm(target, frame))
+        }
+        IROp::Select(result, cond, then_value, else_value) => {
+            // cmov no admite inmediatos: los dos valores van en registros
+            format!("    mov rax, {}\n    mov rcx, {}\n    mov rdx, {}\n    cmp rdx, 0\n    cmovne rax, rcx\n    mov {}, rax\n",
+                    ir_value_to_asm(else_value, frame),
+                    ir_value_to_asm(then_value, frame),
+                    ir_value_to_asm(cond, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::Print(value, kind) => {
+            call_instruction(print_function(*kind), std::slice::from_ref(value), None, frame)
+        }
+        IROp::Call(name, args, result) => call_instruction(name, args, result.as_ref(), frame),
+        IROp::Alloc(result, size) => {
+            call_instruction("array_new", &[IRValue::Const(*size as i64)], Some(result), frame)
+        }
+        IROp::ArrayGet(result, array, index) => {
+            // Los elementos empiezan tras la palabra de longitud
+            format!("    mov rax, {}\n    mov rcx, {}\n    mov rax, [rax + rcx*8 + 8]\n    mov {}, rax\n",
+                    ir_value_to_asm(array, frame),
+                    ir_value_to_asm(index, frame),
+                    ir_value_to_asm(result, frame))
+        }
+        IROp::ArraySet(array, index, value) => {
+            format!("    mov rax, {}\n    mov rcx, {}\n    mov rdx, {}\n    mov [rax + rcx*8 + 8], rdx\n",
+                    ir_value_to_asm(array, frame),
+                    ir_value_to_asm(index, frame),
+                    ir_value_to_asm(value, frame))
+        }
+        IROp::Label(name) => {
+            format!("{}:\n", name)
+        }
+        IROp::Jump(label) => {
+            format!("    jmp {}\n", label)
+        }
+        IROp::JumpIfZero(value, label) => {
+            format!("    mov rax, {}\n    cmp rax, 0\n    je {}\n",
+                    ir_value_to_asm(value, frame),
+                    label)
+        }
+        IROp::JumpIfNotZero(value, label) => {
+            format!("    mov rax, {}\n    cmp rax, 0\n    jne {}\n",
+                    ir_value_to_asm(value, frame),
+                    label)
+        }
+        IROp::Return(Some(value)) => {
+            format!("    mov rax, {}\n{}",
+                    ir_value_to_asm(value, frame),
+                    EPILOGUE)
+        }
+        IROp::Return(None) => EPILOGUE.to_string(),
     }
 }
 
-fn ir_value_to_asm(value: &IRValue) -> String {
+fn ir_value_to_asm(value: &IRValue, frame: &FrameLayout) -> String {
     // Same as unix version
     match value {
         IRValue::Const(n) => n.to_string(),
-        IRValue::Local(name) => format!("[rbp - {}]", get_local_offset(name) * 8),
         IRValue::Global(name) => format!("[{}]", name),
-        IRValue::Temp(name) => format!("rax"),
+        IRValue::Data(label) => label.clone(),
+        IRValue::Local(_) | IRValue::Temp(_) => frame.operand(value),
     }
 }
-
-fn get_local_offset(name: &str) -> usize {
-    name.chars().last().unwrap_or('0') as usize - '0' as usize
-}
@@ -1,5 +1,7 @@
-use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
-use crate::parser::ast::{Expr, Program, Stmt, Type};
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue, InlineHint, PrintKind};
+use crate::parser::ast::{Expr, ExprKind, NodeId, Program, Stmt, StmtKind, Type};
+use crate::types::{DefaultValue, TypeSystem};
+use anyhow::{bail, Result};
 use std::collections::HashMap;
 
 pub struct IRBuilder {
@@ -7,8 +9,24 @@ pub struct IRBuilder {
     current_function: Option<String>,
     temp_counter: usize,
     label_counter: usize,
-    string_literals: HashMap<String, String>,
+    string_literals: Vec<(String, String)>,
     string_counter: usize,
+    // Valor de cada variante de enum, por nombre
+    enum_values: HashMap<String, i64>,
+    // Número de parámetros fijos de cada función variádica
+    variadic_functions: HashMap<String, usize>,
+    // Valores por defecto de los parámetros de cada función
+    default_arguments: HashMap<String, Vec<Option<Expr>>>,
+    // Tipos que calculó el análisis semántico, por expresión
+    expression_types: HashMap<NodeId, Type>,
+    // Local de IR de cada variable visible, un mapa por bloque: un let
+    // interior puede ocultar a otro de distinto tipo
+    scopes: Vec<HashMap<String, IRValue>>,
+    // Variables de las sentencias de nivel superior, que van a main
+    top_level_scope: HashMap<String, IRValue>,
+    // Valor de cada constante global; el análisis semántico garantiza que
+    // se puede calcular en cualquier sitio sin efectos
+    global_constants: HashMap<String, Expr>,
 }
 
 impl IRBuilder {
@@ -18,159 +36,547 @@ impl IRBuilder {
             current_function: None,
             temp_counter: 0,
             label_counter: 0,
-            string_literals: HashMap::new(),
+            string_literals: Vec::new(),
             string_counter: 0,
+            enum_values: HashMap::new(),
+            variadic_functions: HashMap::new(),
+            default_arguments: HashMap::new(),
+            expression_types: HashMap::new(),
+            scopes: Vec::new(),
+            top_level_scope: HashMap::new(),
+            global_constants: HashMap::new(),
         }
     }
 
-    pub fn build(&mut self, program: &Program) -> IRProgram {
+    pub fn with_expression_types(mut self, types: HashMap<NodeId, Type>) -> Self {
+        self.expression_types = types;
+        self
+    }
+
+    pub fn build(&mut self, program: &Program) -> Result<IRProgram> {
+        // Las variantes se pueden usar antes de su declaración
         for stmt in &program.statements {
-            self.build_statement(stmt);
+            if let StmtKind::Enum { variants, .. } = &stmt.kind {
+                for (value, variant) in variants.iter().enumerate() {
+                    self.enum_values.insert(variant.clone(), value as i64);
+                }
+            }
+            if let StmtKind::Function { name, params, defaults, .. } = &stmt.kind {
+                if let Some((_, Type::Variadic(_))) = params.last() {
+                    self.variadic_functions.insert(name.clone(), params.len() - 1);
+                }
+                if defaults.iter().any(Option::is_some) {
+                    self.default_arguments.insert(name.clone(), defaults.clone());
+                }
+            }
+            if let StmtKind::Let { name, value: Some(value), mutable: false, .. } = &stmt.kind {
+                self.global_constants.insert(name.clone(), value.clone());
+            }
         }
 
-        IRProgram {
-            functions: self.functions.clone(),
-            globals: HashMap::new(),
+        for stmt in &program.statements {
+            self.build_statement(stmt)?;
         }
+
+        Ok(IRProgram {
+            functions: self.functions.clone(),
+            strings: self.string_literals.clone(),
+            constant_arrays: Vec::new(),
+        })
     }
 
-    fn build_statement(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Function { name, params, body, .. } => {
+    fn build_statement(&mut self, stmt: &Stmt) -> Result<()> {
+        match &stmt.kind {
+            StmtKind::Function { name, params, body, attributes, .. } => {
                 self.current_function = Some(name.clone());
+                let inline = if attributes.iter().any(|a| a == "inline") {
+                    InlineHint::Always
+                } else if attributes.iter().any(|a| a == "noinline") {
+                    InlineHint::Never
+                } else {
+                    InlineHint::Auto
+                };
                 let mut function = IRFunction {
                     name: name.clone(),
                     params: params.iter().map(|(name, _)| name.clone()).collect(),
                     instructions: Vec::new(),
                     locals: HashMap::new(),
+                    inline,
                 };
 
+                // Los parámetros viven en el frame igual que los locales
+                self.scopes = vec![params
+                    .iter()
+                    .map(|(name, _)| (name.clone(), IRValue::Local(name.clone())))
+                    .collect()];
+
                 // Build function body
                 for body_stmt in body {
-                    self.build_function_statement(&mut function, body_stmt);
+                    self.build_function_statement(&mut function, body_stmt)?;
                 }
 
+                self.scopes.clear();
                 self.functions.push(function);
                 self.current_function = None;
             }
+            // No generan código: cada uso se sustituye por el valor
+            StmtKind::Let { mutable: false, .. } => {}
             _ => {
                 // Global statements go to main function
-                if let Some(main_func) = self.functions.iter_mut().find(|f| f.name == "main") {
-                    self.build_function_statement(main_func, stmt);
+                if let Some(pos) = self.functions.iter().position(|f| f.name == "main") {
+                    let mut main_func = self.functions.remove(pos);
+                    self.scopes = vec![std::mem::take(&mut self.top_level_scope)];
+                    let result = self.build_function_statement(&mut main_func, stmt);
+                    self.top_level_scope = self.scopes.pop().unwrap_or_default();
+                    self.functions.insert(pos, main_func);
+                    result?;
                 }
             }
         }
+        Ok(())
     }
 
-    fn build_function_statement(&mut self, function: &mut IRFunction, stmt: &Stmt) {
-        match stmt {
-            Stmt::Let { name, value, .. } => {
-                let value_result = self.build_expression(function, value);
-                let local_var = IRValue::Local(name.clone());
+    fn build_function_statement(&mut self, function: &mut IRFunction, stmt: &Stmt) -> Result<()> {
+        match &stmt.kind {
+            StmtKind::Let { name, value: Some(value), .. } => {
+                // El valor se calcula antes de declarar: en `let x = x + 1`
+                // la x de la derecha es la de fuera
+                let value_result = self.build_expression(function, value)?;
+                let local_var = self.declare_local(function, name, stmt.id);
                 function.instructions.push(IROp::Assign(local_var, value_result));
-                function.locals.insert(name.clone(), local_var);
             }
-            Stmt::If { condition, then_block, else_block } => {
-                let cond_result = self.build_expression(function, condition);
+            StmtKind::Let { name, type_annotation, value: None, .. } => {
+                let local_var = self.declare_local(function, name, stmt.id);
+                let type_ = type_annotation.as_ref().expect("let sin valor ni tipo");
+                // Los tipos con nombre que deja pasar el análisis son enums
+                let type_ = match type_ {
+                    Type::Named(name) => Type::Enum(name.clone()),
+                    other => other.clone(),
+                };
+                let instr = match TypeSystem::new().get_default_value(&type_) {
+                    Some(DefaultValue::Int(value)) => IROp::Assign(local_var, IRValue::Const(value)),
+                    Some(DefaultValue::Bool(value)) => IROp::Assign(local_var, IRValue::Const(value as i64)),
+                    Some(DefaultValue::Char(value)) => IROp::Assign(local_var, IRValue::Const(value as i64)),
+                    Some(DefaultValue::String(value)) => IROp::Assign(local_var, self.string_literal(value)),
+                    Some(DefaultValue::EmptyArray) => IROp::Alloc(local_var, 0),
+                    Some(DefaultValue::Float(_)) => bail!("float no soportado en codegen"),
+                    None => bail!("'{}' no tiene valor por defecto", name),
+                };
+                function.instructions.push(instr);
+            }
+            StmtKind::Assign { target, value } => {
+                let value_result = self.build_expression(function, value)?;
+                let target_var = self.variable(target);
+                function.instructions.push(IROp::Assign(target_var, value_result));
+            }
+            // Una tupla se guarda como un array de tamaño fijo
+            StmtKind::LetArray { names, value } | StmtKind::LetTuple { names, value } => {
+                let array = self.build_expression(function, value)?;
+                for (index, name) in names.iter().enumerate() {
+                    let local_var = self.declare_local(function, name, stmt.id);
+                    function.instructions.push(IROp::ArrayGet(
+                        local_var,
+                        array.clone(),
+                        IRValue::Const(index as i64),
+                    ));
+                }
+            }
+            StmtKind::If { condition, then_block, else_block } => {
+                let cond_result = self.build_expression(function, condition)?;
                 let else_label = self.new_label();
                 let end_label = self.new_label();
 
                 function.instructions.push(IROp::JumpIfZero(cond_result, else_label.clone()));
 
                 // Then block
-                for then_stmt in then_block {
-                    self.build_function_statement(function, then_stmt);
-                }
+                self.build_block(function, then_block)?;
                 function.instructions.push(IROp::Jump(end_label.clone()));
 
                 // Else block
                 function.instructions.push(IROp::Label(else_label));
                 if let Some(else_stmts) = else_block {
-                    for else_stmt in else_stmts {
-                        self.build_function_statement(function, else_stmt);
-                    }
+                    self.build_block(function, else_stmts)?;
                 }
 
                 function.instructions.push(IROp::Label(end_label));
             }
-            Stmt::While { condition, body } => {
+            StmtKind::While { condition, body } => {
                 let start_label = self.new_label();
                 let end_label = self.new_label();
 
                 function.instructions.push(IROp::Label(start_label.clone()));
-                let cond_result = self.build_expression(function, condition);
+                let cond_result = self.build_expression(function, condition)?;
                 function.instructions.push(IROp::JumpIfZero(cond_result, end_label.clone()));
 
-                for body_stmt in body {
-                    self.build_function_statement(function, body_stmt);
-                }
+                self.build_block(function, body)?;
                 function.instructions.push(IROp::Jump(start_label));
                 function.instructions.push(IROp::Label(end_label));
             }
-            Stmt::Return(Some(expr)) => {
-                let result = self.build_expression(function, expr);
+            StmtKind::For { init, condition, increment, body } => {
+                // La variable de inicialización vive en su propio ámbito
+                self.scopes.push(HashMap::new());
+                let result = self.build_for(function, init, condition, increment, body);
+                self.scopes.pop();
+                result?;
+            }            StmtKind::Return(Some(expr)) => {
+                let result = self.build_expression(function, expr)?;
                 function.instructions.push(IROp::Return(Some(result)));
             }
-            Stmt::Return(None) => {
+            StmtKind::Return(None) => {
                 function.instructions.push(IROp::Return(None));
             }
-            Stmt::Print(expr) => {
-                let result = self.build_expression(function, expr);
-                function.instructions.push(IROp::Print(result));
+            StmtKind::Print(expr) => {
+                let result = self.build_expression(function, expr)?;
+                let kind = match self.expression_types.get(&expr.id) {
+                    Some(Type::Bool) => PrintKind::Bool,
+                    Some(Type::String) => PrintKind::String,
+                    Some(Type::Char) => PrintKind::Char,
+                    _ => PrintKind::Int,
+                };
+                function.instructions.push(IROp::Print(result, kind));
+            }
+            StmtKind::Expression(expr) => {
+                self.build_expression(function, expr)?;
             }
             _ => {}
         }
+        Ok(())
+    }
+
+    fn build_block(&mut self, function: &mut IRFunction, stmts: &[Stmt]) -> Result<()> {
+        self.scopes.push(HashMap::new());
+        let result = stmts.iter().try_for_each(|stmt| self.build_function_statement(function, stmt));
+        self.scopes.pop();
+        result
+    }
+
+    fn build_for(
+        &mut self,
+        function: &mut IRFunction,
+        init: &Stmt,
+        condition: &Expr,
+        increment: &Stmt,
+        body: &[Stmt],
+    ) -> Result<()> {
+        self.build_function_statement(function, init)?;
+        let start_label = self.new_label();
+        let end_label = self.new_label();
+
+        function.instructions.push(IROp::Label(start_label.clone()));
+        let cond_result = self.build_expression(function, condition)?;
+        function.instructions.push(IROp::JumpIfZero(cond_result, end_label.clone()));
+
+        self.build_block(function, body)?;
+        self.build_function_statement(function, increment)?;
+        function.instructions.push(IROp::Jump(start_label));
+        function.instructions.push(IROp::Label(end_label));
+        Ok(())
+    }
+
+    // Cada let tiene su propio local. El primero de un nombre en la función
+    // se llama como la variable; los que lo ocultan llevan además el id del
+    // let, para que el interior no pise al exterior.
+    fn declare_local(&mut self, function: &mut IRFunction, name: &str, id: NodeId) -> IRValue {
+        let taken = function.locals.contains_key(name) || function.params.iter().any(|param| param == name);
+        let local_name = if taken { format!("{}.{}", name, id.0) } else { name.to_string() };
+        let local_var = IRValue::Local(local_name.clone());
+        function.locals.insert(local_name, local_var.clone());
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), local_var.clone());
+        }
+        local_var
     }
 
-    fn build_expression(&mut self, function: &mut IRFunction, expr: &Expr) -> IRValue {
-        match expr {
-            Expr::Number(n) => IRValue::Const(*n),
-            Expr::Boolean(b) => IRValue::Const(if *b { 1 } else { 0 }),
-            Expr::String(s) => {
-                let string_name = format!("str_{}", self.string_counter);
-                self.string_counter += 1;
-                self.string_literals.insert(string_name.clone(), s.clone());
-                IRValue::Global(string_name)
+    fn build_expression(&mut self, function: &mut IRFunction, expr: &Expr) -> Result<IRValue> {
+        let value = match &expr.kind {
+            ExprKind::Number(n) => IRValue::Const(*n),
+            ExprKind::Float(_) => bail!("float no soportado en codegen"),
+            ExprKind::Boolean(b) => IRValue::Const(if *b { 1 } else { 0 }),
+            ExprKind::String(s) => self.string_literal(s.clone()),
+            ExprKind::Char(c) => IRValue::Const(*c as i64),
+            ExprKind::Ident(name) => {
+                let is_local = self.scopes.iter().any(|scope| scope.contains_key(name));
+                match self.global_constants.get(name).cloned() {
+                    Some(value) if !is_local => {
+                        // El valor se calcula sin ver los locales del punto de uso
+                        let scopes = std::mem::take(&mut self.scopes);
+                        let result = self.build_expression(function, &value);
+                        self.scopes = scopes;
+                        result?
+                    }
+                    _ => self.variable(name),
+                }
+            }
+            ExprKind::Grouped(inner) => self.build_expression(function, inner)?,
+            ExprKind::EnumVariant { variant, .. } => IRValue::Const(self.enum_values[variant]),
+            ExprKind::ArrayLiteral(elements) | ExprKind::Tuple(elements) => {
+                let array = self.new_temp();
+                function.instructions.push(IROp::Alloc(array.clone(), elements.len()));
+                for (index, element) in elements.iter().enumerate() {
+                    let element_value = self.build_expression(function, element)?;
+                    function.instructions.push(IROp::ArraySet(
+                        array.clone(),
+                        IRValue::Const(index as i64),
+                        element_value,
+                    ));
+                }
+                array
             }
-            Expr::Ident(name) => {
-                if let Some(local) = function.locals.get(name) {
-                    local.clone()
+            ExprKind::TupleIndex { tuple, index } => {
+                let tuple_value = self.build_expression(function, tuple)?;
+                let temp = self.new_temp();
+                function.instructions.push(IROp::ArrayGet(temp.clone(), tuple_value, IRValue::Const(*index as i64)));
+                temp
+            }
+            ExprKind::ArrayIndex { array, index } => {
+                let array_value = self.build_expression(function, array)?;
+                let index_value = self.build_expression(function, index)?;
+                let temp = self.new_temp();
+                function.instructions.push(IROp::ArrayGet(temp.clone(), array_value, index_value));
+                temp
+            }
+            ExprKind::Prefix { op, operand } => {
+                let operand_result = self.build_expression(function, operand)?;
+                let temp = self.new_temp();
+                // -x como 0 - x y !b como b == 0
+                let instruction = match op.as_str() {
+                    "-" => IROp::Sub(temp.clone(), IRValue::Const(0), operand_result),
+                    "!" => IROp::CmpEq(temp.clone(), operand_result, IRValue::Const(0)),
+                    _ => bail!("Operador prefijo no soportado: {}", op),
+                };
+                function.instructions.push(instruction);
+                temp
+            }
+            // "a" + "b" y "x" * 3 se resuelven sin llamar al runtime
+            ExprKind::Infix { .. } if expr.constant_string().is_some() => {
+                self.string_literal(expr.constant_string().unwrap())
+            }
+            ExprKind::Infix { left, op, right } if op == "&&" || op == "||" => {
+                // Evaluación en cortocircuito: el operando derecho solo se
+                // calcula si el izquierdo no decide ya el resultado
+                let result = self.new_temp();
+                let end_label = self.new_label();
+
+                let left_result = self.build_expression(function, left)?;
+                function.instructions.push(IROp::Assign(result.clone(), left_result.clone()));
+                if op == "&&" {
+                    function.instructions.push(IROp::JumpIfZero(left_result, end_label.clone()));
                 } else {
-                    IRValue::Global(name.clone())
+                    function.instructions.push(IROp::JumpIfNotZero(left_result, end_label.clone()));
                 }
+                let right_result = self.build_expression(function, right)?;
+                function.instructions.push(IROp::Assign(result.clone(), right_result));
+                function.instructions.push(IROp::Label(end_label));
+                result
             }
-            Expr::Infix { left, op, right } => {
-                let left_result = self.build_expression(function, left);
-                let right_result = self.build_expression(function, right);
+            ExprKind::Infix { left, op, right } => {
+                let left_result = self.build_expression(function, left)?;
+                let right_result = self.build_expression(function, right)?;
                 let temp = self.new_temp();
 
+                // La suma de strings que no se resuelve en compilación reserva
+                // la cadena nueva en el runtime
+                if op == "+" && self.expression_types.get(&expr.id) == Some(&Type::String) {
+                    function.instructions.push(IROp::Call(
+                        "string_concat".to_string(),
+                        vec![left_result, right_result],
+                        Some(temp.clone()),
+                    ));
+                    return Ok(temp);
+                }
+
                 let op_instruction = match op.as_str() {
                     "+" => IROp::Add(temp.clone(), left_result, right_result),
                     "-" => IROp::Sub(temp.clone(), left_result, right_result),
                     "*" => IROp::Mul(temp.clone(), left_result, right_result),
                     "/" => IROp::Div(temp.clone(), left_result, right_result),
+                    "%" => IROp::Mod(temp.clone(), left_result, right_result),
                     "==" => IROp::CmpEq(temp.clone(), left_result, right_result),
                     "<" => IROp::CmpLt(temp.clone(), left_result, right_result),
-                    _ => panic!("Operador no soportado: {}", op),
+                    ">" => IROp::CmpGt(temp.clone(), left_result, right_result),
+                    "<=" => IROp::CmpLe(temp.clone(), left_result, right_result),
+                    ">=" => IROp::CmpGe(temp.clone(), left_result, right_result),
+                    "!=" => IROp::CmpNe(temp.clone(), left_result, right_result),
+                    _ => bail!("Operador no soportado: {}", op),
                 };
 
                 function.instructions.push(op_instruction);
                 temp
             }
-            Expr::Call { function: func_name, args } => {
-                let arg_values: Vec<IRValue> = args
+            ExprKind::Call { function: func_name, args } => {
+                // len("...") se resuelve en compilación sin llamar al runtime;
+                // cuenta bytes, igual que strlen
+                if let ("len", [arg]) = (func_name.as_str(), args.as_slice()) {
+                    if let Some(s) = arg.constant_string() {
+                        return Ok(IRValue::Const(s.len() as i64));
+                    }
+                    let value = self.build_expression(function, arg)?;
+                    let length = self.new_temp();
+                    function.instructions.push(IROp::Call(
+                        self.length_function(arg).to_string(),
+                        vec![value],
+                        Some(length.clone()),
+                    ));
+                    return Ok(length);
+                }
+                if let ("is_empty", [arg]) = (func_name.as_str(), args.as_slice()) {
+                    return self.build_is_empty(function, arg);
+                }
+                if let ("assert_eq", [left, right]) = (func_name.as_str(), args.as_slice()) {
+                    return self.build_assert_eq(function, left, right);
+                }
+                let mut arg_values = args
                     .iter()
                     .map(|arg| self.build_expression(function, arg))
-                    .collect();
+                    .collect::<Result<Vec<_>>>()?;
+                // Los argumentos omitidos toman su valor por defecto, que se
+                // evalúa en el punto de la llamada
+                if let Some(defaults) = self.default_arguments.get(func_name).cloned() {
+                    for default in defaults.iter().skip(args.len()).flatten() {
+                        let value = self.build_expression(function, default)?;
+                        arg_values.push(value);
+                    }
+                }
+                // Los argumentos variádicos se pasan como un único array
+                if let Some(&fixed) = self.variadic_functions.get(func_name) {
+                    let rest = arg_values.split_off(fixed);
+                    let array = self.new_temp();
+                    function.instructions.push(IROp::Alloc(array.clone(), rest.len()));
+                    for (index, value) in rest.into_iter().enumerate() {
+                        function.instructions.push(IROp::ArraySet(
+                            array.clone(),
+                            IRValue::Const(index as i64),
+                            value,
+                        ));
+                    }
+                    arg_values.push(array);
+                }
+                let runtime_function = match args.as_slice() {
+                    [arg] if func_name == "write" && self.expression_types.get(&arg.id) == Some(&Type::String) => {
+                        "print_string_no_nl"
+                    }
+                    _ => runtime_name(func_name),
+                };
                 let result = self.new_temp();
                 function.instructions.push(IROp::Call(
-                    func_name.clone(),
+                    runtime_function.to_string(),
                     arg_values,
                     Some(result.clone()),
                 ));
                 result
             }
+            ExprKind::New { size, .. } => {
+                // Reserva en el heap; el runtime inicializa los elementos a cero
+                let size_value = self.build_expression(function, size)?;
+                let result = self.new_temp();
+                function.instructions.push(IROp::Call(
+                    "array_new".to_string(),
+                    vec![size_value],
+                    Some(result.clone()),
+                ));
+                result
+            }
+            ExprKind::Ternary { condition, then_expr, else_expr } => {
+                let result = self.new_temp();
+                let else_label = self.new_label();
+                let end_label = self.new_label();
+
+                let cond_result = self.build_expression(function, condition)?;
+                function.instructions.push(IROp::JumpIfZero(cond_result, else_label.clone()));
+                let then_result = self.build_expression(function, then_expr)?;
+                function.instructions.push(IROp::Assign(result.clone(), then_result));
+                function.instructions.push(IROp::Jump(end_label.clone()));
+
+                function.instructions.push(IROp::Label(else_label));
+                let else_result = self.build_expression(function, else_expr)?;
+                function.instructions.push(IROp::Assign(result.clone(), else_result));
+                function.instructions.push(IROp::Label(end_label));
+                result
+            }
             _ => IRValue::Const(0), // Default
+        };
+        Ok(value)
+    }
+
+    // is_empty(x) es longitud(x) == 0; la longitud la da el runtime según
+    // el tipo del argumento
+    fn build_is_empty(&mut self, function: &mut IRFunction, arg: &Expr) -> Result<IRValue> {
+        if let Some(s) = arg.constant_string() {
+            return Ok(IRValue::Const(s.is_empty() as i64));
+        }
+        let value = self.build_expression(function, arg)?;
+        let length = self.new_temp();
+        function.instructions.push(IROp::Call(
+            self.length_function(arg).to_string(),
+            vec![value],
+            Some(length.clone()),
+        ));
+        let result = self.new_temp();
+        function.instructions.push(IROp::CmpEq(result.clone(), length, IRValue::Const(0)));
+        Ok(result)
+    }
+
+    // El runtime guarda la longitud de los arrays delante de los elementos;
+    // la de un string la cuenta strlen
+    fn length_function(&self, arg: &Expr) -> &'static str {
+        match self.expression_types.get(&arg.id) {
+            Some(Type::Array(_)) => "array_length",
+            _ => "string_length",
+        }
+    }
+
+    // assert_eq(a, b) salta el aviso si los valores coinciden; si no, el
+    // runtime muestra los dos y termina el programa. Los strings se
+    // comparan por contenido, no por dirección.
+    //
+    //     t = a != b                (strings: t = call string_equals(a, b); t = t == 0)
+    //     if t == 0 goto ok
+    //     call panic_assert_eq(a, b)
+    //   ok:
+    fn build_assert_eq(&mut self, function: &mut IRFunction, left: &Expr, right: &Expr) -> Result<IRValue> {
+        let is_string = matches!(self.expression_types.get(&left.id), Some(Type::String));
+        let left_value = self.build_expression(function, left)?;
+        let right_value = self.build_expression(function, right)?;
+
+        let differ = self.new_temp();
+        if is_string {
+            let equal = self.new_temp();
+            function.instructions.push(IROp::Call(
+                "string_equals".to_string(),
+                vec![left_value.clone(), right_value.clone()],
+                Some(equal.clone()),
+            ));
+            function.instructions.push(IROp::CmpEq(differ.clone(), equal, IRValue::Const(0)));
+        } else {
+            function.instructions.push(IROp::CmpNe(differ.clone(), left_value.clone(), right_value.clone()));
+        }
+
+        let ok_label = self.new_label();
+        function.instructions.push(IROp::JumpIfZero(differ, ok_label.clone()));
+        let panic_function = if is_string { "panic_assert_eq_string" } else { "panic_assert_eq" };
+        function.instructions.push(IROp::Call(
+            panic_function.to_string(),
+            vec![left_value, right_value],
+            None,
+        ));
+        function.instructions.push(IROp::Label(ok_label));
+        Ok(IRValue::Const(0))
+    }
+
+    fn string_literal(&mut self, value: String) -> IRValue {
+        let string_name = format!("str_{}", self.string_counter);
+        self.string_counter += 1;
+        self.string_literals.push((string_name.clone(), value));
+        IRValue::Data(string_name)
+    }
+
+    fn variable(&self, name: &str) -> IRValue {
+        if let Some(local) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            local.clone()
+        } else if let Some(value) = self.enum_values.get(name) {
+            IRValue::Const(*value)
+        } else {
+            IRValue::Global(name.to_string())
         }
     }
 
@@ -186,3 +592,406 @@ impl IRBuilder {
         label_name
     }
 }
+
+// Builtins que se implementan con una función del runtime de otro nombre
+fn runtime_name(name: &str) -> &str {
+    match name {
+        "write" => "print_int_no_nl",
+        "write_string" => "print_string_no_nl",
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{IROp, IRValue, InlineHint, PrintKind};
+    use crate::optimizer::{OptLevel, Optimizer};
+    use crate::testing::{build_ir, function, run};
+
+    fn printed(source: &str, name: &str) -> Vec<(IRValue, PrintKind)> {
+        function(&build_ir(source), name)
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Print(value, kind) => Some((value.clone(), *kind)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inner_let_does_not_overwrite_outer_variable() {
+        let prints = printed(
+            "fn main() -> int { let x = 1; if (true) { let x = \"hola\"; print(x); } print(x); return 0; }",
+            "main",
+        );
+        assert_eq!(prints.len(), 2);
+        assert_eq!(prints[1], (IRValue::Local("x".to_string()), PrintKind::Int));
+        assert_ne!(prints[0].0, prints[1].0);
+    }
+
+    #[test]
+    fn let_reads_outer_variable_before_shadowing_it() {
+        let program = build_ir("fn main() -> int { let x = 1; let x = x + 1; print(x); return 0; }");
+        let main = function(&program, "main");
+        let IROp::Print(printed, _) = main.instructions.iter().find(|i| matches!(i, IROp::Print(..))).unwrap() else {
+            unreachable!()
+        };
+        assert_ne!(*printed, IRValue::Local("x".to_string()));
+        assert!(main.instructions.iter().any(|instr| {
+            matches!(instr, IROp::Add(_, IRValue::Local(name), IRValue::Const(1)) if name == "x")
+        }));
+    }
+
+    #[test]
+    fn parameter_shadowed_in_block_keeps_its_value() {
+        let prints = printed(
+            "fn f(x: int) -> int { if (x > 0) { let x = \"pos\"; print(x); } print(x); return x; }",
+            "f",
+        );
+        assert_eq!(prints[1].0, IRValue::Local("x".to_string()));
+        assert_ne!(prints[0].0, prints[1].0);
+    }
+
+    #[test]
+    fn loop_variables_are_scoped_to_the_loop() {
+        let prints = printed(
+            "fn main() -> int { let i = 7; for (let i = 0; i < 2; i = i + 1) { print(i); } print(i); return 0; }",
+            "main",
+        );
+        assert_eq!(prints[1].0, IRValue::Local("i".to_string()));
+        assert_ne!(prints[0].0, prints[1].0);
+    }
+
+    #[test]
+    fn global_constant_is_substituted_at_each_use() {
+        let source = "const K: int = 3; const DOUBLE = K * 2; \
+                      fn f() -> int { let K = 100; return K + DOUBLE; } \
+                      fn main() -> int { print(K); return 0; }";
+        assert_eq!(printed(source, "main"), vec![(IRValue::Const(3), PrintKind::Int)]);
+        let program = build_ir(source);
+        let f = function(&program, "f");
+        assert!(f.instructions.iter().any(|instr| {
+            matches!(instr, IROp::Mul(_, IRValue::Const(3), IRValue::Const(2)))
+        }));
+        assert!(program.functions.iter().flat_map(|f| &f.instructions).all(|instr| {
+            !instr.used().iter().any(|value| matches!(value, IRValue::Global(_)))
+        }));
+    }
+
+    #[test]
+    fn len_counts_bytes_and_calls_the_runtime_for_variables() {
+        let program = build_ir("fn main() -> int { let s = \"hola\"; print(len(\"é\")); print(len(s)); return 0; }");
+        let main = function(&program, "main");
+        let IROp::Print(folded, _) = main.instructions.iter().find(|i| matches!(i, IROp::Print(..))).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(*folded, IRValue::Const(2));
+        assert!(main.instructions.iter().any(|instr| matches!(instr, IROp::Call(name, _, _) if name == "string_length")));
+        assert!(!main.instructions.iter().any(|instr| matches!(instr, IROp::Call(name, _, _) if name == "len")));
+    }
+
+    #[test]
+    fn len_of_variadic_parameter_reads_the_array_length() {
+        let program = build_ir("fn count(nums: ...int) -> int { return len(nums); } \
+                                fn main() -> int { print(count(1, 2, 3)); return 0; }");
+        let count = function(&program, "count");
+        assert!(count.instructions.iter().any(|instr| {
+            matches!(instr, IROp::Call(name, args, _) if name == "array_length" && args == &[IRValue::Local("nums".to_string())])
+        }));
+    }
+
+    #[test]
+    fn new_calls_the_runtime_with_the_size() {
+        let program = build_ir("fn main() -> int { let n = 3; let a = new [int](n * 2); return a[0]; }");
+        let calls: Vec<_> = function(&program, "main")
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Call(name, args, Some(_)) => Some((name.as_str(), args.len())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, [("array_new", 1)]);
+    }
+
+    // `let x: T;` empieza con el valor por defecto de T, y un bool sin
+    // valor se sigue imprimiendo como bool
+    #[test]
+    fn declarations_without_value_start_at_the_type_default() {
+        let source = "enum Color { Red, Green } fn f() -> int { let n: int; let b: bool; let c: char; \
+                      let k: Color; print(n); print(b); print(c); print(k); n = n + 2; return n; }";
+        assert_eq!(run(&build_ir(source), "f", &[]), (Some(2), vec![0, 0, 0, 0]));
+        let kinds: Vec<PrintKind> = printed(source, "f").into_iter().map(|(_, kind)| kind).collect();
+        assert_eq!(kinds, [PrintKind::Int, PrintKind::Bool, PrintKind::Char, PrintKind::Int]);
+
+        // El string vacío va a la sección de datos y el array al heap
+        let program = build_ir("fn f() { let s: string; let a: [int]; print(s); print(len(a)); }");
+        let instructions = &function(&program, "f").instructions;
+        assert!(matches!(instructions[0], IROp::Assign(IRValue::Local(_), IRValue::Data(_))));
+        assert!(matches!(instructions[1], IROp::Alloc(IRValue::Local(_), 0)));
+        assert_eq!(program.strings.iter().map(|(_, value)| value.as_str()).collect::<Vec<_>>(), [""]);
+    }
+
+    #[test]
+    fn comparisons_and_logic_print_as_bool() {
+        let source = "fn f(a: int, b: int) -> int { print(3 < 5); print(a < b); print(b < a); \
+                      print(a == 3 && b != 5); print(!(a > b)); print(a + b); return 0; }";
+        let mut expected = vec![PrintKind::Bool; 5];
+        expected.push(PrintKind::Int);
+        for level in [OptLevel::O0, OptLevel::O2] {
+            let mut program = build_ir(source);
+            Optimizer::with_level(level).optimize(&mut program);
+            let kinds: Vec<PrintKind> = function(&program, "f")
+                .instructions
+                .iter()
+                .filter_map(|instr| match instr {
+                    IROp::Print(_, kind) => Some(*kind),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(kinds, expected);
+            assert_eq!(run(&program, "f", &[3, 5]).1, [1, 1, 0, 0, 1, 8]);
+        }
+    }
+
+    // Forma exacta del cortocircuito: el operando derecho queda detrás de
+    // un salto que se toma cuando el izquierdo ya decide el resultado.
+    // optimizer::branchless y optimizer::logical reconocen esta forma.
+    #[test]
+    fn short_circuit_lowering_is_pinned() {
+        let cases: [(&str, &[&str]); 3] = [
+            ("a && b", &["t0 = a", "if a == 0 goto label_0", "t0 = b", "label_0:", "return t0"]),
+            ("a || b", &["t0 = a", "if a != 0 goto label_0", "t0 = b", "label_0:", "return t0"]),
+            (
+                "a && (b || c)",
+                &[
+                    "t0 = a",
+                    "if a == 0 goto label_0",
+                    "t1 = b",
+                    "if b != 0 goto label_1",
+                    "t1 = c",
+                    "label_1:",
+                    "t0 = t1",
+                    "label_0:",
+                    "return t0",
+                ],
+            ),
+        ];
+        for (expr, expected) in cases {
+            let source = format!("fn f(a: bool, b: bool, c: bool) -> bool {{ return {}; }}", expr);
+            let ir: Vec<String> =
+                function(&build_ir(&source), "f").instructions.iter().map(|instr| instr.to_string()).collect();
+            assert_eq!(ir, expected, "{}", expr);
+        }
+    }
+
+    // Una tupla se construye como un bloque de tamaño fijo y sus elementos
+    // se leen por posición, igual que los de un array
+    #[test]
+    fn tuples_lower_to_fixed_size_blocks() {
+        let source = "fn divmod(a: int, b: int) -> (int, int) { return (a / b, a % b); } \
+                      fn f(a: int, b: int) -> int { let (q, r) = divmod(a, b); return q + divmod(a, b).1; }";
+        let program = build_ir(source);
+        let ir = |name| function(&program, name).instructions.iter().map(|instr| instr.to_string()).collect::<Vec<_>>();
+        assert_eq!(ir("divmod"), ["t0 = alloc 2", "t1 = a / b", "t0[0] = t1", "t2 = a % b", "t0[1] = t2", "return t0"]);
+        assert_eq!(
+            ir("f"),
+            ["t3 = call divmod(a, b)", "q = t3[0]", "r = t3[1]", "t4 = call divmod(a, b)", "t5 = t4[1]", "t6 = q + t5", "return t6"]
+        );
+    }
+
+    // write no añade salto de línea: va a las variantes _no_nl del runtime
+    #[test]
+    fn write_lowers_to_the_no_newline_runtime_calls() {
+        let program = build_ir("fn main() { write(\"a\"); write(\"b\"); write(1); write_string(\"c\"); }");
+        let calls: Vec<&str> = function(&program, "main")
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Call(name, _, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, ["print_string_no_nl", "print_string_no_nl", "print_int_no_nl", "print_string_no_nl"]);
+    }
+
+    // -x se calcula como 0 - x y !b como b == 0
+    #[test]
+    fn prefix_operators_lower_to_subtraction_and_comparison() {
+        let source = "fn f(x: int, b: bool) -> int { let y = -5; let z = !true; let w = -x; let v = !b; \
+                      print(z); print(v); return y + w; }";
+        let program = build_ir(source);
+        let ir: Vec<String> = function(&program, "f").instructions.iter().map(|instr| instr.to_string()).collect();
+        assert_eq!(&ir[..8], ["t0 = 0 - 5", "y = t0", "t1 = 1 == 0", "z = t1", "t2 = 0 - x", "w = t2", "t3 = b == 0", "v = t3"]);
+        assert_eq!(run(&program, "f", &[3, 0]), (Some(-8), vec![0, 1]));
+        assert_eq!(run(&program, "f", &[-7, 1]), (Some(2), vec![0, 0]));
+    }
+
+    // init; inicio: condición, salida si es falsa; cuerpo; incremento; vuelta
+    #[test]
+    fn for_loops_lower_to_a_counting_loop() {
+        let source = "fn f(n: int) -> int { let s = 0; for (let i = 0; i < n; i = i + 1) { s = s + i; } return s; }";
+        let program = build_ir(source);
+        let ir: Vec<String> = function(&program, "f").instructions.iter().map(|instr| instr.to_string()).collect();
+        let expected = [
+            "s = 0",
+            "i = 0",
+            "label_0:",
+            "t0 = i < n",
+            "if t0 == 0 goto label_1",
+            "t1 = s + i",
+            "s = t1",
+            "t2 = i + 1",
+            "i = t2",
+            "goto label_0",
+            "label_1:",
+            "return s",
+        ];
+        assert_eq!(ir, expected);
+        assert_eq!(run(&program, "f", &[5]).0, Some(10));
+        assert_eq!(run(&program, "f", &[0]).0, Some(0));
+    }
+
+    #[test]
+    fn function_attributes_become_inline_hints() {
+        let program = build_ir("@inline fn a() {} @noinline fn b() {} fn c() {}");
+        let hints: Vec<InlineHint> = ["a", "b", "c"].iter().map(|name| function(&program, name).inline).collect();
+        assert_eq!(hints, [InlineHint::Always, InlineHint::Never, InlineHint::Auto]);
+    }
+
+    #[test]
+    fn each_comparison_lowers_to_its_own_instruction() {
+        let expected = |op: &str, a: i64, b: i64| match op {
+            "==" => a == b,
+            "!=" => a != b,
+            "<" => a < b,
+            ">" => a > b,
+            "<=" => a <= b,
+            _ => a >= b,
+        };
+        for op in ["==", "!=", "<", ">", "<=", ">="] {
+            let program = build_ir(&format!("fn f(a: int, b: int) -> bool {{ return a {} b; }}", op));
+            let ir: Vec<String> = function(&program, "f").instructions.iter().map(|instr| instr.to_string()).collect();
+            assert_eq!(ir, [format!("t0 = a {} b", op), "return t0".to_string()]);
+            for (a, b) in [(1, 2), (2, 2), (3, 2), (-1, 1)] {
+                assert_eq!(run(&program, "f", &[a, b]).0, Some(expected(op, a, b) as i64), "{} {} {}", a, op, b);
+            }
+        }
+    }
+
+    #[test]
+    fn else_if_chains_run_exactly_one_branch() {
+        let program = build_ir(
+            "fn f(n: int) { if (n < 0) { print(1); } else if (n == 0) { print(2); } else { print(3); } print(4); }",
+        );
+        assert_eq!(run(&program, "f", &[-5]).1, [1, 4]);
+        assert_eq!(run(&program, "f", &[0]).1, [2, 4]);
+        assert_eq!(run(&program, "f", &[5]).1, [3, 4]);
+    }
+
+    #[test]
+    fn enum_variants_are_their_ordinal_constants() {
+        let program = build_ir(
+            "enum Color { Red, Green, Blue } \
+             fn pick(Blue: int) -> int { return Blue; } \
+             fn main() -> int { print(Red); print(Color.Green); print(Blue); print(pick(7)); return 0; }",
+        );
+        assert_eq!(run(&program, "main", &[]).1, vec![0, 1, 2, 7]);
+        let prints = printed("enum Color { Red, Green } fn main() -> int { print(Color.Green); return 0; }", "main");
+        assert_eq!(prints, vec![(IRValue::Const(1), PrintKind::Int)]);
+    }
+
+    #[test]
+    fn omitted_arguments_take_the_parameter_default() {
+        let program = build_ir(
+            "fn greet(name: string, greeting: string = \"Hello\") { write(greeting); print(name); } \
+             fn main() -> int { greet(\"x\"); greet(\"x\", \"Hi\"); return 0; }",
+        );
+        let greetings: Vec<&str> = function(&program, "main")
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Call(name, args, _) if name == "greet" => Some(args),
+                _ => None,
+            })
+            .map(|args| match &args[..] {
+                [_, IRValue::Data(label)] => program.strings.iter().find(|(l, _)| l == label).unwrap().1.as_str(),
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(greetings, ["Hello", "Hi"]);
+
+        let program = build_ir(
+            "fn scale(x: int, by: int = 10, plus: int = 3) -> int { return x * by + plus; } \
+             fn main() -> int { print(scale(5)); print(scale(5, 2)); print(scale(5, 2, 0)); return 0; }",
+        );
+        assert_eq!(run(&program, "main", &[]).1, vec![53, 13, 10]);
+    }
+
+    #[test]
+    fn is_empty_compares_the_length_with_zero() {
+        let program = build_ir(
+            "fn f(s: string, a: [int]) -> int { print(is_empty(s)); print(is_empty(a)); return 0; }",
+        );
+        let f = function(&program, "f");
+        for length_function in ["string_length", "array_length"] {
+            let length = f
+                .instructions
+                .iter()
+                .find_map(|instr| match instr {
+                    IROp::Call(name, args, Some(length)) if name == length_function && args.len() == 1 => Some(length),
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("falta {}: {:?}", length_function, f.instructions));
+            assert!(f
+                .instructions
+                .iter()
+                .any(|instr| matches!(instr, IROp::CmpEq(_, value, IRValue::Const(0)) if value == length)));
+        }
+        // La longitud de un literal se conoce al compilar
+        let prints = printed("fn main() -> int { print(is_empty(\"\")); print(is_empty(\"x\")); return 0; }", "main");
+        assert_eq!(prints, vec![(IRValue::Const(1), PrintKind::Bool), (IRValue::Const(0), PrintKind::Bool)]);
+    }
+
+    #[test]
+    fn print_picks_the_runtime_call_from_the_type() {
+        let source = "fn main() -> int { print(\"hi\"); print(42); let s = \"yo\"; print(s); return 0; }";
+        let strings = build_ir(source).strings;
+        assert_eq!(strings, [("str_0".to_string(), "hi".to_string()), ("str_1".to_string(), "yo".to_string())]);
+        let prints = printed(source, "main");
+        assert_eq!(
+            prints,
+            vec![
+                (IRValue::Data("str_0".to_string()), PrintKind::String),
+                (IRValue::Const(42), PrintKind::Int),
+                (IRValue::Local("s".to_string()), PrintKind::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn assert_eq_compares_and_panics_only_when_the_values_differ() {
+        let program = build_ir("fn main() -> int { assert_eq(1, 2); assert_eq(\"a\", \"b\"); return 0; }");
+        let main = function(&program, "main");
+        let [IROp::CmpNe(differ, IRValue::Const(1), IRValue::Const(2)), IROp::JumpIfZero(condition, ok),
+             IROp::Call(panic, args, None), IROp::Label(label), ..] = &main.instructions[..]
+        else {
+            panic!("{:?}", main.instructions);
+        };
+        assert_eq!((condition, panic.as_str(), label), (differ, "panic_assert_eq", ok));
+        assert_eq!(args, &[IRValue::Const(1), IRValue::Const(2)]);
+
+        // Los strings se comparan por contenido
+        let calls: Vec<&str> = main
+            .instructions
+            .iter()
+            .skip(4)
+            .filter_map(|instr| match instr {
+                IROp::Call(name, _, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, ["string_equals", "panic_assert_eq_string"]);
+    }
+}
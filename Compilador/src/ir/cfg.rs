@@ -0,0 +1,241 @@
+use crate::ir::{IRFunction, IROp};
+use std::collections::{HashMap, HashSet};
+
+// Bloque básico: instrucciones [start, end) de la función, sin saltos
+// hacia dentro salvo al principio ni hacia fuera salvo al final.
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub predecessors: Vec<Vec<usize>>,
+}
+
+impl ControlFlowGraph {
+    pub fn build(function: &IRFunction) -> Self {
+        let instructions = &function.instructions;
+
+        // Empieza bloque: la primera instrucción, cada etiqueta y lo que
+        // sigue a un salto o return
+        let mut leaders = vec![0];
+        for (i, instr) in instructions.iter().enumerate() {
+            match instr {
+                IROp::Label(_) => leaders.push(i),
+                IROp::Jump(_) | IROp::JumpIfZero(..) | IROp::JumpIfNotZero(..) | IROp::Return(_) => {
+                    leaders.push(i + 1)
+                }
+                _ => {}
+            }
+        }
+        leaders.retain(|&i| i < instructions.len());
+        leaders.sort_unstable();
+        leaders.dedup();
+
+        let mut blocks: Vec<BasicBlock> = leaders
+            .iter()
+            .enumerate()
+            .map(|(b, &start)| BasicBlock {
+                start,
+                end: leaders.get(b + 1).copied().unwrap_or(instructions.len()),
+                successors: Vec::new(),
+            })
+            .collect();
+
+        let label_blocks: HashMap<&str, usize> = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(b, block)| match &instructions[block.start] {
+                IROp::Label(name) => Some((name.as_str(), b)),
+                _ => None,
+            })
+            .collect();
+
+        let block_count = blocks.len();
+        for (b, block) in blocks.iter_mut().enumerate() {
+            let fallthrough = (b + 1 < block_count).then_some(b + 1);
+            block.successors = match &instructions[block.end - 1] {
+                IROp::Jump(label) => label_blocks.get(label.as_str()).copied().into_iter().collect(),
+                IROp::JumpIfZero(_, label) | IROp::JumpIfNotZero(_, label) => {
+                    let mut successors: Vec<usize> =
+                        label_blocks.get(label.as_str()).copied().into_iter().collect();
+                    successors.extend(fallthrough);
+                    successors
+                }
+                IROp::Return(_) => Vec::new(),
+                _ => fallthrough.into_iter().collect(),
+            };
+            block.successors.dedup();
+        }
+
+        let mut predecessors = vec![Vec::new(); block_count];
+        for (b, block) in blocks.iter().enumerate() {
+            for &successor in &block.successors {
+                predecessors[successor].push(b);
+            }
+        }
+
+        ControlFlowGraph { blocks, predecessors }
+    }
+
+    // Algoritmo iterativo clásico: dom(b) = {b} ∪ ⋂ dom(p) para cada
+    // predecesor p. Los bloques inalcanzables quedan con un conjunto vacío.
+    pub fn dominators(&self) -> Vec<HashSet<usize>> {
+        let count = self.blocks.len();
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let reachable = self.reachable();
+        let all: HashSet<usize> = (0..count).filter(|b| reachable.contains(b)).collect();
+        let mut dominators: Vec<HashSet<usize>> = (0..count)
+            .map(|b| match b {
+                0 => HashSet::from([0]),
+                _ if reachable.contains(&b) => all.clone(),
+                _ => HashSet::new(),
+            })
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in 1..count {
+                if !reachable.contains(&b) {
+                    continue;
+                }
+                let mut new_set: Option<HashSet<usize>> = None;
+                for &p in self.predecessors[b].iter().filter(|p| reachable.contains(p)) {
+                    new_set = Some(match new_set {
+                        None => dominators[p].clone(),
+                        Some(set) => set.intersection(&dominators[p]).copied().collect(),
+                    });
+                }
+                let mut new_set = new_set.unwrap_or_default();
+                new_set.insert(b);
+                if new_set != dominators[b] {
+                    dominators[b] = new_set;
+                    changed = true;
+                }
+            }
+        }
+
+        dominators
+    }
+
+    // Cabeceras de bucles naturales: destinos de aristas b -> h donde h
+    // domina a b
+    pub fn loop_headers(&self) -> Vec<usize> {
+        let dominators = self.dominators();
+        let mut headers: Vec<usize> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(b, block)| {
+                let dominators = &dominators[b];
+                block.successors.iter().copied().filter(move |h| dominators.contains(h))
+            })
+            .collect();
+        headers.sort_unstable();
+        headers.dedup();
+        headers
+    }
+
+    // Bloques a los que se llega desde la entrada
+    pub fn reachable(&self) -> HashSet<usize> {
+        let mut reachable = HashSet::new();
+        let mut pending = vec![0];
+        while let Some(b) = pending.pop() {
+            if reachable.insert(b) {
+                pending.extend(self.blocks[b].successors.iter().copied());
+            }
+        }
+        reachable
+    }
+}
+
+impl IRFunction {
+    // Dominadores de cada bloque básico, indexados como
+    // ControlFlowGraph::build(self).blocks
+    pub fn dominators(&self) -> Vec<HashSet<usize>> {
+        ControlFlowGraph::build(self).dominators()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ControlFlowGraph;
+    use crate::ir::IROp;
+    use crate::testing::{build_ir, function};
+    use std::collections::HashSet;
+
+    fn cfg(source: &str) -> (ControlFlowGraph, Vec<IROp>) {
+        let program = build_ir(source);
+        let function = function(&program, "f");
+        (ControlFlowGraph::build(function), function.instructions.clone())
+    }
+
+    #[test]
+    fn blocks_split_at_labels_and_jumps() {
+        let (cfg, instructions) = cfg("fn f(x: int) -> int { if (x > 0) { x = 1; } else { x = 2; } return x; }");
+        // entrada, then, else y la unión
+        assert_eq!(cfg.blocks.len(), 4);
+        assert_eq!(cfg.blocks.first().unwrap().start, 0);
+        assert_eq!(cfg.blocks.last().unwrap().end, instructions.len());
+        for pair in cfg.blocks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        assert_eq!(cfg.blocks[0].successors.len(), 2);
+        assert_eq!(cfg.predecessors[3].len(), 2);
+        assert!(cfg.loop_headers().is_empty());
+
+        let dominators = cfg.dominators();
+        assert!(dominators.iter().all(|set| set.contains(&0)));
+        assert!(!dominators[3].contains(&1) && !dominators[3].contains(&2));
+    }
+
+    #[test]
+    fn loop_headers_are_the_targets_of_back_edges() {
+        let (cfg, instructions) = cfg(
+            "fn f(n: int) -> int { let t = 0; let i = 0; while (i < n) { let j = 0; \
+             while (j < i) { t = t + j; j = j + 1; } i = i + 1; } return t; }",
+        );
+        let headers = cfg.loop_headers();
+        assert_eq!(headers.len(), 2);
+        for header in headers {
+            assert!(matches!(instructions[cfg.blocks[header].start], IROp::Label(_)));
+            let back_edges = cfg.predecessors[header].iter().filter(|&&p| p > header).count();
+            assert_eq!(back_edges, 1);
+        }
+    }
+
+    #[test]
+    fn unreachable_blocks_have_no_dominators() {
+        let (cfg, _) = cfg("fn f() -> int { return 1; print(2); return 3; }");
+        assert_eq!(cfg.blocks.len(), 2);
+        assert!(!cfg.reachable().contains(&1));
+        assert!(cfg.dominators()[1].is_empty());
+    }
+
+    #[test]
+    fn dominators_of_a_diamond_and_a_loop() {
+        let set = |blocks: &[usize]| blocks.iter().copied().collect::<HashSet<usize>>();
+
+        // 0 -> {1, 2} -> 3: ninguna rama domina a la unión
+        let program = build_ir("fn f(x: int) -> int { if (x > 0) { x = 1; } else { x = 2; } return x; }");
+        let dominators = function(&program, "f").dominators();
+        assert_eq!(dominators, [set(&[0]), set(&[0, 1]), set(&[0, 2]), set(&[0, 3])]);
+
+        // 0 -> 1 (cabecera) -> {2 (cuerpo) -> 1, 3 (salida)}
+        let program = build_ir("fn f(n: int) -> int { let i = 0; while (i < n) { i = i + 1; } return i; }");
+        let function = function(&program, "f");
+        let dominators = function.dominators();
+        assert_eq!(dominators, [set(&[0]), set(&[0, 1]), set(&[0, 1, 2]), set(&[0, 1, 3])]);
+        let cfg = ControlFlowGraph::build(function);
+        assert_eq!(cfg.blocks[2].successors, [1]);
+        assert_eq!(cfg.loop_headers(), [1]);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::{IRFunction, IRValue};
+use std::collections::HashSet;
+
+// Solo los locales y temporales viven en el frame; las constantes y
+// globales no participan en el análisis.
+pub fn in_frame(value: &IRValue) -> bool {
+    matches!(value, IRValue::Local(_) | IRValue::Temp(_))
+}
+
+// Valores del frame vivos justo después de cada instrucción: se leerán más
+// adelante en algún camino sin que antes se escriban.
+pub fn live_after(function: &IRFunction) -> Vec<HashSet<IRValue>> {
+    let instructions = &function.instructions;
+    let cfg = ControlFlowGraph::build(function);
+
+    // uses: leídos en el bloque antes de escribirse; defs: escritos en él
+    let mut uses = Vec::with_capacity(cfg.blocks.len());
+    let mut defs = Vec::with_capacity(cfg.blocks.len());
+    for block in &cfg.blocks {
+        let mut block_uses = HashSet::new();
+        let mut block_defs = HashSet::new();
+        for instr in &instructions[block.start..block.end] {
+            for value in instr.used() {
+                if in_frame(value) && !block_defs.contains(value) {
+                    block_uses.insert(value.clone());
+                }
+            }
+            if let Some(value) = instr.defined().filter(|v| in_frame(v)) {
+                block_defs.insert(value.clone());
+            }
+        }
+        uses.push(block_uses);
+        defs.push(block_defs);
+    }
+
+    let mut live_in: Vec<HashSet<IRValue>> = vec![HashSet::new(); cfg.blocks.len()];
+    let mut live_out: Vec<HashSet<IRValue>> = vec![HashSet::new(); cfg.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..cfg.blocks.len()).rev() {
+            let out: HashSet<IRValue> = cfg.blocks[b]
+                .successors
+                .iter()
+                .flat_map(|&s| live_in[s].iter().cloned())
+                .collect();
+            let mut new_in = uses[b].clone();
+            new_in.extend(out.difference(&defs[b]).cloned());
+            if new_in != live_in[b] || out != live_out[b] {
+                live_in[b] = new_in;
+                live_out[b] = out;
+                changed = true;
+            }
+        }
+    }
+
+    // Recorre cada bloque hacia atrás partiendo de su conjunto de salida
+    let mut result = vec![HashSet::new(); instructions.len()];
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        let mut live = live_out[b].clone();
+        for i in (block.start..block.end).rev() {
+            result[i] = live.clone();
+            if let Some(value) = instructions[i].defined() {
+                live.remove(value);
+            }
+            live.extend(instructions[i].used().into_iter().filter(|v| in_frame(v)).cloned());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::live_after;
+    use crate::ir::{IROp, IRValue};
+    use crate::testing::{build_ir, function};
+
+    // k se lee en el cuerpo: sigue viva en todo el bucle, salto de vuelta
+    // incluido, y muere al salir
+    #[test]
+    fn values_read_in_a_loop_stay_live_across_the_back_edge() {
+        let program = build_ir(
+            "fn f(n: int) -> int { let t = 0; let k = 7; while (n > 0) { t = t + k; n = n - 1; } return t; }",
+        );
+        let function = function(&program, "f");
+        let instructions = &function.instructions;
+        let live = live_after(function);
+        let k = IRValue::Local("k".to_string());
+
+        let back_edge = instructions.iter().rposition(|instr| matches!(instr, IROp::Jump(_))).unwrap();
+        let IROp::Jump(header) = &instructions[back_edge] else { unreachable!() };
+        let header = instructions.iter().position(|instr| matches!(instr, IROp::Label(l) if l == header)).unwrap();
+        assert!((header..=back_edge).all(|i| live[i].contains(&k)));
+        assert!(!live[back_edge + 1..].iter().any(|set| set.contains(&k)));
+    }
+}
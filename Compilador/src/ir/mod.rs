@@ -1,9 +1,17 @@
-#[derive(Debug, Clone)]
+pub mod builder;
+pub mod cfg;
+pub mod liveness;
+pub mod ssa;
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum IRValue {
     Const(i64),
     Local(String),
     Global(String),
     Temp(String),
+    Data(String), // dirección de una etiqueta de datos
 }
 
 #[derive(Debug, Clone)]
@@ -12,31 +20,308 @@ pub enum IROp {
     Sub(IRValue, IRValue, IRValue),      // result = left - right
     Mul(IRValue, IRValue, IRValue),      // result = left * right
     Div(IRValue, IRValue, IRValue),      // result = left / right
+    Mod(IRValue, IRValue, IRValue),      // result = left % right
+    Shl(IRValue, IRValue, IRValue),      // result = left << right
+    Sar(IRValue, IRValue, IRValue),      // result = left >> right (aritmético)
+    Shr(IRValue, IRValue, IRValue),      // result = left >> right (lógico)
+    And(IRValue, IRValue, IRValue),      // result = left & right
+    Or(IRValue, IRValue, IRValue),       // result = left | right
     CmpEq(IRValue, IRValue, IRValue),    // result = left == right
     CmpLt(IRValue, IRValue, IRValue),    // result = left < right
+    CmpGt(IRValue, IRValue, IRValue),    // result = left > right
+    CmpLe(IRValue, IRValue, IRValue),    // result = left <= right
+    CmpGe(IRValue, IRValue, IRValue),    // result = left >= right
+    CmpNe(IRValue, IRValue, IRValue),    // result = left != right
     Assign(IRValue, IRValue),            // target = source
+    Select(IRValue, IRValue, IRValue, IRValue), // result = cond != 0 ? a : b
     Call(String, Vec<IRValue>, Option<IRValue>), // call func(args) -> result
     Label(String),                       // label:
     Jump(String),                        // jmp label
     JumpIfZero(IRValue, String),         // jz value, label
     JumpIfNotZero(IRValue, String),      // jnz value, label
     Return(Option<IRValue>),             // return value
-    Print(IRValue),                      // print value
-    Alloc(String, usize),                // alloc array
+    Print(IRValue, PrintKind),           // print value
+    Alloc(IRValue, usize),               // result = new array of size elements
     ArraySet(IRValue, IRValue, IRValue), // array[index] = value
     ArrayGet(IRValue, IRValue, IRValue), // value = array[index]
 }
 
+// Cómo se muestra el valor de un print: cada tipo tiene su función en el runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintKind {
+    Int,
+    Bool,
+    String,
+    Char,
+}
+
+impl IROp {
+    // Reconstruye la instrucción aplicando `value` a cada operando (incluido
+    // el destino) y `label` a cada etiqueta o nombre de array.
+    pub fn map(
+        &self,
+        value: &mut dyn FnMut(&IRValue) -> IRValue,
+        label: &mut dyn FnMut(&str) -> String,
+    ) -> IROp {
+        match self {
+            IROp::Add(r, a, b) => IROp::Add(value(r), value(a), value(b)),
+            IROp::Sub(r, a, b) => IROp::Sub(value(r), value(a), value(b)),
+            IROp::Mul(r, a, b) => IROp::Mul(value(r), value(a), value(b)),
+            IROp::Div(r, a, b) => IROp::Div(value(r), value(a), value(b)),
+            IROp::Mod(r, a, b) => IROp::Mod(value(r), value(a), value(b)),
+            IROp::Shl(r, a, b) => IROp::Shl(value(r), value(a), value(b)),
+            IROp::Sar(r, a, b) => IROp::Sar(value(r), value(a), value(b)),
+            IROp::Shr(r, a, b) => IROp::Shr(value(r), value(a), value(b)),
+            IROp::And(r, a, b) => IROp::And(value(r), value(a), value(b)),
+            IROp::Or(r, a, b) => IROp::Or(value(r), value(a), value(b)),
+            IROp::CmpEq(r, a, b) => IROp::CmpEq(value(r), value(a), value(b)),
+            IROp::CmpLt(r, a, b) => IROp::CmpLt(value(r), value(a), value(b)),
+            IROp::CmpGt(r, a, b) => IROp::CmpGt(value(r), value(a), value(b)),
+            IROp::CmpLe(r, a, b) => IROp::CmpLe(value(r), value(a), value(b)),
+            IROp::CmpGe(r, a, b) => IROp::CmpGe(value(r), value(a), value(b)),
+            IROp::CmpNe(r, a, b) => IROp::CmpNe(value(r), value(a), value(b)),
+            IROp::Assign(target, source) => IROp::Assign(value(target), value(source)),
+            IROp::Select(r, cond, a, b) => IROp::Select(value(r), value(cond), value(a), value(b)),
+            IROp::Call(func, args, result) => IROp::Call(
+                func.clone(),
+                args.iter().map(&mut *value).collect(),
+                result.as_ref().map(&mut *value),
+            ),
+            IROp::Label(name) => IROp::Label(label(name)),
+            IROp::Jump(target) => IROp::Jump(label(target)),
+            IROp::JumpIfZero(cond, target) => IROp::JumpIfZero(value(cond), label(target)),
+            IROp::JumpIfNotZero(cond, target) => IROp::JumpIfNotZero(value(cond), label(target)),
+            IROp::Return(result) => IROp::Return(result.as_ref().map(&mut *value)),
+            IROp::Print(v, kind) => IROp::Print(value(v), *kind),
+            IROp::Alloc(r, size) => IROp::Alloc(value(r), *size),
+            IROp::ArraySet(array, index, v) => IROp::ArraySet(value(array), value(index), value(v)),
+            IROp::ArrayGet(r, array, index) => IROp::ArrayGet(value(r), value(array), value(index)),
+        }
+    }
+
+    // Instrucciones que hacen algo más que escribir su resultado: no se
+    // pueden eliminar, fusionar ni mover aunque el resultado no se use o
+    // ya se haya calculado. Una llamada puede imprimir o leer la entrada,
+    // y cada Alloc devuelve un array distinto.
+    pub fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            IROp::Call(..)
+                | IROp::Print(..)
+                | IROp::Alloc(..)
+                | IROp::ArraySet(..)
+                | IROp::Label(_)
+                | IROp::Jump(_)
+                | IROp::JumpIfZero(..)
+                | IROp::JumpIfNotZero(..)
+                | IROp::Return(_)
+        )
+    }
+
+    // Valor que escribe la instrucción, si escribe alguno
+    pub fn defined(&self) -> Option<&IRValue> {
+        match self {
+            IROp::Add(r, _, _)
+            | IROp::Sub(r, _, _)
+            | IROp::Mul(r, _, _)
+            | IROp::Div(r, _, _)
+            | IROp::Mod(r, _, _)
+            | IROp::Shl(r, _, _)
+            | IROp::Sar(r, _, _)
+            | IROp::Shr(r, _, _)
+            | IROp::And(r, _, _)
+            | IROp::Or(r, _, _)
+            | IROp::CmpEq(r, _, _)
+            | IROp::CmpLt(r, _, _)
+            | IROp::CmpGt(r, _, _)
+            | IROp::CmpLe(r, _, _)
+            | IROp::CmpGe(r, _, _)
+            | IROp::CmpNe(r, _, _)
+            | IROp::Assign(r, _)
+            | IROp::Select(r, _, _, _)
+            | IROp::ArrayGet(r, _, _)
+            | IROp::Alloc(r, _) => Some(r),
+            IROp::Call(_, _, result) => result.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn defined_mut(&mut self) -> Option<&mut IRValue> {
+        match self {
+            IROp::Add(r, _, _)
+            | IROp::Sub(r, _, _)
+            | IROp::Mul(r, _, _)
+            | IROp::Div(r, _, _)
+            | IROp::Mod(r, _, _)
+            | IROp::Shl(r, _, _)
+            | IROp::Sar(r, _, _)
+            | IROp::Shr(r, _, _)
+            | IROp::And(r, _, _)
+            | IROp::Or(r, _, _)
+            | IROp::CmpEq(r, _, _)
+            | IROp::CmpLt(r, _, _)
+            | IROp::CmpGt(r, _, _)
+            | IROp::CmpLe(r, _, _)
+            | IROp::CmpGe(r, _, _)
+            | IROp::CmpNe(r, _, _)
+            | IROp::Assign(r, _)
+            | IROp::Select(r, _, _, _)
+            | IROp::ArrayGet(r, _, _)
+            | IROp::Alloc(r, _) => Some(r),
+            IROp::Call(_, _, result) => result.as_mut(),
+            _ => None,
+        }
+    }
+
+    // Valores que lee la instrucción
+    pub fn used(&self) -> Vec<&IRValue> {
+        match self {
+            IROp::Add(_, a, b)
+            | IROp::Sub(_, a, b)
+            | IROp::Mul(_, a, b)
+            | IROp::Div(_, a, b)
+            | IROp::Mod(_, a, b)
+            | IROp::Shl(_, a, b)
+            | IROp::Sar(_, a, b)
+            | IROp::Shr(_, a, b)
+            | IROp::And(_, a, b)
+            | IROp::Or(_, a, b)
+            | IROp::CmpEq(_, a, b)
+            | IROp::CmpLt(_, a, b)
+            | IROp::CmpGt(_, a, b)
+            | IROp::CmpLe(_, a, b)
+            | IROp::CmpGe(_, a, b)
+            | IROp::CmpNe(_, a, b)
+            | IROp::ArrayGet(_, a, b) => vec![a, b],
+            IROp::Assign(_, source) => vec![source],
+            IROp::Select(_, cond, a, b) => vec![cond, a, b],
+            IROp::Call(_, args, _) => args.iter().collect(),
+            IROp::JumpIfZero(cond, _) | IROp::JumpIfNotZero(cond, _) => vec![cond],
+            IROp::Return(Some(v)) | IROp::Print(v, _) => vec![v],
+            IROp::ArraySet(array, index, v) => vec![array, index, v],
+            IROp::Return(None) | IROp::Label(_) | IROp::Jump(_) | IROp::Alloc(..) => Vec::new(),
+        }
+    }
+}
+
+// Indicación para el inliner, tomada de @inline/@noinline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InlineHint {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
 #[derive(Debug, Clone)]
 pub struct IRFunction {
     pub name: String,
     pub params: Vec<String>,
     pub instructions: Vec<IROp>,
     pub locals: std::collections::HashMap<String, IRValue>,
+    pub inline: InlineHint,
 }
 
 #[derive(Debug, Clone)]
 pub struct IRProgram {
     pub functions: Vec<IRFunction>,
-    pub globals: std::collections::HashMap<String, IRValue>,
+    pub strings: Vec<(String, String)>, // (etiqueta, contenido) de los literales
+    pub constant_arrays: Vec<(String, Vec<i64>)>, // (etiqueta, elementos) de solo lectura
+}
+
+// Formato de texto para --emit-ir: código de tres direcciones legible,
+// con los globales como `@nombre` y las direcciones de datos como `&etiqueta`
+impl fmt::Display for IRValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IRValue::Const(n) => write!(f, "{}", n),
+            IRValue::Local(name) | IRValue::Temp(name) => write!(f, "{}", name),
+            IRValue::Global(name) => write!(f, "@{}", name),
+            IRValue::Data(label) => write!(f, "&{}", label),
+        }
+    }
+}
+
+impl fmt::Display for IROp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let binary = |f: &mut fmt::Formatter, r: &IRValue, a: &IRValue, op: &str, b: &IRValue| {
+            write!(f, "{} = {} {} {}", r, a, op, b)
+        };
+        match self {
+            IROp::Add(r, a, b) => binary(f, r, a, "+", b),
+            IROp::Sub(r, a, b) => binary(f, r, a, "-", b),
+            IROp::Mul(r, a, b) => binary(f, r, a, "*", b),
+            IROp::Div(r, a, b) => binary(f, r, a, "/", b),
+            IROp::Mod(r, a, b) => binary(f, r, a, "%", b),
+            IROp::Shl(r, a, b) => binary(f, r, a, "<<", b),
+            IROp::Sar(r, a, b) => binary(f, r, a, ">>", b),
+            IROp::Shr(r, a, b) => binary(f, r, a, ">>>", b),
+            IROp::And(r, a, b) => binary(f, r, a, "&", b),
+            IROp::Or(r, a, b) => binary(f, r, a, "|", b),
+            IROp::CmpEq(r, a, b) => binary(f, r, a, "==", b),
+            IROp::CmpLt(r, a, b) => binary(f, r, a, "<", b),
+            IROp::CmpGt(r, a, b) => binary(f, r, a, ">", b),
+            IROp::CmpLe(r, a, b) => binary(f, r, a, "<=", b),
+            IROp::CmpGe(r, a, b) => binary(f, r, a, ">=", b),
+            IROp::CmpNe(r, a, b) => binary(f, r, a, "!=", b),
+            IROp::Assign(target, source) => write!(f, "{} = {}", target, source),
+            IROp::Select(r, cond, a, b) => write!(f, "{} = {} ? {} : {}", r, cond, a, b),
+            IROp::Call(func, args, result) => {
+                if let Some(result) = result {
+                    write!(f, "{} = ", result)?;
+                }
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "call {}({})", func, args.join(", "))
+            }
+            IROp::Label(name) => write!(f, "{}:", name),
+            IROp::Jump(label) => write!(f, "goto {}", label),
+            IROp::JumpIfZero(cond, label) => write!(f, "if {} == 0 goto {}", cond, label),
+            IROp::JumpIfNotZero(cond, label) => write!(f, "if {} != 0 goto {}", cond, label),
+            IROp::Return(Some(value)) => write!(f, "return {}", value),
+            IROp::Return(None) => write!(f, "return"),
+            IROp::Print(value, PrintKind::Int) => write!(f, "print {}", value),
+            IROp::Print(value, PrintKind::Bool) => write!(f, "print_bool {}", value),
+            IROp::Print(value, PrintKind::String) => write!(f, "print_string {}", value),
+            IROp::Print(value, PrintKind::Char) => write!(f, "print_char {}", value),
+            IROp::Alloc(r, size) => write!(f, "{} = alloc {}", r, size),
+            IROp::ArraySet(array, index, value) => write!(f, "{}[{}] = {}", array, index, value),
+            IROp::ArrayGet(r, array, index) => write!(f, "{} = {}[{}]", r, array, index),
+        }
+    }
+}
+
+impl fmt::Display for IRFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "fn {}({}):", self.name, self.params.join(", "))?;
+        for instr in &self.instructions {
+            // Las etiquetas sobresalen para que se vean los bloques
+            match instr {
+                IROp::Label(_) => writeln!(f, "  {}", instr)?,
+                _ => writeln!(f, "    {}", instr)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for IRProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (label, value) in &self.strings {
+            writeln!(f, "{} = {:?}", label, value)?;
+        }
+        for (label, values) in &self.constant_arrays {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            writeln!(f, "{} = [{}]", label, values.join(", "))?;
+        }
+        if !self.strings.is_empty() || !self.constant_arrays.is_empty() {
+            writeln!(f)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
 }
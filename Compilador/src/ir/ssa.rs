@@ -0,0 +1,342 @@
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::liveness::{in_frame, live_after};
+use crate::ir::{IRFunction, IROp, IRValue};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Forma SSA de una función: cada local o temporal se escribe una sola vez,
+// con el número de versión detrás del nombre (`x.2`). Donde se juntan
+// caminos con definiciones distintas, una phi elige el valor según el
+// predecesor por el que se llegó. Los bloques inalcanzables se omiten.
+pub struct SsaFunction {
+    pub name: String,
+    pub params: Vec<IRValue>,
+    pub blocks: Vec<SsaBlock>,
+}
+
+// Un bloque se llama como su etiqueta o, si no tiene, `bN`
+pub struct SsaBlock {
+    pub name: String,
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<IROp>,
+}
+
+// Valor que llega por cada predecesor; None si por ese camino la variable
+// todavía no se ha escrito. El predecesor de la entrada se llama `entry`.
+pub struct Phi {
+    pub result: IRValue,
+    pub sources: Vec<(String, Option<IRValue>)>,
+}
+
+// Construcción clásica de Cytron et al.: phis en la frontera de dominancia
+// de cada definición y renombrado recorriendo el árbol de dominadores. Solo
+// se pone una phi donde la variable está viva, para no llenar el volcado
+// de phis de temporales que nadie lee.
+pub fn to_ssa(function: &IRFunction) -> SsaFunction {
+    let cfg = ControlFlowGraph::build(function);
+    let dominators = function.dominators();
+    let idom = immediate_dominators(&dominators);
+    let frontiers = dominance_frontiers(&cfg, &idom);
+    let reachable: Vec<usize> = (0..cfg.blocks.len()).filter(|&b| !dominators[b].is_empty()).collect();
+
+    let names: Vec<String> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(b, block)| match &function.instructions[block.start] {
+            IROp::Label(name) => name.clone(),
+            _ => format!("b{}", b),
+        })
+        .collect();
+
+    // Bloques que escriben cada variable, en orden de primera escritura
+    // para que el volcado sea estable
+    let mut definitions: Vec<(IRValue, Vec<usize>)> = Vec::new();
+    for &b in &reachable {
+        let block = &cfg.blocks[b];
+        for instr in &function.instructions[block.start..block.end] {
+            let Some(value) = instr.defined().filter(|v| in_frame(v)) else {
+                continue;
+            };
+            match definitions.iter_mut().find(|(variable, _)| variable == value) {
+                Some((_, blocks)) => blocks.push(b),
+                None => definitions.push((value.clone(), vec![b])),
+            }
+        }
+    }
+
+    let live_after = live_after(function);
+    let live_in: Vec<HashSet<IRValue>> = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            let first = &function.instructions[block.start];
+            let mut live = live_after[block.start].clone();
+            if let Some(defined) = first.defined() {
+                live.remove(defined);
+            }
+            live.extend(first.used().into_iter().filter(|v| in_frame(v)).cloned());
+            live
+        })
+        .collect();
+
+    let mut phi_variables: Vec<Vec<IRValue>> = vec![Vec::new(); cfg.blocks.len()];
+    for (variable, blocks) in &definitions {
+        let mut pending = blocks.clone();
+        let mut placed = HashSet::new();
+        while let Some(b) = pending.pop() {
+            for &frontier in &frontiers[b] {
+                if live_in[frontier].contains(variable) && placed.insert(frontier) {
+                    phi_variables[frontier].push(variable.clone());
+                    pending.push(frontier);
+                }
+            }
+        }
+    }
+
+    let mut children = vec![Vec::new(); cfg.blocks.len()];
+    for &b in &reachable {
+        if let Some(parent) = idom[b] {
+            children[parent].push(b);
+        }
+    }
+
+    let mut renamer = Renamer {
+        function,
+        cfg: &cfg,
+        names: &names,
+        phi_variables: &phi_variables,
+        children: &children,
+        versions: HashMap::new(),
+        stacks: HashMap::new(),
+        // Las phis existen desde el principio: un predecesor puede visitarse
+        // antes que el bloque y añadirles ya su valor
+        blocks: names
+            .iter()
+            .zip(&phi_variables)
+            .map(|(name, variables)| SsaBlock {
+                name: name.clone(),
+                phis: variables
+                    .iter()
+                    .map(|variable| Phi { result: variable.clone(), sources: Vec::new() })
+                    .collect(),
+                instructions: Vec::new(),
+            })
+            .collect(),
+    };
+
+    // Los parámetros se escriben antes del primer bloque
+    let params: Vec<IRValue> = function
+        .params
+        .iter()
+        .map(|param| renamer.define(&IRValue::Local(param.clone())))
+        .collect();
+    if !cfg.blocks.is_empty() {
+        let entry_sources: Vec<Option<IRValue>> =
+            phi_variables[0].iter().map(|variable| renamer.current(variable)).collect();
+        renamer.rename(0);
+        for (phi, source) in renamer.blocks[0].phis.iter_mut().zip(entry_sources) {
+            phi.sources.insert(0, ("entry".to_string(), source));
+        }
+    }
+
+    let blocks = renamer
+        .blocks
+        .into_iter()
+        .enumerate()
+        .filter(|(b, _)| reachable.contains(b))
+        .map(|(_, block)| block)
+        .collect();
+
+    SsaFunction { name: function.name.clone(), params, blocks }
+}
+
+// El dominador inmediato es el dominador estricto más cercano: el que a
+// su vez tiene más dominadores
+fn immediate_dominators(dominators: &[HashSet<usize>]) -> Vec<Option<usize>> {
+    dominators
+        .iter()
+        .enumerate()
+        .map(|(b, set)| {
+            set.iter()
+                .copied()
+                .filter(|&d| d != b)
+                .max_by_key(|&d| dominators[d].len())
+        })
+        .collect()
+}
+
+// Frontera de dominancia (Cooper, Harvey y Kennedy): desde cada
+// predecesor se sube por el árbol hasta el dominador inmediato del bloque
+fn dominance_frontiers(cfg: &ControlFlowGraph, idom: &[Option<usize>]) -> Vec<HashSet<usize>> {
+    let mut frontiers = vec![HashSet::new(); cfg.blocks.len()];
+    for (b, predecessors) in cfg.predecessors.iter().enumerate() {
+        for &p in predecessors {
+            let mut runner = Some(p);
+            while runner != idom[b] {
+                let Some(r) = runner else { break };
+                frontiers[r].insert(b);
+                runner = idom[r];
+            }
+        }
+    }
+    frontiers
+}
+
+struct Renamer<'a> {
+    function: &'a IRFunction,
+    cfg: &'a ControlFlowGraph,
+    names: &'a [String],
+    phi_variables: &'a [Vec<IRValue>],
+    children: &'a [Vec<usize>],
+    // Siguiente versión de cada nombre y versiones visibles en el bloque
+    versions: HashMap<String, usize>,
+    stacks: HashMap<IRValue, Vec<IRValue>>,
+    blocks: Vec<SsaBlock>,
+}
+
+impl Renamer<'_> {
+    fn define(&mut self, variable: &IRValue) -> IRValue {
+        let (IRValue::Local(name) | IRValue::Temp(name)) = variable else {
+            return variable.clone();
+        };
+        let version = self.versions.entry(name.clone()).or_insert(0);
+        let versioned = format!("{}.{}", name, version);
+        *version += 1;
+        let value = match variable {
+            IRValue::Local(_) => IRValue::Local(versioned),
+            _ => IRValue::Temp(versioned),
+        };
+        self.stacks.entry(variable.clone()).or_default().push(value.clone());
+        value
+    }
+
+    fn current(&self, variable: &IRValue) -> Option<IRValue> {
+        self.stacks.get(variable).and_then(|stack| stack.last()).cloned()
+    }
+
+    fn rename(&mut self, b: usize) {
+        let (function, cfg, phi_variables, children) = (self.function, self.cfg, self.phi_variables, self.children);
+        let mut defined = Vec::new();
+
+        for (index, variable) in phi_variables[b].iter().enumerate() {
+            self.blocks[b].phis[index].result = self.define(variable);
+            defined.push(variable.clone());
+        }
+
+        let block = &cfg.blocks[b];
+        for instr in &function.instructions[block.start..block.end] {
+            if matches!(instr, IROp::Label(_)) {
+                continue;
+            }
+            // Primero los operandos con las versiones de antes de la
+            // instrucción, luego la nueva versión del destino
+            let mut renamed = instr.map(
+                &mut |value| match in_frame(value) {
+                    true => self.current(value).unwrap_or_else(|| value.clone()),
+                    false => value.clone(),
+                },
+                &mut |label| label.to_string(),
+            );
+            if let Some(variable) = instr.defined().filter(|v| in_frame(v)) {
+                let value = self.define(variable);
+                *renamed.defined_mut().unwrap() = value;
+                defined.push(variable.clone());
+            }
+            self.blocks[b].instructions.push(renamed);
+        }
+
+        for &successor in &block.successors {
+            for (index, variable) in phi_variables[successor].iter().enumerate() {
+                let source = self.current(variable);
+                self.blocks[successor].phis[index].sources.push((self.names[b].clone(), source));
+            }
+        }
+
+        for &child in &children[b] {
+            self.rename(child);
+        }
+
+        for variable in defined {
+            self.stacks.get_mut(&variable).unwrap().pop();
+        }
+    }
+}
+
+impl fmt::Display for SsaFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|param| param.to_string()).collect();
+        writeln!(f, "fn {}({}):", self.name, params.join(", "))?;
+        for block in &self.blocks {
+            writeln!(f, "  {}:", block.name)?;
+            for phi in &block.phis {
+                let sources: Vec<String> = phi
+                    .sources
+                    .iter()
+                    .map(|(predecessor, value)| match value {
+                        Some(value) => format!("[{}, {}]", value, predecessor),
+                        None => format!("[undef, {}]", predecessor),
+                    })
+                    .collect();
+                writeln!(f, "    {} = phi {}", phi.result, sources.join(", "))?;
+            }
+            for instr in &block.instructions {
+                writeln!(f, "    {}", instr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_ssa;
+    use crate::testing::{build_ir, function};
+
+    // Phis de cada bloque, ya formateadas, y el volcado para los mensajes
+    fn phis(source: &str) -> (Vec<(String, Vec<String>)>, String) {
+        let ssa = to_ssa(function(&build_ir(source), "f"));
+        let phis = ssa
+            .blocks
+            .iter()
+            .map(|block| {
+                let phis = block
+                    .phis
+                    .iter()
+                    .map(|phi| {
+                        let sources: Vec<String> = phi
+                            .sources
+                            .iter()
+                            .map(|(predecessor, value)| format!("{}@{}", value.as_ref().unwrap(), predecessor))
+                            .collect();
+                        format!("{} = {}", phi.result, sources.join(" "))
+                    })
+                    .collect();
+                (block.name.clone(), phis)
+            })
+            .collect();
+        (phis, ssa.to_string())
+    }
+
+    #[test]
+    fn phis_go_at_the_if_merge_and_the_loop_header() {
+        let source = "fn f(c: bool, n: int) -> int { let x = 0; if (c) { x = 1; } else { x = 2; } \
+                      let i = 0; while (i < n) { i = i + 1; } return x + i; }";
+        let (phis, dump) = phis(source);
+        let with_phis: Vec<_> = phis.iter().filter(|(_, phis)| !phis.is_empty()).collect();
+        let expected = [
+            ("label_1".to_string(), vec!["x.3 = x.1@b1 x.2@label_0".to_string()]),
+            ("label_2".to_string(), vec!["i.1 = i.0@label_1 i.2@b5".to_string()]),
+        ];
+        assert_eq!(with_phis, expected.iter().collect::<Vec<_>>(), "{}", dump);
+    }
+
+    // Una variable que solo se escribe en una rama no se junta con nada
+    // si nadie la lee después
+    #[test]
+    fn no_phi_for_values_dead_at_the_merge() {
+        let source = "fn f(c: bool) -> int { let x = 0; if (c) { x = 1; print(x); } return 0; }";
+        let (phis, dump) = phis(source);
+        assert!(phis.iter().all(|(_, phis)| phis.is_empty()), "{}", dump);
+    }
+}
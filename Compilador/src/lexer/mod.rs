@@ -1,10 +1,31 @@
-use crate::lexer::token::Token;
+pub mod token;
+
+use crate::lexer::token::{Span, Token};
 use anyhow::Result;
 
+const DEFAULT_TAB_WIDTH: usize = 8;
+
 pub struct Lexer {
     input: Vec<char>,
     pos: usize,
     ch: char,
+    line: usize,
+    column: usize,
+    // Columnas entre paradas de tabulador, para que las posiciones de los
+    // errores coincidan con lo que muestra el terminal
+    tab_width: usize,
+    // Posición donde empieza el último token leído
+    token_span: Span,
+    token_start: usize,
+}
+
+// Token con su posición y los caracteres [start, end) del fuente que ocupa
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexedToken {
+    pub token: Token,
+    pub span: Span,
+    pub start: usize,
+    pub end: usize,
 }
 
 impl Lexer {
@@ -14,6 +35,11 @@ impl Lexer {
             input: chars,
             pos: 0,
             ch: '\0',
+            line: 1,
+            column: 1,
+            tab_width: DEFAULT_TAB_WIDTH,
+            token_span: Span::default(),
+            token_start: 0,
         };
         if !lexer.input.is_empty() {
             lexer.ch = lexer.input[0];
@@ -21,8 +47,35 @@ impl Lexer {
         lexer
     }
 
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    // Sigue el análisis desde el carácter `pos`, que está en `span`
+    pub fn starting_at(mut self, pos: usize, span: Span) -> Self {
+        self.pos = pos.min(self.input.len());
+        self.ch = self.input.get(self.pos).copied().unwrap_or('\0');
+        self.line = span.line;
+        self.column = span.column;
+        self
+    }
+
     fn read_char(&mut self) {
-        self.pos += 1;
+        // Nunca avanzar más allá del final: los slices usan self.pos
+        if self.pos < self.input.len() {
+            if self.ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else if self.ch == '\t' {
+                // Las columnas empiezan en 1: la siguiente parada es la
+                // primera columna tras un múltiplo de tab_width
+                self.column = (self.column - 1) / self.tab_width * self.tab_width + self.tab_width + 1;
+            } else {
+                self.column += 1;
+            }
+            self.pos += 1;
+        }
         if self.pos >= self.input.len() {
             self.ch = '\0';
         } else {
@@ -30,8 +83,46 @@ impl Lexer {
         }
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_whitespace() && self.ch != '\0' {
+    fn peek_char(&self) -> char {
+        self.input.get(self.pos + 1).copied().unwrap_or('\0')
+    }
+
+    // Salta espacios y comentarios: '//' hasta el fin de línea y
+    // '/* ... */', que pueden anidarse
+    fn skip_whitespace(&mut self) -> Result<()> {
+        loop {
+            while self.ch.is_whitespace() && !self.at_eof() {
+                self.read_char();
+            }
+            if self.ch == '/' && self.peek_char() == '/' {
+                while self.ch != '\n' && !self.at_eof() {
+                    self.read_char();
+                }
+            } else if self.ch == '/' && self.peek_char() == '*' {
+                self.skip_block_comment()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            if self.at_eof() {
+                return Err(anyhow::anyhow!("comentario de bloque sin cerrar"));
+            }
+            if self.ch == '/' && self.peek_char() == '*' {
+                depth += 1;
+                self.read_char();
+            } else if self.ch == '*' && self.peek_char() == '/' {
+                depth -= 1;
+                self.read_char();
+                if depth == 0 {
+                    self.read_char();
+                    return Ok(());
+                }
+            }
             self.read_char();
         }
     }
@@ -44,29 +135,126 @@ impl Lexer {
         self.input[start..self.pos].iter().collect()
     }
 
-    fn read_number(&mut self) -> i64 {
+    // Posición actual, para errores a mitad de un token
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn at_eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn read_number(&mut self) -> Result<Token> {
         let start = self.pos;
-        while self.ch.is_digit(10) {
+        while self.ch.is_ascii_digit() {
             self.read_char();
         }
-        self.input[start..self.pos].iter().collect::<String>().parse().unwrap()
+        // Un '.' solo forma parte del número si le siguen dígitos, y no
+        // cuando el número es el índice de una tupla: en t.0.1 son dos
+        let tuple_index = start > 0 && self.input[start - 1] == '.';
+        if self.ch == '.' && self.peek_char().is_ascii_digit() && !tuple_index {
+            self.read_char();
+            while self.ch.is_ascii_digit() {
+                self.read_char();
+            }
+            let digits: String = self.input[start..self.pos].iter().collect();
+            return digits
+                .parse()
+                .map(Token::Float)
+                .map_err(|_| anyhow::anyhow!("literal float no válido: {}", digits));
+        }
+        let digits: String = self.input[start..self.pos].iter().collect();
+        digits
+            .parse()
+            .map(Token::Number)
+            .map_err(|_| anyhow::anyhow!("literal entero fuera de rango: {}", digits))
     }
 
-    fn read_string(&mut self) -> String {
+    fn read_string(&mut self) -> Result<String> {
         self.read_char(); // skip opening quote
-        let start = self.pos;
-        while self.ch != '"' && self.ch != '\0' {
+        let mut result = String::new();
+        while self.ch != '"' && !self.at_eof() {
+            if self.ch == '\\' {
+                self.read_char();
+                if self.at_eof() {
+                    break;
+                }
+                result.push(self.escape()?);
+            } else {
+                result.push(self.ch);
+            }
             self.read_char();
         }
-        let result: String = self.input[start..self.pos].iter().collect();
-        if self.ch == '"' {
-            self.read_char(); // skip closing quote
+        if self.at_eof() {
+            return Err(anyhow::anyhow!("Cadena sin cerrar"));
+        }
+        self.read_char(); // skip closing quote
+        Ok(result)
+    }
+
+    // Carácter que representa `\x`, con self.ch en la x
+    fn escape(&self) -> Result<char> {
+        Ok(match self.ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            c => return Err(anyhow::anyhow!("Secuencia de escape desconocida: \\{}", c)),
+        })
+    }
+
+    // 'A' o '\n': un solo carácter ASCII, que se guarda como un byte
+    fn read_char_literal(&mut self) -> Result<char> {
+        self.read_char(); // skip opening quote
+        let value = match self.ch {
+            '\'' => return Err(anyhow::anyhow!("Literal de carácter vacío")),
+            '\n' => return Err(anyhow::anyhow!("Literal de carácter sin cerrar")),
+            _ if self.at_eof() => return Err(anyhow::anyhow!("Literal de carácter sin cerrar")),
+            '\\' => {
+                self.read_char();
+                self.escape()?
+            }
+            c if !c.is_ascii() => {
+                return Err(anyhow::anyhow!("Solo se admiten caracteres ASCII: '{}'", c))
+            }
+            c => c,
+        };
+        self.read_char();
+        if self.ch != '\'' {
+            // Si la comilla de cierre está más adelante en la línea es un
+            // 'ab'; si no, la comilla quedó abierta
+            if self.input[self.pos..].iter().take_while(|&&c| c != '\n').any(|&c| c == '\'') {
+                return Err(anyhow::anyhow!("Un literal de carácter solo puede tener un carácter"));
+            }
+            return Err(anyhow::anyhow!("Literal de carácter sin cerrar"));
         }
-        result
+        self.read_char(); // skip closing quote
+        Ok(value)
+    }
+
+    // Como next_token, con la posición del token; los errores la incluyen
+    pub fn next_lexed(&mut self) -> Result<LexedToken> {
+        let token = self
+            .next_token()
+            .map_err(|err| anyhow::anyhow!("error en {}: {}", self.span(), err))?;
+        Ok(LexedToken {
+            token,
+            span: self.token_span,
+            start: self.token_start,
+            end: self.pos,
+        })
     }
 
     pub fn next_token(&mut self) -> Result<Token> {
-        self.skip_whitespace();
+        self.skip_whitespace()?;
+        self.token_span = self.span();
+        self.token_start = self.pos;
 
         let tok = match self.ch {
             '=' => {
@@ -84,7 +272,7 @@ impl Lexer {
                     self.read_char();
                     Token::NotEq
                 } else {
-                    return Err(anyhow::anyhow!("Carácter no válido después de !"));
+                    Token::Bang
                 }
             }
             '<' => {
@@ -105,10 +293,33 @@ impl Lexer {
                     Token::Gt
                 }
             }
+            '&' => {
+                self.read_char();
+                if self.ch == '&' {
+                    self.read_char();
+                    Token::AndAnd
+                } else {
+                    return Err(anyhow::anyhow!("Carácter no válido: & (¿quisiste decir &&?)"));
+                }
+            }
+            '|' => {
+                self.read_char();
+                if self.ch == '|' {
+                    self.read_char();
+                    Token::OrOr
+                } else {
+                    return Err(anyhow::anyhow!("Carácter no válido: | (¿quisiste decir ||?)"));
+                }
+            }
             '+' => {
                 self.read_char();
                 Token::Plus
             }
+            '-' if self.peek_char() == '>' => {
+                self.read_char();
+                self.read_char();
+                Token::Arrow
+            }
             '-' => {
                 self.read_char();
                 Token::Minus
@@ -121,6 +332,10 @@ impl Lexer {
                 self.read_char();
                 Token::Slash
             }
+            '%' => {
+                self.read_char();
+                Token::Percent
+            }
             ';' => {
                 self.read_char();
                 Token::Semicolon
@@ -133,6 +348,24 @@ impl Lexer {
                 self.read_char();
                 Token::Colon
             }
+            '.' if self.peek_char() == '.' && self.input.get(self.pos + 2) == Some(&'.') => {
+                self.read_char();
+                self.read_char();
+                self.read_char();
+                Token::Ellipsis
+            }
+            '.' => {
+                self.read_char();
+                Token::Dot
+            }
+            '@' => {
+                self.read_char();
+                Token::At
+            }
+            '?' => {
+                self.read_char();
+                Token::Question
+            }
             '(' => {
                 self.read_char();
                 Token::LParen
@@ -158,33 +391,132 @@ impl Lexer {
                 Token::RBracket
             }
             '"' => {
-                Token::String(self.read_string())
+                Token::String(self.read_string()?)
             }
-            '\0' => Token::Eof,
+            '\'' => Token::Char(self.read_char_literal()?),
+            '\0' if self.at_eof() => Token::Eof,
             c if c.is_alphabetic() || c == '_' => {
                 let ident = self.read_identifier();
                 match ident.as_str() {
                     "let" => Token::Let,
+                    "const" => Token::Const,
                     "fn" => Token::Fn,
                     "if" => Token::If,
                     "else" => Token::Else,
                     "while" => Token::While,
                     "for" => Token::For,
                     "return" => Token::Return,
+                    "new" => Token::New,
+                    "enum" => Token::Enum,
                     "true" => Token::True,
                     "false" => Token::False,
                     "print" => Token::Print,
                     "int" => Token::Ident("int".to_string()),
+                    "float" => Token::Ident("float".to_string()),
                     "bool" => Token::Ident("bool".to_string()),
                     "string" => Token::Ident("string".to_string()),
+                    "char" => Token::Ident("char".to_string()),
                     "void" => Token::Ident("void".to_string()),
                     _ => Token::Ident(ident),
                 }
             }
-            c if c.is_digit(10) => Token::Number(self.read_number()),
-            _ => return Err(anyhow::anyhow!("Carácter no válido: {}", c)),
+            c if c.is_ascii_digit() => self.read_number()?,
+            c => return Err(anyhow::anyhow!("Carácter no válido: {}", c)),
         };
 
         Ok(tok)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Lexer;
+    use crate::lexer::token::Token;
+    use crate::testing::parse;
+
+    fn lex_error(source: &str) -> String {
+        let mut lexer = Lexer::new(source.to_string());
+        loop {
+            match lexer.next_token() {
+                Ok(Token::Eof) => panic!("sin error: {:?}", source),
+                Ok(_) => {}
+                Err(err) => return err.to_string(),
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let cases = [
+            ("\"abc", "Cadena sin cerrar"),
+            ("\"abc\\", "Cadena sin cerrar"),
+            ("\"\\q\"", "Secuencia de escape desconocida"),
+            ("''", "Literal de carácter vacío"),
+            ("'ab'", "solo puede tener un carácter"),
+            ("'a", "Literal de carácter sin cerrar"),
+            ("'ñ'", "Solo se admiten caracteres ASCII"),
+            ("/* a /* b */", "comentario de bloque sin cerrar"),
+            ("99999999999999999999", "literal entero fuera de rango"),
+            ("a & b", "¿quisiste decir &&?"),
+            ("a | b", "¿quisiste decir ||?"),
+            ("#", "Carácter no válido"),
+        ];
+        for (source, message) in cases {
+            let error = lex_error(source);
+            assert!(error.contains(message), "{:?}: {}", source, error);
+        }
+    }
+
+    // Entradas pseudoaleatorias (xorshift con semilla fija): el lexer y el
+    // parser deben terminar con un resultado o un error, nunca con un pánico
+    #[test]
+    fn random_input_never_panics() {
+        const ALPHABET: &[char] = &[
+            'a', 'z', '_', '0', '9', '.', '"', '\'', '\\', '/', '*', '+', '-', '=', '!', '<', '>', '&', '|',
+            '(', ')', '[', ']', '{', '}', ',', ';', ':', '?', '@', '#', ' ', '\n', '\t', '\0', 'ñ', '€',
+        ];
+        const KEYWORDS: &[&str] = &["fn ", "let ", "if ", "else ", "while ", "return ", "new ", "..."];
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..random(60) {
+                if random(8) == 0 {
+                    source.push_str(KEYWORDS[random(KEYWORDS.len())]);
+                } else {
+                    source.push(ALPHABET[random(ALPHABET.len())]);
+                }
+            }
+            // Cada token consume al menos un carácter
+            let mut lexer = Lexer::new(source.clone());
+            let finished = (0..=source.len()).any(|_| matches!(lexer.next_token(), Ok(Token::Eof) | Err(_)));
+            assert!(finished, "{:?}", source);
+            let _ = parse(&source);
+        }
+    }
+
+    // Columna del primer token que lee `lexer`
+    fn first_column(mut lexer: Lexer) -> usize {
+        lexer.next_lexed().unwrap().span.column
+    }
+
+    #[test]
+    fn tabs_advance_to_the_next_tab_stop() {
+        let column = |source: &str| first_column(Lexer::new(source.to_string()));
+        assert_eq!(column("\tx"), 9);
+        assert_eq!(column("\t\tx"), 17);
+        // Un tabulador a mitad de parada solo llega hasta ella
+        assert_eq!(column("/**/\tx"), 9);
+
+        let column = |source: &str| first_column(Lexer::new(source.to_string()).with_tab_width(4));
+        assert_eq!(column("\tx"), 5);
+        assert_eq!(column("\t\tx"), 9);
+        assert_eq!(column("/**/\tx"), 9);
+        assert_eq!(column("  \tx"), 5);
+    }
+}
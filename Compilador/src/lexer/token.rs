@@ -2,25 +2,37 @@
 pub enum Token {
     // Existentes
     Let,
+    Const,
     Ident(String),
     Number(i64),
+    Float(f64),
     String(String),
+    Char(char),
     True,
     False,
     Plus,
     Minus,
+    Arrow,
     Star,
     Slash,
+    Percent,
     Eq,
     EqEq,
     NotEq,
+    Bang,
     Lt,
     Gt,
     LtEq,
     GtEq,
+    AndAnd,
+    OrOr,
     Semicolon,
     Comma,
     Colon,
+    Dot,
+    Ellipsis,
+    Question,
+    At,
     Print,
     LParen,
     RParen,
@@ -35,8 +47,28 @@ pub enum Token {
     While,
     For,
     Fn,
+    Enum,
     Return,
-    Array,
+    New,
     
     Eof,
 }
+
+// Posición en el fuente, ambas desde 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span { line: 1, column: 1 }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "línea {}, columna {}", self.line, self.column)
+    }
+}
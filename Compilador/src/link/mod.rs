@@ -0,0 +1,72 @@
+use anyhow::{bail, Result};
+use std::env;
+use std::process::Command;
+use target_lexicon::{Architecture, OperatingSystem};
+
+// Herramientas externas; se pueden cambiar con estas variables de entorno
+const ASSEMBLER_VAR: &str = "COMPILADOR_ASM";
+const LINKER_VAR: &str = "COMPILADOR_CC";
+
+// Ensambla el código generado y lo enlaza con el runtime en un ejecutable.
+// Devuelve la ruta del ejecutable.
+//
+// El backend ARM64 genera sintaxis de GNU as, que el compilador de C
+// ensambla directamente; los de x86-64 generan NASM. En Linux el punto de
+// entrada es nuestro `_start`, así que no se enlazan los de la libc.
+pub fn assemble_and_link(
+    asm_file: &str,
+    runtime_file: &str,
+    output_file: &str,
+    architecture: Architecture,
+    os: OperatingSystem,
+    pie: bool,
+) -> Result<String> {
+    let linker = tool(LINKER_VAR, "cc");
+    let is_windows = os == OperatingSystem::Windows;
+    let executable = if is_windows {
+        format!("{}.exe", output_file)
+    } else {
+        output_file.to_string()
+    };
+
+    let mut link = Command::new(&linker);
+    if matches!(architecture, Architecture::Aarch64(_)) {
+        link.arg(asm_file);
+    } else {
+        let assembler = tool(ASSEMBLER_VAR, "nasm");
+        let (format, object_file) = if is_windows {
+            ("win64", format!("{}.obj", output_file))
+        } else {
+            ("elf64", format!("{}.o", output_file))
+        };
+        run(
+            Command::new(&assembler).args(["-f", format, asm_file, "-o", &object_file]),
+            &assembler,
+            ASSEMBLER_VAR,
+        )?;
+        link.arg(object_file);
+    }
+    if !is_windows {
+        link.arg("-nostartfiles");
+        link.arg(if pie { "-pie" } else { "-no-pie" });
+    }
+    link.args([runtime_file, "-o", &executable]);
+    run(&mut link, &linker, LINKER_VAR)?;
+
+    Ok(executable)
+}
+
+fn tool(variable: &str, default: &str) -> String {
+    env::var(variable).unwrap_or_else(|_| default.to_string())
+}
+
+fn run(command: &mut Command, tool: &str, variable: &str) -> Result<()> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("'{}' terminó con error ({})", tool, status),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            bail!("No se encontró '{}': instálalo o indica otro con la variable {}", tool, variable)
+        }
+        Err(error) => bail!("No se pudo ejecutar '{}': {}", tool, error),
+    }
+}
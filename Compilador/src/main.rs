@@ -5,65 +5,505 @@ mod ir;
 mod optimizer;
 mod codegen;
 mod runtime;
+mod link;
 mod types;
+#[cfg(test)]
+mod testing;
 
 use crate::lexer::Lexer;
 use crate::parser::Parser;
+use crate::parser::incremental::IncrementalParser;
+use crate::parser::printer::format_program;
 use crate::semantic::SemanticAnalyzer;
+use crate::semantic::complexity::function_complexity;
 use crate::ir::builder::IRBuilder;
-use crate::optimizer::Optimizer;
+use crate::ir::ssa::to_ssa;
+use crate::optimizer::{OptLevel, Optimizer};
 use crate::codegen::generate_code;
 use crate::runtime::generate_runtime;
-use target_lexicon::HOST;
+use crate::link::assemble_and_link;
+use target_lexicon::{Aarch64Architecture, Architecture, OperatingSystem, HOST};
 use std::env;
 use std::fs;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Options {
+    source_file: String,
+    output_file: String,
+    no_shadow: bool,
+    warn_shadow: bool,
+    truthy: bool,
+    fmt: bool,
+    // Sesión de edición para un editor (ver edit_session)
+    incremental: bool,
+    emit_both: bool,
+    // Sistema para el que se genera código; por defecto, el anfitrión
+    target: Option<(Architecture, OperatingSystem)>,
+    complexity: bool,
+    time_passes: bool,
+    emit_ir: bool,
+    pie: bool,
+    emit_ir_before_opt: bool,
+    emit_ssa: bool,
+    // Ensamblar y enlazar para obtener un ejecutable
+    link: bool,
+    opt_level: OptLevel,
+    // Ancho del tabulador al calcular columnas; por defecto, el del lexer
+    tab_width: Option<usize>,
+    max_depth: Option<usize>,
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "--no-shadow" => options.no_shadow = true,
+            "--warn-shadow" => options.warn_shadow = true,
+            "--truthy" => options.truthy = true,
+            "--fmt" => options.fmt = true,
+            "--incremental" => options.incremental = true,
+            "--emit-both" => options.emit_both = true,
+            "--complexity" => options.complexity = true,
+            "--time-passes" => options.time_passes = true,
+            "--emit-ir" | "--emit-ir-after-opt" => options.emit_ir = true,
+            "--emit-ir-before-opt" => options.emit_ir_before_opt = true,
+            "--emit-ssa" => options.emit_ssa = true,
+            "--link" => options.link = true,
+            "--pie" => options.pie = true,
+            "-O0" => options.opt_level = OptLevel::O0,
+            "-O1" => options.opt_level = OptLevel::O1,
+            "-O2" => options.opt_level = OptLevel::O2,
+            flag if flag.starts_with("--target=") => {
+                options.target = Some(parse_target(&flag["--target=".len()..])?);
+            }
+            flag if flag.starts_with("--tab-width=") => {
+                let value = &flag["--tab-width=".len()..];
+                match value.parse::<usize>() {
+                    Ok(width) if width > 0 => options.tab_width = Some(width),
+                    _ => {
+                        eprintln!("Ancho de tabulador no válido: {}", value);
+                        return None;
+                    }
+                }
+            }
+            flag if flag.starts_with("--max-depth=") => {
+                let value = &flag["--max-depth=".len()..];
+                match value.parse::<usize>() {
+                    Ok(depth) if depth > 0 => options.max_depth = Some(depth),
+                    _ => {
+                        eprintln!("Profundidad máxima no válida: {}", value);
+                        return None;
+                    }
+                }
+            }
+            flag if flag.starts_with('-') => {
+                eprintln!("Opción desconocida: {}", flag);
+                return None;
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if options.emit_both && options.target.is_some() {
+        eprintln!("--target y --emit-both no se pueden combinar");
+        return None;
+    }
+
+    if options.emit_both && options.link {
+        eprintln!("--link y --emit-both no se pueden combinar");
+        return None;
+    }
+
+    // En modo --fmt, --complexity e --incremental no hay archivo de salida
+    let source_only = options.fmt || options.complexity || options.incremental;
+    let expected = if source_only { 1 } else { 2 };
+    if positional.len() != expected {
+        return None;
+    }
+
+    if !source_only {
+        options.output_file = positional.pop()?;
+    }
+    options.source_file = positional.pop()?;
+    Some(options)
+}
+
+fn parse_target(name: &str) -> Option<(Architecture, OperatingSystem)> {
+    match name {
+        "linux" => Some((Architecture::X86_64, OperatingSystem::Linux)),
+        "windows" => Some((Architecture::X86_64, OperatingSystem::Windows)),
+        "aarch64-linux" => Some((Architecture::Aarch64(Aarch64Architecture::Aarch64), OperatingSystem::Linux)),
+        _ => {
+            eprintln!("Destino desconocido: {} (valores aceptados: linux, windows, aarch64-linux)", name);
+            None
+        }
+    }
+}
+
+// Pila del hilo que compila. El parser limita el anidamiento, pero en una
+// compilación debug las fases recursivas necesitan varios megas para un
+// fuente en el límite, más de lo que da el hilo principal en Windows.
+const COMPILER_STACK_SIZE: usize = 32 * 1024 * 1024;
 
 fn main() -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .stack_size(COMPILER_STACK_SIZE)
+        .spawn(run)?
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+fn run() -> anyhow::Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        eprintln!("Uso: {} <archivo_fuente> <archivo_salida>", args[0]);
-        std::process::exit(1);
-    }
+    let options = match parse_args(&args) {
+        Some(options) => options,
+        None => {
+            eprintln!("Uso: {} [-O0|-O1|-O2] [--no-shadow] [--warn-shadow] [--truthy] [--target=linux|windows|aarch64-linux] [--emit-both] [--pie] [--link] [--emit-ir] [--emit-ir-before-opt] [--emit-ssa] [--time-passes] [--tab-width=N] [--max-depth=N] <archivo_fuente> <archivo_salida>", args[0]);
+            eprintln!("     {} --fmt <archivo_fuente>", args[0]);
+            eprintln!("     {} --complexity <archivo_fuente>", args[0]);
+            eprintln!("     {} --incremental <archivo_fuente>", args[0]);
+            std::process::exit(1);
+        }
+    };
+    compile(&options)
+}
 
-    let source_file = &args[1];
-    let output_file = &args[2];
-    
+// Compila según `options`, como una invocación desde la línea de órdenes
+fn compile(options: &Options) -> anyhow::Result<()> {
     // Leer código fuente
-    let source_code = fs::read_to_string(source_file)?;
+    let source_code = fs::read_to_string(&options.source_file)?;
     
-    // Etapa 1: Lexical Analysis
-    let lexer = Lexer::new(source_code);
+    if options.incremental {
+        let analyzer = || {
+            SemanticAnalyzer::new()
+                .with_shadowing(!options.no_shadow)
+                .with_shadow_warnings(options.warn_shadow)
+                .with_truthiness(options.truthy)
+        };
+        return edit_session(source_code, std::io::stdin().lock(), &mut std::io::stdout(), analyzer);
+    }
     
-    // Etapa 2: Parsing
-    let mut parser = Parser::new(lexer);
+    // Duración de cada etapa, para --time-passes
+    let mut timings = Vec::new();
+    compile_source(options, source_code, &mut timings)?;
+    if options.time_passes {
+        eprint!("{}", timing_report(&timings));
+    }
+    Ok(())
+}
+
+// Compila el fuente ya leído, anotando en `timings` lo que tarda cada etapa
+fn compile_source(options: &Options, source_code: String, timings: &mut Vec<(String, Duration)>) -> anyhow::Result<()> {
+    let output_file = &options.output_file;
+
+    // Etapas 1 y 2: el parser pide los tokens al lexer a medida que los
+    // necesita, así que se miden juntas
+    let start = Instant::now();
+    let mut lexer = Lexer::new(source_code);
+    if let Some(tab_width) = options.tab_width {
+        lexer = lexer.with_tab_width(tab_width);
+    }
+    let mut parser = Parser::new(lexer)?;
+    if let Some(max_depth) = options.max_depth {
+        parser = parser.with_max_depth(max_depth);
+    }
     let program = parser.parse_program()?;
+    timings.push(("análisis léxico y sintáctico".to_string(), start.elapsed()));
+
+    if options.fmt {
+        print!("{}", format_program(&program));
+        return Ok(());
+    }
+
+    if options.complexity {
+        for (name, complexity) in function_complexity(&program) {
+            println!("{}: {}", name, complexity);
+        }
+        return Ok(());
+    }
     
     // Etapa 3: Semantic Analysis
-    let mut semantic_analyzer = SemanticAnalyzer::new();
+    let start = Instant::now();
+    let mut semantic_analyzer = SemanticAnalyzer::new()
+        .with_shadowing(!options.no_shadow)
+        .with_shadow_warnings(options.warn_shadow)
+        .with_truthiness(options.truthy);
     semantic_analyzer.analyze(&program)?;
+    timings.push(("análisis semántico".to_string(), start.elapsed()));
+    for warning in semantic_analyzer.warnings() {
+        eprintln!("Advertencia: {}", warning);
+    }
     
     // Etapa 4: IR Generation
-    let mut ir_builder = IRBuilder::new();
-    let mut ir_program = ir_builder.build(&program);
+    let start = Instant::now();
+    let mut ir_builder =
+        IRBuilder::new().with_expression_types(semantic_analyzer.expression_types().clone());
+    let mut ir_program = ir_builder.build(&program)?;
+    timings.push(("generación de IR".to_string(), start.elapsed()));
+    
+    // El IR en texto se escribe junto a la salida, antes y/o después de
+    // optimizar, para revisar los pases a ojo
+    let mut generated = Vec::new();
+    if options.emit_ir_before_opt {
+        let ir_file = format!("{}.before.ir", output_file);
+        fs::write(&ir_file, ir_program.to_string())?;
+        generated.push(format!("  - {} (IR sin optimizar)", ir_file));
+    }
     
     // Etapa 5: Optimization
-    let mut optimizer = Optimizer::new();
+    let mut optimizer = Optimizer::with_level(options.opt_level);
     optimizer.optimize(&mut ir_program);
+    for (pass, duration) in optimizer.pass_times() {
+        timings.push((format!("optimización: {}", pass), *duration));
+    }
     
-    // Etapa 6: Code Generation
-    let asm_code = generate_code(ir_program, HOST.operating_system);
-    
-    // Etapa 7: Runtime Generation
-    let runtime_code = generate_runtime(HOST.operating_system);
+    if options.emit_ir {
+        let ir_file = format!("{}.ir", output_file);
+        fs::write(&ir_file, ir_program.to_string())?;
+        generated.push(format!("  - {} (IR optimizado)", ir_file));
+    }
+
+    if options.emit_ssa {
+        let ssa_file = format!("{}.ssa", output_file);
+        let functions: Vec<String> =
+            ir_program.functions.iter().map(|function| to_ssa(function).to_string()).collect();
+        fs::write(&ssa_file, functions.join("\n"))?;
+        generated.push(format!("  - {} (IR en forma SSA)", ssa_file));
+    }
     
-    // Escribir archivos de salida
-    fs::write(format!("{}.s", output_file), asm_code)?;
-    fs::write(format!("{}_runtime.c", output_file), runtime_code)?;
+    // Con --emit-both se generan los dos backends, sin importar el anfitrión
+    let targets = if options.emit_both {
+        vec![
+            (".unix", Architecture::X86_64, OperatingSystem::Linux),
+            (".windows", Architecture::X86_64, OperatingSystem::Windows),
+        ]
+    } else {
+        let (architecture, os) = options.target.unwrap_or((HOST.architecture, HOST.operating_system));
+        vec![("", architecture, os)]
+    };
+
+    for (suffix, architecture, os) in targets {
+        // Etapa 6: Code Generation
+        let start = Instant::now();
+        let asm_code = generate_code(ir_program.clone(), architecture, os, options.opt_level, options.pie);
+        let stage = match suffix.strip_prefix('.') {
+            Some(target) => format!("generación de código ({})", target),
+            None => "generación de código".to_string(),
+        };
+        timings.push((stage, start.elapsed()));
+        
+        // Etapa 7: Runtime Generation
+        let runtime_code = generate_runtime(os);
+        
+        // Escribir archivos de salida
+        let asm_file = format!("{}{}.s", output_file, suffix);
+        let runtime_file = format!("{}{}_runtime.c", output_file, suffix);
+        fs::write(&asm_file, asm_code)?;
+        fs::write(&runtime_file, runtime_code)?;
+        generated.push(format!("  - {} (código ensamblador)", asm_file));
+        generated.push(format!("  - {} (runtime)", runtime_file));
+
+        if options.link {
+            let executable = assemble_and_link(&asm_file, &runtime_file, output_file, architecture, os, options.pie)?;
+            generated.push(format!("  - {} (ejecutable)", executable));
+        }
+    }
     
     println!("Compilación completada!");
     println!("Archivos generados:");
-    println!("  - {}.s (código ensamblador)", output_file);
-    println!("  - {}_runtime.c (runtime)", output_file);
+    for file in generated {
+        println!("{}", file);
+    }
     
     Ok(())
 }
+
+fn timing_report(timings: &[(String, Duration)]) -> String {
+    let width = timings.iter().map(|(stage, _)| stage.chars().count()).max().unwrap_or(0);
+    let total: Duration = timings.iter().map(|(_, duration)| *duration).sum();
+    let mut report = String::from("Tiempo por etapa:\n");
+    let mut line = |stage: &str, duration: Duration| {
+        report += &format!("  {:<width$}  {:>10.3} ms\n", stage, duration.as_secs_f64() * 1000.0, width = width);
+    };
+    for (stage, duration) in timings {
+        line(stage, *duration);
+    }
+    line("total", total);
+    report
+}
+
+// --incremental: sesión para un editor que recompila en cada pulsación.
+// Cada línea de la entrada es una edición `línea:columna línea:columna
+// texto`, con posiciones desde 1 (la columna en caracteres) y `\n` para
+// los saltos de línea del texto. Por cada una se responde `ok` con los
+// tokens y sentencias que se han vuelto a analizar, o con el primer error
+// del fuente editado.
+fn edit_session(
+    source: String,
+    input: impl BufRead,
+    output: &mut impl Write,
+    analyzer: impl Fn() -> SemanticAnalyzer,
+) -> anyhow::Result<()> {
+    let mut parser = IncrementalParser::new(source)?;
+    for line in input.lines() {
+        let line = line?;
+        let reply = match parse_edit(parser.source(), &line) {
+            None => format!("error: edición no válida: {}", line),
+            Some((range, text)) => match parser.edit(range, &text) {
+                Err(err) => format!("error: {}", err),
+                Ok(reanalysis) => {
+                    let program = parser.program().expect("análisis sin errores");
+                    match analyzer().analyze(&program) {
+                        Ok(()) => format!("ok {} {}", reanalysis.relexed_tokens, reanalysis.reparsed_items),
+                        Err(err) => format!("error: {}", err),
+                    }
+                }
+            },
+        };
+        writeln!(output, "{}", reply)?;
+    }
+    Ok(())
+}
+
+fn parse_edit(source: &str, line: &str) -> Option<(Range<usize>, String)> {
+    let mut parts = line.splitn(3, ' ');
+    let start = char_offset(source, parts.next()?)?;
+    let end = char_offset(source, parts.next()?)?;
+    let text = parts.next().unwrap_or("").replace("\\n", "\n");
+    Some((start..end, text))
+}
+
+// Carácter del fuente en `línea:columna`; la columna puede ser la que
+// sigue al último carácter de la línea
+fn char_offset(source: &str, position: &str) -> Option<usize> {
+    let (line, column) = position.split_once(':')?;
+    let (line, column): (usize, usize) = (line.parse().ok()?, column.parse().ok()?);
+    let mut offset = 0;
+    for (index, text) in source.split('\n').enumerate() {
+        let length = text.chars().count();
+        if index + 1 == line {
+            return (1..=length + 1).contains(&column).then(|| offset + column - 1);
+        }
+        offset += length + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{char_offset, compile, compile_source, edit_session, parse_args, timing_report};
+    use std::fs;
+    use crate::semantic::SemanticAnalyzer;
+
+    const SOURCE: &str = "fn twice(x: int) -> int {\n    let y = x * 2;\n    return y;\n}\n\
+                          fn main() -> int { return twice(2); }\n";
+
+    fn session(edits: &str) -> Vec<String> {
+        let mut output = Vec::new();
+        edit_session(SOURCE.to_string(), edits.as_bytes(), &mut output, SemanticAnalyzer::new).unwrap();
+        String::from_utf8(output).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn positions_are_lines_and_columns_from_one() {
+        assert_eq!(char_offset(SOURCE, "1:1"), Some(0));
+        assert_eq!(char_offset(SOURCE, "2:13"), SOURCE.find("x * 2"));
+        assert_eq!(char_offset(SOURCE, "1:26"), Some(25));
+        assert_eq!(char_offset(SOURCE, "1:27"), None);
+        assert_eq!(char_offset(SOURCE, "9:1"), None);
+        assert_eq!(char_offset(SOURCE, "0:1"), None);
+        assert_eq!(char_offset(SOURCE, "2"), None);
+    }
+
+    // Cambiar el cuerpo de una función solo vuelve a analizar esa función;
+    // los errores se responden y la sesión sigue con el fuente editado
+    #[test]
+    fn each_edit_reports_what_was_reanalyzed_or_the_first_error() {
+        let replies = session(
+            "2:13 2:18 x * 3 + 1\n\
+             2:13 2:14 zz\n\
+             2:13 2:15 x\n\
+             3:5 3:5 return (\n\
+             3:5 3:13 \n\
+             9:1 9:1 a\n\
+             5:1 5:1 fn one() -> int { return 1; }\\n\n",
+        );
+        assert_eq!(replies.len(), 7);
+        assert_eq!(replies[0], "ok 8 1");
+        assert!(replies[1].contains("Variable 'zz' no declarada"), "{}", replies[1]);
+        assert_eq!(replies[2], "ok 8 1");
+        assert!(replies[3].starts_with("error: error en línea 3"), "{}", replies[3]);
+        assert!(replies[4].starts_with("ok "), "{}", replies[4]);
+        assert_eq!(replies[5], "error: edición no válida: 9:1 9:1 a");
+        assert!(replies[6].starts_with("ok ") && replies[6].ends_with(" 2"), "{}", replies[6]);
+    }
+
+    #[test]
+    fn emit_both_writes_the_unix_and_windows_outputs() {
+        let dir = std::env::temp_dir().join(format!("compilador_emit_both_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::write(path("prog.src"), "fn main() { print(1); }").unwrap();
+
+        let args: Vec<String> = ["compilador", "--emit-both", &path("prog.src"), &path("prog")]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        compile(&parse_args(&args).unwrap()).unwrap();
+        let read = |name: &str| fs::read_to_string(path(name)).unwrap();
+        let (unix, windows) = (read("prog.unix.s"), read("prog.windows.s"));
+        let runtimes = (read("prog.unix_runtime.c"), read("prog.windows_runtime.c"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_ne!(unix, windows);
+        assert!(unix.contains("global _start") && unix.contains("\n_start:\n"));
+        // En Windows el punto de entrada es el main del enlazador, que llama al del programa
+        assert!(windows.contains("global main\n") && windows.contains("\nmain_func:\n"));
+        assert!(!windows.contains("_start"));
+        assert_ne!(runtimes.0, runtimes.1);
+    }
+
+    #[test]
+    fn time_passes_reports_every_stage() {
+        let dir = std::env::temp_dir().join(format!("compilador_time_passes_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("prog").to_string_lossy().into_owned();
+        let args: Vec<String> = ["compilador", "-O2", "--emit-both", "--time-passes", "prog.src", &output]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let options = parse_args(&args).unwrap();
+        assert!(options.time_passes);
+
+        let mut timings = Vec::new();
+        let source = "fn main() -> int { let x = 1; if (x < 2) { print(x); } return 0; }";
+        compile_source(&options, source.to_string(), &mut timings).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let report = timing_report(&timings);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "Tiempo por etapa:");
+        let stages: Vec<&str> = lines[1..].iter().map(|line| line.split("  ").nth(1).unwrap().trim()).collect();
+        for stage in [
+            "análisis léxico y sintáctico",
+            "análisis semántico",
+            "generación de IR",
+            "optimización: inlining",
+            "optimización: propagación de constantes",
+            "optimización: subexpresiones comunes",
+            "optimización: optimización de bucles",
+            "optimización: arrays constantes",
+            "generación de código (unix)",
+            "generación de código (windows)",
+        ] {
+            assert!(stages.contains(&stage), "falta {}:\n{}", stage, report);
+        }
+        assert_eq!(stages.last(), Some(&"total"));
+        assert!(lines[1..].iter().all(|line| line.ends_with(" ms")));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::ir::{IRFunction, IROp, IRValue};
+
+// `a && b` y `a || b` se construyen con un salto sobre el operando derecho:
+//
+//     r = a
+//     if a == 0 goto fin      (|| salta si a != 0)
+//     ...cálculo de b...
+//     r = b
+//   fin:
+//
+// Si calcular b no tiene efectos ni puede fallar no hay nada que saltarse.
+// El análisis semántico exige operandos booleanos, que valen 0 o 1, así
+// que basta con `r = a & b` o `r = a | b`.
+pub fn remove_logical_branches(function: &mut IRFunction) {
+    // Tras reescribir un operador interno el externo puede quedar sin
+    // saltos en su operando derecho
+    while let Some(candidate) = find_candidate(function) {
+        let ShortCircuit { start, end, is_and, result, left, right } = candidate;
+        let mut replacement: Vec<IROp> = function.instructions[start + 2..end - 1].to_vec();
+        replacement.push(if is_and {
+            IROp::And(result, left, right)
+        } else {
+            IROp::Or(result, left, right)
+        });
+        function.instructions.splice(start..=end, replacement);
+    }
+}
+
+// Un ternario entre dos valores ya calculados, o un if que asigna a la
+// misma variable en las dos ramas, queda como
+//
+//     if c == 0 goto else
+//     r = a
+//     goto fin
+//   else:
+//     r = b
+//   fin:
+//
+// y pasa a `r = c ? a : b`, que se emite con cmov (csel en ARM64) en vez
+// de saltos. Si alguna rama calcula algo más se dejan los saltos.
+pub fn select_simple_ternaries(function: &mut IRFunction) {
+    let mut start = 0;
+    while start + 6 <= function.instructions.len() {
+        if let Some(select) = simple_ternary(&function.instructions, start) {
+            function.instructions.splice(start..start + 6, [select]);
+        }
+        start += 1;
+    }
+}
+
+fn simple_ternary(instructions: &[IROp], start: usize) -> Option<IROp> {
+    let [IROp::JumpIfZero(cond, else_label), IROp::Assign(result, then_value), IROp::Jump(end_label),
+         IROp::Label(else_name), IROp::Assign(target, else_value), IROp::Label(end_name)] = &instructions[start..start + 6]
+    else {
+        return None;
+    };
+    if else_name != else_label || end_name != end_label || target != result {
+        return None;
+    }
+    // Las dos etiquetas desaparecen: solo pueden llegarles estos saltos
+    (jumps_to(instructions, else_label) == 1 && jumps_to(instructions, end_label) == 1)
+        .then(|| IROp::Select(result.clone(), cond.clone(), then_value.clone(), else_value.clone()))
+}
+
+fn jumps_to(instructions: &[IROp], label: &str) -> usize {
+    instructions
+        .iter()
+        .filter(|instr| match instr {
+            IROp::Jump(target) | IROp::JumpIfZero(_, target) | IROp::JumpIfNotZero(_, target) => target == label,
+            _ => false,
+        })
+        .count()
+}
+
+// Un cortocircuito reconocido: `r = a` en `start` y la etiqueta final en `end`
+struct ShortCircuit {
+    start: usize,
+    end: usize,
+    is_and: bool,
+    result: IRValue,
+    left: IRValue,
+    right: IRValue,
+}
+
+// Primer cortocircuito cuyo operando derecho se puede calcular siempre
+fn find_candidate(function: &IRFunction) -> Option<ShortCircuit> {
+    let instructions = &function.instructions;
+    (0..instructions.len()).find_map(|start| {
+        let (IROp::Assign(result @ IRValue::Temp(_), left), Some(jump)) =
+            (&instructions[start], instructions.get(start + 1))
+        else {
+            return None;
+        };
+        let (is_and, cond, label) = match jump {
+            IROp::JumpIfZero(cond, label) => (true, cond, label),
+            IROp::JumpIfNotZero(cond, label) => (false, cond, label),
+            _ => return None,
+        };
+        if cond != left {
+            return None;
+        }
+
+        // El operando derecho acaba en `r = b` justo antes de la etiqueta
+        let end = start + 2 + instructions[start + 2..]
+            .iter()
+            .position(|instr| !can_speculate(instr))?;
+        let (Some(IROp::Label(end_label)), Some(IROp::Assign(target, right))) =
+            (instructions.get(end), instructions.get(end - 1))
+        else {
+            return None;
+        };
+        if end_label != label || target != result {
+            return None;
+        }
+
+        // Nadie más puede saltar a la etiqueta que desaparece
+        (jumps_to(instructions, label) == 1).then(|| ShortCircuit {
+            start,
+            end,
+            is_and,
+            result: result.clone(),
+            left: left.clone(),
+            right: right.clone(),
+        })
+    })
+}
+
+// Instrucciones que se pueden ejecutar aunque el programa original no
+// llegara a ellas: sin efectos laterales y sin posibilidad de fallar, así
+// que ni divisiones (divisor cero) ni accesos a arrays (índice fuera de rango)
+fn can_speculate(instr: &IROp) -> bool {
+    matches!(
+        instr,
+        IROp::Add(..)
+            | IROp::Sub(..)
+            | IROp::Mul(..)
+            | IROp::Shl(..)
+            | IROp::Sar(..)
+            | IROp::Shr(..)
+            | IROp::And(..)
+            | IROp::Or(..)
+            | IROp::CmpEq(..)
+            | IROp::CmpLt(..)
+            | IROp::CmpGt(..)
+            | IROp::CmpLe(..)
+            | IROp::CmpGe(..)
+            | IROp::CmpNe(..)
+            | IROp::Assign(..)
+            | IROp::Select(..)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_simple_ternaries;
+    use crate::ir::{IRFunction, IROp, IRProgram, IRValue, InlineHint};
+    use crate::codegen::unix::generate_unix_asm;
+    use crate::optimizer::{OptLevel, Optimizer};
+    use crate::testing::{build_ir, function, run};
+    use std::collections::HashMap;
+
+    fn optimized(source: &str, level: OptLevel) -> IRProgram {
+        let mut program = build_ir(source);
+        Optimizer::with_level(level).optimize(&mut program);
+        program
+    }
+
+    fn selects(program: &IRProgram) -> usize {
+        function(program, "f").instructions.iter().filter(|instr| matches!(instr, IROp::Select(..))).count()
+    }
+
+    fn has_branches(program: &IRProgram) -> bool {
+        function(program, "f").instructions.iter().any(|instr| matches!(instr, IROp::JumpIfZero(..) | IROp::JumpIfNotZero(..)))
+    }
+
+    #[test]
+    fn ternaries_between_values_become_a_select_at_o2() {
+        let sources = [
+            "fn f(c: int, a: int, b: int) -> int { return c > 0 ? a : b; }",
+            "fn f(c: int, a: int, b: int) -> int { return c > a ? 7 : b; }",
+            "fn f(c: int, a: int, b: int) -> int { let x = 0; if (c > 0) { x = a; } else { x = b; } return x; }",
+        ];
+        for source in sources {
+            let program = optimized(source, OptLevel::O2);
+            assert_eq!(selects(&program), 1, "{}", source);
+            assert!(!has_branches(&program), "{}", source);
+
+            let program = optimized(source, OptLevel::O1);
+            assert_eq!(selects(&program), 0, "{}", source);
+            assert!(has_branches(&program), "{}", source);
+
+            let unoptimized = optimized(source, OptLevel::O0);
+            let program = optimized(source, OptLevel::O2);
+            for args in [[1, 2, 3], [0, 2, 3], [-4, 5, 6], [9, 9, 9]] {
+                assert_eq!(run(&program, "f", &args), run(&unoptimized, "f", &args), "{} con {:?}", source, args);
+            }
+        }
+    }
+
+    // Una rama que calcula algo, aunque se pudiera adelantar, sigue con saltos
+    #[test]
+    fn arms_that_compute_keep_the_branches() {
+        let g = "fn g(x: int) -> int { print(x); return x; } ";
+        for arms in ["a / b : b", "g(a) : b", "a : g(b)", "a + 1 : b", "(a > 0 ? a : b) : b"] {
+            let source = format!("{}fn f(c: int, a: int, b: int) -> int {{ return c > 0 ? {}; }}", g, arms);
+            let program = optimized(&source, OptLevel::O2);
+            assert!(has_branches(&program), "{}", arms);
+            let unoptimized = optimized(&source, OptLevel::O0);
+            for args in [[1, 2, 3], [0, 2, 3], [1, -2, 3]] {
+                assert_eq!(run(&program, "f", &args), run(&unoptimized, "f", &args), "{} con {:?}", arms, args);
+            }
+        }
+    }
+
+    // Las etiquetas desaparecen, así que no puede saltarles nadie más
+    #[test]
+    fn labels_reached_from_elsewhere_keep_the_branches() {
+        let label = |name: &str| IROp::Label(name.to_string());
+        let local = |name: &str| IRValue::Local(name.to_string());
+        let ternary = vec![
+            IROp::JumpIfZero(local("c"), "else".to_string()),
+            IROp::Assign(local("x"), local("a")),
+            IROp::Jump("end".to_string()),
+            label("else"),
+            IROp::Assign(local("x"), local("b")),
+            label("end"),
+            IROp::Return(Some(local("x"))),
+        ];
+        for extra in ["else", "end"] {
+            let mut instructions = vec![IROp::JumpIfNotZero(local("a"), extra.to_string())];
+            instructions.extend(ternary.iter().cloned());
+            let mut function = IRFunction {
+                name: "f".to_string(),
+                params: vec!["c".to_string(), "a".to_string(), "b".to_string()],
+                instructions,
+                locals: HashMap::new(),
+                inline: InlineHint::Auto,
+            };
+            select_simple_ternaries(&mut function);
+            assert_eq!(function.instructions.len(), 8, "{}", extra);
+        }
+
+        let mut function = IRFunction {
+            name: "f".to_string(),
+            params: vec!["c".to_string(), "a".to_string(), "b".to_string()],
+            instructions: ternary,
+            locals: HashMap::new(),
+            inline: InlineHint::Auto,
+        };
+        select_simple_ternaries(&mut function);
+        assert!(matches!(function.instructions[..], [IROp::Select(..), IROp::Return(_)]));
+    }
+
+    fn logical_ops(program: &IRProgram) -> usize {
+        function(program, "f").instructions.iter().filter(|instr| matches!(instr, IROp::And(..) | IROp::Or(..))).count()
+    }
+
+    #[test]
+    fn cheap_logical_operands_need_no_branches_at_o2() {
+        let g = "fn g(x: int) -> bool { print(x); return x > 0; } ";
+        let cases = [
+            ("a > 0 && b > 0", 1),
+            ("a > 0 || b > 0", 1),
+            ("a > 0 && (b > 0 || a < b + 5)", 2),
+            ("a > 0 && g(b)", 0),
+            ("g(a) || g(b)", 0),
+            ("b != 0 && a / b > 1", 0),
+        ];
+        for (condition, operators) in cases {
+            let source = format!("{}fn f(a: int, b: int) -> bool {{ let r = {}; return r; }}", g, condition);
+            let program = optimized(&source, OptLevel::O2);
+            assert_eq!(logical_ops(&program), operators, "{}", condition);
+            assert_eq!(has_branches(&program), operators == 0, "{}", condition);
+            assert_eq!(logical_ops(&optimized(&source, OptLevel::O1)), 0, "{}", condition);
+
+            let unoptimized = optimized(&source, OptLevel::O0);
+            for args in [[1, 2], [0, 2], [3, 0], [-1, -1], [7, 3]] {
+                assert_eq!(run(&program, "f", &args), run(&unoptimized, "f", &args), "{} con {:?}", condition, args);
+            }
+        }
+
+        let program = optimized("fn f(a: bool, b: bool) -> bool { let r = a && b; return r; }", OptLevel::O2);
+        let asm = generate_unix_asm(program, OptLevel::O2, false);
+        let f = &asm[asm.find("\nf:\n").unwrap()..];
+        assert!(f.contains("    and rax, "), "{}", f);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
+use std::collections::HashSet;
+
+// Un literal de array cuyos elementos son todos constantes y que nunca se
+// modifica no necesita reservarse ni rellenarse en ejecución: se emite
+// como datos de solo lectura y el temporal recibe su dirección.
+pub fn place_constant_arrays(program: &mut IRProgram) {
+    for function in &mut program.functions {
+        let groups = constant_literals(function);
+
+        // Si alguna escritura no pertenece a la inicialización de un literal
+        // constante, un array podría modificarse después: no se toca nada
+        let init_writes: usize = groups.iter().map(|(_, values)| values.len()).sum();
+        let all_writes = function
+            .instructions
+            .iter()
+            .filter(|instr| matches!(instr, IROp::ArraySet(..)))
+            .count();
+        if groups.is_empty() || init_writes != all_writes {
+            continue;
+        }
+
+        let starts: HashSet<usize> = groups.iter().map(|(start, _)| *start).collect();
+        let mut instructions = Vec::with_capacity(function.instructions.len());
+        let mut skip = 0;
+        for (i, instr) in function.instructions.drain(..).enumerate() {
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            match instr {
+                IROp::Alloc(array, size) if starts.contains(&i) => {
+                    let label = format!("const_array_{}", program.constant_arrays.len());
+                    let values = groups.iter().find(|(start, _)| *start == i).unwrap().1.clone();
+                    program.constant_arrays.push((label.clone(), values));
+                    instructions.push(IROp::Assign(array, IRValue::Data(label)));
+                    skip = size;
+                }
+                other => instructions.push(other),
+            }
+        }
+        function.instructions = instructions;
+    }
+}
+
+// Posición del Alloc y elementos de cada literal que el builder dejó como
+// Alloc seguido de un ArraySet constante por elemento, en orden
+fn constant_literals(function: &IRFunction) -> Vec<(usize, Vec<i64>)> {
+    let instructions = &function.instructions;
+    let mut groups = Vec::new();
+    for (i, instr) in instructions.iter().enumerate() {
+        let IROp::Alloc(array, size) = instr else {
+            continue;
+        };
+        let values: Option<Vec<i64>> = (0..*size)
+            .map(|index| match instructions.get(i + 1 + index) {
+                Some(IROp::ArraySet(target, IRValue::Const(k), IRValue::Const(value)))
+                    if target == array && *k == index as i64 =>
+                {
+                    Some(*value)
+                }
+                _ => None,
+            })
+            .collect();
+        if let Some(values) = values {
+            groups.push((i, values));
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::place_constant_arrays;
+    use crate::codegen::unix::generate_unix_asm;
+    use crate::ir::{IROp, IRProgram, IRValue};
+    use crate::optimizer::OptLevel;
+    use crate::testing::{build_ir, function};
+
+    fn placed(source: &str) -> IRProgram {
+        let mut program = build_ir(source);
+        place_constant_arrays(&mut program);
+        program
+    }
+
+    fn runtime_arrays(program: &IRProgram) -> usize {
+        function(program, "main")
+            .instructions
+            .iter()
+            .filter(|instr| matches!(instr, IROp::Alloc(..) | IROp::ArraySet(..)))
+            .count()
+    }
+
+    #[test]
+    fn unmodified_constant_literals_go_to_rodata() {
+        let program = placed("fn main() { let a = [1, 2, 3]; print(a[1]); }");
+        assert_eq!(program.constant_arrays, [("const_array_0".to_string(), vec![1, 2, 3])]);
+        assert_eq!(runtime_arrays(&program), 0);
+        assert!(function(&program, "main")
+            .instructions
+            .iter()
+            .any(|instr| matches!(instr, IROp::Assign(_, IRValue::Data(label)) if label == "const_array_0")));
+        // Longitud delante de los elementos, como los arrays del runtime
+        let asm = generate_unix_asm(program, OptLevel::O1, false);
+        assert!(asm.starts_with("section .rodata\nconst_array_0: dq 3,1,2,3\n"), "{}", asm);
+    }
+
+    #[test]
+    fn literals_built_at_runtime_stay_on_the_heap() {
+        // Un elemento que no es constante
+        let program = placed("fn main() { let x = input_int(); let a = [x, 2]; print(a[0]); }");
+        assert!(program.constant_arrays.is_empty());
+        assert_eq!(runtime_arrays(&program), 3);
+        // Otra escritura en la función: por prudencia no se toca ningún array
+        let program = placed(
+            "fn sum(xs: ...int) -> int { return len(xs); } \
+             fn main() { let a = [1, 2]; print(a[0] + sum(input_int())); }",
+        );
+        assert!(program.constant_arrays.is_empty());
+        assert_eq!(runtime_arrays(&program), 5);
+    }
+}
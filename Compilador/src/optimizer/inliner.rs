@@ -0,0 +1,170 @@
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue, InlineHint};
+use std::collections::HashMap;
+
+// Solo se insertan funciones pequeñas llamadas desde pocos sitios,
+// para no multiplicar el tamaño del código.
+const MAX_INLINE_SIZE: usize = 12;
+const MAX_CALL_SITES: usize = 3;
+
+pub fn inline_functions(program: &mut IRProgram) {
+    let mut call_sites: HashMap<String, usize> = HashMap::new();
+    for function in &program.functions {
+        for instr in &function.instructions {
+            if let IROp::Call(name, _, _) = instr {
+                *call_sites.entry(name.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let candidates: HashMap<String, IRFunction> = program
+        .functions
+        .iter()
+        .filter(|f| is_inlinable(f, call_sites.get(&f.name).copied().unwrap_or(0)))
+        .map(|f| (f.name.clone(), f.clone()))
+        .collect();
+
+    let mut counter = 0;
+    for function in &mut program.functions {
+        let mut instructions = Vec::with_capacity(function.instructions.len());
+        for instr in function.instructions.drain(..) {
+            match instr {
+                IROp::Call(name, args, result) if name != function.name && candidates.contains_key(&name) => {
+                    let callee = &candidates[&name];
+                    let suffix = format!(".inl{}", counter);
+                    counter += 1;
+                    inline_call(callee, &args, result, &suffix, &mut instructions, &mut function.locals);
+                }
+                other => instructions.push(other),
+            }
+        }
+        function.instructions = instructions;
+    }
+}
+
+fn is_inlinable(function: &IRFunction, call_sites: usize) -> bool {
+    // @inline se salta los límites de tamaño y de llamadas, @noinline lo impide
+    let within_limits = match function.inline {
+        InlineHint::Always => true,
+        InlineHint::Never => false,
+        InlineHint::Auto => call_sites <= MAX_CALL_SITES && function.instructions.len() <= MAX_INLINE_SIZE,
+    };
+    function.name != "main"
+        && call_sites > 0
+        && within_limits
+        // Las funciones recursivas no se pueden expandir
+        && !function
+            .instructions
+            .iter()
+            .any(|instr| matches!(instr, IROp::Call(name, _, _) if *name == function.name))
+}
+
+// Sustituye la llamada por el cuerpo de `callee`, renombrando locales,
+// temporales y etiquetas con `suffix` para no chocar con las del llamador.
+fn inline_call(
+    callee: &IRFunction,
+    args: &[IRValue],
+    result: Option<IRValue>,
+    suffix: &str,
+    instructions: &mut Vec<IROp>,
+    locals: &mut HashMap<String, IRValue>,
+) {
+    let mut rename_value = |value: &IRValue| match value {
+        IRValue::Local(name) => IRValue::Local(format!("{}{}", name, suffix)),
+        IRValue::Temp(name) => IRValue::Temp(format!("{}{}", name, suffix)),
+        other => other.clone(),
+    };
+    let mut rename_label = |label: &str| format!("{}{}", label, suffix);
+    let end_label = format!("inline_end{}", suffix);
+
+    // Los argumentos se copian a los parámetros renombrados
+    for (param, arg) in callee.params.iter().zip(args) {
+        let param_local = rename_value(&IRValue::Local(param.clone()));
+        if let IRValue::Local(name) = &param_local {
+            locals.insert(name.clone(), param_local.clone());
+        }
+        instructions.push(IROp::Assign(param_local, arg.clone()));
+    }
+    for local in callee.locals.values() {
+        if let IRValue::Local(name) = rename_value(local) {
+            locals.insert(name.clone(), IRValue::Local(name));
+        }
+    }
+
+    for instr in &callee.instructions {
+        match instr.map(&mut rename_value, &mut rename_label) {
+            IROp::Return(value) => {
+                if let (Some(result), Some(value)) = (&result, value) {
+                    instructions.push(IROp::Assign(result.clone(), value));
+                }
+                instructions.push(IROp::Jump(end_label.clone()));
+            }
+            other => instructions.push(other),
+        }
+    }
+    instructions.push(IROp::Label(end_label));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inline_functions;
+    use crate::ir::{IROp, IRProgram};
+    use crate::testing::{build_ir, function};
+
+    fn calls(program: &IRProgram, name: &str) -> Vec<String> {
+        function(program, name)
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Call(callee, _, _) => Some(callee.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn inlined(source: &str) -> IRProgram {
+        let mut program = build_ir(source);
+        inline_functions(&mut program);
+        program
+    }
+
+    #[test]
+    fn small_functions_are_expanded_with_renamed_locals() {
+        let program = inlined(
+            "fn twice(x: int) -> int { let y = x * 2; return y; } \
+             fn main() -> int { return twice(1) + twice(2); }",
+        );
+        assert!(calls(&program, "main").is_empty());
+        let main = function(&program, "main");
+        assert!(main.locals.contains_key("y.inl0") && main.locals.contains_key("y.inl1"));
+        let end_labels = main
+            .instructions
+            .iter()
+            .filter(|instr| matches!(instr, IROp::Label(label) if label.starts_with("inline_end")))
+            .count();
+        assert_eq!(end_labels, 2);
+    }
+
+    #[test]
+    fn recursive_and_noinline_functions_stay_calls() {
+        let program = inlined(
+            "fn fact(n: int) -> int { if (n < 2) { return 1; } return n * fact(n - 1); } \
+             @noinline fn one() -> int { return 1; } \
+             fn main() -> int { return fact(3) + one(); }",
+        );
+        assert_eq!(calls(&program, "main"), ["fact", "one"]);
+    }
+
+    #[test]
+    fn size_and_call_site_limits_unless_marked_inline() {
+        let body = "let a = x + 1; let b = a + 1; let c = b + 1; let d = c + 1; let e = d + 1; \
+                    let f = e + 1; let g = f + 1; return g;";
+        let call_four_times = "fn main() -> int { return one() + one() + one() + one(); }";
+        let big = format!("fn big(x: int) -> int {{ {} }} fn main() -> int {{ return big(1); }}", body);
+        assert_eq!(calls(&inlined(&big), "main"), ["big"]);
+        assert_eq!(calls(&inlined(&format!("@inline {}", big)), "main"), Vec::<String>::new());
+        let popular = format!("fn one() -> int {{ return 1; }} {}", call_four_times);
+        assert_eq!(calls(&inlined(&popular), "main").len(), 4);
+        let forced = format!("@inline fn one() -> int {{ return 1; }} {}", call_four_times);
+        assert!(calls(&inlined(&forced), "main").is_empty());
+    }
+}
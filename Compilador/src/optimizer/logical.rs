@@ -0,0 +1,159 @@
+use crate::ir::{IRFunction, IROp, IRValue};
+use crate::optimizer::constant_value;
+
+// `a && b` y `a || b` (ver el esquema en branchless.rs) con un operando
+// constante. Si el izquierdo decide el resultado, el derecho nunca llega a
+// calcularse y desaparece con sus efectos; si no lo decide, sobra el salto:
+//
+//     true && e  ->  e          false && e  ->  false
+//     false || e ->  e          true || e   ->  true
+//
+// Con la constante a la derecha el izquierdo ya se ha calculado, así que
+// solo cambia el resultado: `a && false` es false, `a || true` es true y
+// en los otros dos casos queda `a`.
+pub fn simplify_constant_logical(function: &mut IRFunction) {
+    // Tras simplificar un operador interno el externo puede quedar con un
+    // operando constante
+    while let Some((range, replacement)) = find_simplification(function) {
+        function.instructions.splice(range, replacement);
+    }
+}
+
+fn find_simplification(function: &IRFunction) -> Option<(std::ops::RangeInclusive<usize>, Vec<IROp>)> {
+    let instructions = &function.instructions;
+    (0..instructions.len()).find_map(|start| {
+        let (IROp::Assign(result @ IRValue::Temp(_), left), Some(jump)) =
+            (&instructions[start], instructions.get(start + 1))
+        else {
+            return None;
+        };
+        // El salto se toma cuando el izquierdo decide: && con 0, || con 1
+        let (decisive, cond, label) = match jump {
+            IROp::JumpIfZero(cond, label) => (0, cond, label),
+            IROp::JumpIfNotZero(cond, label) => (1, cond, label),
+            _ => return None,
+        };
+        if cond != left {
+            return None;
+        }
+
+        let end = start + 2 + instructions[start + 2..]
+            .iter()
+            .position(|instr| matches!(instr, IROp::Label(name) if name == label))?;
+        let IROp::Assign(target, right) = &instructions[end - 1] else {
+            return None;
+        };
+        if target != result {
+            return None;
+        }
+
+        // La etiqueta final desaparece: solo puede saltar a ella este salto
+        if jumps_to(instructions, label).any(|i| i != start + 1) {
+            return None;
+        }
+
+        if let Some(value) = constant_value(function, left) {
+            let replacement = if (value != 0) as i64 == decisive {
+                // Nada de lo que hay entre el salto y la etiqueta se ejecuta;
+                // a sus etiquetas solo se salta desde dentro
+                let reached_from_outside = instructions[start + 2..end].iter().any(|instr| {
+                    matches!(instr, IROp::Label(name) if jumps_to(instructions, name).any(|i| !(start + 2..end).contains(&i)))
+                });
+                if reached_from_outside {
+                    return None;
+                }
+                vec![IROp::Assign(result.clone(), left.clone())]
+            } else {
+                instructions[start + 2..end].to_vec()
+            };
+            return Some((start..=end, replacement));
+        }
+
+        if end - 1 == start + 2 {
+            if let IRValue::Const(value) = right {
+                let replacement = if (*value != 0) as i64 == decisive {
+                    IROp::Assign(result.clone(), right.clone())
+                } else {
+                    IROp::Assign(result.clone(), left.clone())
+                };
+                return Some((start..=end, vec![replacement]));
+            }
+        }
+        None
+    })
+}
+
+// Posiciones de los saltos a una etiqueta
+fn jumps_to<'a>(instructions: &'a [IROp], label: &'a str) -> impl Iterator<Item = usize> + 'a {
+    instructions.iter().enumerate().filter_map(move |(i, instr)| match instr {
+        IROp::Jump(target) | IROp::JumpIfZero(_, target) | IROp::JumpIfNotZero(_, target) if target == label => {
+            Some(i)
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simplify_constant_logical;
+    use crate::ir::{IROp, IRProgram};
+    use crate::testing::{build_ir, function, run};
+
+    fn simplified(source: &str) -> IRProgram {
+        let mut program = build_ir(source);
+        for function in &mut program.functions {
+            simplify_constant_logical(function);
+        }
+        program
+    }
+
+    fn has_branches(program: &IRProgram) -> bool {
+        function(program, "f")
+            .instructions
+            .iter()
+            .any(|instr| matches!(instr, IROp::JumpIfZero(..) | IROp::JumpIfNotZero(..)))
+    }
+
+    #[test]
+    fn constant_left_operand_decides_or_disappears() {
+        let g = "fn g(x: int) -> bool { print(x); return x > 0; } ";
+        let cases = [
+            ("true && g(1)", true, vec![1, 1]),
+            ("false && g(1)", false, vec![0]),
+            ("false || g(0)", true, vec![0, 0]),
+            ("true || g(0)", false, vec![1]),
+            ("(true && false) || g(2)", true, vec![2, 1]),
+        ];
+        for (expr, calls_g, printed) in cases {
+            let source = format!("{}fn f() -> int {{ print({}); return 0; }}", g, expr);
+            let program = simplified(&source);
+            assert!(!has_branches(&program), "{}", expr);
+            let calls = function(&program, "f").instructions.iter().any(|instr| matches!(instr, IROp::Call(..)));
+            assert_eq!(calls, calls_g, "{}", expr);
+            assert_eq!(run(&program, "f", &[]).1, printed, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn constant_right_operand_keeps_the_left_one() {
+        let cases = [
+            ("x > 0 && false", [0, 0]),
+            ("x > 0 || true", [1, 1]),
+            ("x > 0 && true", [1, 0]),
+            ("x > 0 || false", [1, 0]),
+        ];
+        for (expr, printed) in cases {
+            let source = format!("fn f(x: int) -> int {{ print({}); return 0; }}", expr);
+            let program = simplified(&source);
+            assert!(!has_branches(&program), "{}", expr);
+            assert_eq!([run(&program, "f", &[5]).1[0], run(&program, "f", &[-5]).1[0]], printed, "{}", expr);
+        }
+    }
+
+    // Con los dos operandos variables no hay nada que simplificar
+    #[test]
+    fn variable_operands_keep_the_short_circuit() {
+        let program = simplified("fn f(x: int) -> int { print(x > 0 && x < 9); return 0; }");
+        assert!(has_branches(&program));
+    }
+}
@@ -1,92 +1,201 @@
-use crate::ir::{IROp, IRProgram, IRValue};
+mod branchless;
+mod constant_arrays;
+mod inliner;
+mod logical;
+
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
 use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    O0,
+    #[default]
+    O1,
+    O2,
+}
 
 pub struct Optimizer {
-    constant_pool: HashMap<String, i64>,
-    used_variables: HashSet<String>,
+    level: OptLevel,
+    // Tiempo acumulado de cada pase, en orden de primera ejecución
+    pass_times: Vec<(&'static str, Duration)>,
 }
 
 impl Optimizer {
-    pub fn new() -> Self {
+    pub fn with_level(level: OptLevel) -> Self {
         Optimizer {
-            constant_pool: HashMap::new(),
-            used_variables: HashSet::new(),
+            level,
+            pass_times: Vec::new(),
+        }
+    }
+
+    pub fn pass_times(&self) -> &[(&'static str, Duration)] {
+        &self.pass_times
+    }
+
+    fn record_pass(&mut self, pass: &'static str, start: Instant) {
+        let elapsed = start.elapsed();
+        match self.pass_times.iter_mut().find(|(name, _)| *name == pass) {
+            Some((_, total)) => *total += elapsed,
+            None => self.pass_times.push((pass, elapsed)),
         }
     }
 
     pub fn optimize(&mut self, program: &mut IRProgram) {
+        // -O0 deja el IR tal cual sale del builder, para poder separar los
+        // fallos del optimizador de los de la generación de código
+        if self.level == OptLevel::O0 {
+            return;
+        }
+
+        if self.level >= OptLevel::O2 {
+            let start = Instant::now();
+            inliner::inline_functions(program);
+            self.record_pass("inlining", start);
+        }
+
         for function in &mut program.functions {
+            let start = Instant::now();
             self.constant_propagation(function);
+            self.record_pass("propagación de constantes", start);
+            let start = Instant::now();
+            logical::simplify_constant_logical(function);
+            self.record_pass("&& y || con constantes", start);
+            let start = Instant::now();
+            simplify_constant_branches(function);
+            self.record_pass("ramas constantes", start);
+            let start = Instant::now();
+            self.strength_reduction(function);
+            self.record_pass("reducción de fuerza", start);
+            let start = Instant::now();
             self.dead_code_elimination(function);
-            self.common_subexpression_elimination(function);
-            self.loop_optimization(function);
+            self.record_pass("eliminación de código muerto", start);
+            if self.level >= OptLevel::O2 {
+                let start = Instant::now();
+                branchless::select_simple_ternaries(function);
+                self.record_pass("ternarios con cmov", start);
+                let start = Instant::now();
+                branchless::remove_logical_branches(function);
+                self.record_pass("&& y || sin saltos", start);
+                let start = Instant::now();
+                self.common_subexpression_elimination(function);
+                self.record_pass("subexpresiones comunes", start);
+                // CSE deja copias `t1 = t0`; al propagarlas quedan muertas
+                let start = Instant::now();
+                self.copy_propagation(function);
+                self.record_pass("propagación de copias", start);
+                let start = Instant::now();
+                self.dead_code_elimination(function);
+                self.record_pass("eliminación de código muerto", start);
+                let start = Instant::now();
+                self.loop_optimization(function);
+                self.record_pass("optimización de bucles", start);
+            }
+            let start = Instant::now();
+            remove_jumps_to_next(function);
+            self.record_pass("saltos redundantes", start);
         }
+
+        let start = Instant::now();
+        constant_arrays::place_constant_arrays(program);
+        self.record_pass("arrays constantes", start);
     }
 
     fn constant_propagation(&mut self, function: &mut IRFunction) {
         let mut constants = HashMap::new();
 
         for instr in &mut function.instructions {
-            match instr {
+            let folded = match instr {
                 IROp::Assign(target, IRValue::Const(value)) => {
                     if let IRValue::Temp(name) = target {
                         constants.insert(name.clone(), *value);
                     }
+                    None
                 }
-                IROp::Add(result, left, right) => {
-                    if let (IRValue::Const(a), IRValue::Const(b)) = (left, right) {
-                        if let IRValue::Temp(name) = result {
-                            constants.insert(name.clone(), a + b);
-                            *instr = IROp::Assign(result.clone(), IRValue::Const(a + b));
-                        }
-                    }
-                }
-                IROp::Sub(result, left, right) => {
-                    if let (IRValue::Const(a), IRValue::Const(b)) = (left, right) {
-                        if let IRValue::Temp(name) = result {
-                            constants.insert(name.clone(), a - b);
-                            *instr = IROp::Assign(result.clone(), IRValue::Const(a - b));
-                        }
-                    }
+                // Con desbordamiento da la vuelta, igual que add/sub en runtime
+                IROp::Add(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), a.wrapping_add(*b))),
+                IROp::Sub(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), a.wrapping_sub(*b))),
+                // Las comparaciones valen 1 o 0, igual que setcc en runtime
+                IROp::CmpEq(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), (a == b) as i64)),
+                IROp::CmpNe(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), (a != b) as i64)),
+                IROp::CmpLt(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), (a < b) as i64)),
+                IROp::CmpLe(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), (a <= b) as i64)),
+                IROp::CmpGt(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), (a > b) as i64)),
+                IROp::CmpGe(result, IRValue::Const(a), IRValue::Const(b)) => Some((result.clone(), (a >= b) as i64)),
+                _ => None,
+            };
+
+            if let Some((result, value)) = folded {
+                if let IRValue::Temp(name) = &result {
+                    constants.insert(name.clone(), value);
+                    *instr = IROp::Assign(result, IRValue::Const(value));
                 }
-                _ => {}
             }
         }
     }
 
+    // Elimina las instrucciones puras cuyo temporal no lee nadie. Se repite
+    // porque quitar una puede dejar sin uso los operandos de otra.
     fn dead_code_elimination(&mut self, function: &mut IRFunction) {
-        let mut used_temps = HashSet::new();
-        let mut used_globals = HashSet::new();
+        loop {
+            let mut used_temps = HashSet::new();
+            let mut used_globals = HashSet::new();
 
-        // Mark used variables
-        for instr in &function.instructions {
-            match instr {
-                IROp::Print(value) | IROp::Return(Some(value)) => {
+            // Mark used variables, incluidos argumentos de llamadas y condiciones
+            for instr in &function.instructions {
+                for value in instr.used() {
                     self.mark_used(value, &mut used_temps, &mut used_globals);
                 }
-                IROp::Add(_, left, right) | IROp::Sub(_, left, right) | 
-                IROp::Mul(_, left, right) | IROp::Div(_, left, right) => {
-                    self.mark_used(left, &mut used_temps, &mut used_globals);
-                    self.mark_used(right, &mut used_temps, &mut used_globals);
+            }
+
+            // Remove unused instructions; las que tienen efectos laterales
+            // (llamadas, print...) se conservan aunque su resultado sobre
+            let before = function.instructions.len();
+            function.instructions.retain(|instr| {
+                match instr.defined() {
+                    Some(IRValue::Temp(name)) if !instr.has_side_effects() => used_temps.contains(name),
+                    _ => true,
                 }
-                _ => {}
+            });
+            if function.instructions.len() == before {
+                break;
             }
         }
+    }
 
-        // Remove unused instructions
-        function.instructions.retain(|instr| {
+    // Multiplicaciones y divisiones por potencias de dos pasan a ser
+    // desplazamientos.
+    fn strength_reduction(&mut self, function: &mut IRFunction) {
+        let mut instructions = Vec::with_capacity(function.instructions.len());
+        for instr in function.instructions.drain(..) {
             match instr {
-                IROp::Add(result, _, _) | IROp::Sub(result, _, _) |
-                IROp::Mul(result, _, _) | IROp::Div(result, _, _) => {
-                    if let IRValue::Temp(name) = result {
-                        used_temps.contains(name)
-                    } else {
-                        true
-                    }
+                IROp::Mul(result, value, IRValue::Const(c)) | IROp::Mul(result, IRValue::Const(c), value)
+                    if power_of_two(c).is_some() =>
+                {
+                    let shift = power_of_two(c).unwrap();
+                    instructions.push(IROp::Shl(result, value, IRValue::Const(shift)));
+                }
+                IROp::Div(result, value, IRValue::Const(c)) if power_of_two(c).is_some() => {
+                    // La división con signo trunca hacia cero y el desplazamiento
+                    // aritmético redondea hacia abajo: a los negativos se les suma
+                    // 2^k - 1 antes de desplazar. El sesgo sale del bit de signo.
+                    let shift = power_of_two(c).unwrap();
+                    let bias = match &result {
+                        IRValue::Temp(name) | IRValue::Local(name) | IRValue::Global(name) => {
+                            IRValue::Temp(format!("{}.bias", name))
+                        }
+                        IRValue::Const(_) | IRValue::Data(_) => IRValue::Temp("bias".to_string()),
+                    };
+                    instructions.push(IROp::Sar(bias.clone(), value.clone(), IRValue::Const(63)));
+                    instructions.push(IROp::Shr(bias.clone(), bias.clone(), IRValue::Const(64 - shift)));
+                    instructions.push(IROp::Add(bias.clone(), value, bias.clone()));
+                    instructions.push(IROp::Sar(result, bias, IRValue::Const(shift)));
                 }
-                _ => true,
+                other => instructions.push(other),
             }
-        });
+        }
+        function.instructions = instructions;
     }
 
     fn mark_used(&self, value: &IRValue, temps: &mut HashSet<String>, globals: &mut HashSet<String>) {
@@ -97,46 +206,574 @@ impl Optimizer {
         }
     }
 
+    // Reutiliza el resultado de una operación ya calculada en el mismo
+    // bloque básico. La caché se vacía en cada etiqueta, porque a partir de
+    // ahí puede llegarse por un camino que no calculó la expresión.
     fn common_subexpression_elimination(&mut self, function: &mut IRFunction) {
-        let mut expressions = HashMap::new();
-        let mut replacements = HashMap::new();
+        let mut expressions: HashMap<(&'static str, IRValue, IRValue), IRValue> = HashMap::new();
 
         for instr in &mut function.instructions {
-            match instr {
-                IROp::Add(result, left, right) => {
-                    let key = format!("add_{:?}_{:?}", left, right);
-                    if let Some(existing) = expressions.get(&key) {
-                        if let IRValue::Temp(result_name) = result {
-                            replacements.insert(result_name.clone(), existing.clone());
-                            *instr = IROp::Assign(result.clone(), existing.clone());
-                        }
-                    } else {
-                        if let IRValue::Temp(result_name) = result {
-                            expressions.insert(key, result.clone());
-                        }
-                    }
+            if matches!(instr, IROp::Label(_)) {
+                expressions.clear();
+                continue;
+            }
+            // Una llamada puede escribir cualquier global
+            if matches!(instr, IROp::Call(..)) {
+                expressions.retain(|(_, left, right), _| {
+                    !matches!(left, IRValue::Global(_)) && !matches!(right, IRValue::Global(_))
+                });
+            }
+            // Dos llamadas iguales no dan por qué el mismo resultado, pero
+            // lo que escriben sí invalida lo calculado con el valor anterior
+            if instr.has_side_effects() {
+                invalidate(&mut expressions, instr.defined());
+                continue;
+            }
+
+            let expression = match instr {
+                IROp::Add(result @ IRValue::Temp(_), left, right) => {
+                    Some((result.clone(), commutative_key("add", left, right)))
+                }
+                IROp::Mul(result @ IRValue::Temp(_), left, right) => {
+                    Some((result.clone(), commutative_key("mul", left, right)))
+                }
+                // a - b y a / b no equivalen a b - a ni b / a
+                IROp::Sub(result @ IRValue::Temp(_), left, right) => {
+                    Some((result.clone(), ("sub", left.clone(), right.clone())))
+                }
+                IROp::Div(result @ IRValue::Temp(_), left, right) => {
+                    Some((result.clone(), ("div", left.clone(), right.clone())))
+                }
+                _ => None,
+            };
+
+            if let Some((result, key)) = &expression {
+                if let Some(existing) = expressions.get(key) {
+                    *instr = IROp::Assign(result.clone(), existing.clone());
+                }
+            }
+
+            invalidate(&mut expressions, instr.defined());
+
+            if let (Some((result, key)), IROp::Add(..) | IROp::Mul(..) | IROp::Sub(..) | IROp::Div(..)) =
+                (expression, &*instr)
+            {
+                if key.1 != result && key.2 != result {
+                    expressions.insert(key, result);
                 }
-                _ => {}
             }
         }
     }
 
-    fn loop_optimization(&mut self, function: &mut IRFunction) {
-        // Simple loop invariant code motion
-        let mut i = 0;
-        while i < function.instructions.len() {
-            if let IROp::Label(label) = &function.instructions[i] {
-                if label.starts_with("label_") {
-                    // Check for loop pattern and optimize
-                    self.optimize_loop(&mut function.instructions, i);
+    // Tras `a = b` entre temporales, las lecturas de a pasan a leer b hasta
+    // que se escriba a o b. Como en CSE, solo dentro del bloque básico.
+    fn copy_propagation(&mut self, function: &mut IRFunction) {
+        let mut copies: HashMap<IRValue, IRValue> = HashMap::new();
+
+        for instr in &mut function.instructions {
+            if matches!(instr, IROp::Label(_)) {
+                copies.clear();
+                continue;
+            }
+
+            let defined = instr.defined().cloned();
+            let mut propagated = instr.map(
+                &mut |value| copies.get(value).cloned().unwrap_or_else(|| value.clone()),
+                &mut |label| label.to_string(),
+            );
+            if let Some(defined) = defined {
+                *propagated.defined_mut().unwrap() = defined.clone();
+                copies.retain(|copy, source| *copy != defined && *source != defined);
+            }
+            if let IROp::Assign(copy @ IRValue::Temp(_), source @ IRValue::Temp(_)) = &propagated {
+                if copy != source {
+                    copies.insert(copy.clone(), source.clone());
                 }
             }
-            i += 1;
+            *instr = propagated;
+        }
+    }
+
+    fn loop_optimization(&mut self, function: &mut IRFunction) {
+        // Simple loop invariant code motion. Los bucles se detectan por las
+        // aristas de retroceso hacia un bloque que las domina.
+        let cfg = ControlFlowGraph::build(function);
+        for header in cfg.loop_headers() {
+            self.optimize_loop(&mut function.instructions, cfg.blocks[header].start);
         }
     }
 
-    fn optimize_loop(&mut self, instructions: &mut Vec<IROp>, start_idx: usize) {
+    fn optimize_loop(&mut self, _instructions: &mut [IROp], _start_idx: usize) {
         // Move invariant computations outside loops
-        // This is a simplified version
+        // This is a simplified version. Solo serían candidatas las
+        // instrucciones sin efectos laterales (ver IROp::has_side_effects):
+        // una llamada en la condición se reevalúa en cada iteración.
+    }
+}
+
+// Lo que acaba de escribirse invalida las expresiones que lo leen y las
+// que lo tenían como resultado
+fn invalidate(expressions: &mut HashMap<(&'static str, IRValue, IRValue), IRValue>, defined: Option<&IRValue>) {
+    if let Some(defined) = defined {
+        expressions.retain(|(_, left, right), result| left != defined && right != defined && result != defined);
+    }
+}
+
+// Quita los `goto L` que solo caerían en L: las etiquetas no generan
+// código, así que basta con que L esté entre las que siguen al salto
+fn remove_jumps_to_next(function: &mut IRFunction) {
+    let instructions = &function.instructions;
+    let redundant: Vec<bool> = (0..instructions.len())
+        .map(|i| match &instructions[i] {
+            IROp::Jump(target) => instructions[i + 1..]
+                .iter()
+                .map_while(|instr| match instr {
+                    IROp::Label(name) => Some(name),
+                    _ => None,
+                })
+                .any(|name| name == target),
+            _ => false,
+        })
+        .collect();
+    let mut redundant = redundant.into_iter();
+    function.instructions.retain(|_| !redundant.next().unwrap());
+}
+
+// Un literal o un temporal que solo se escribe una vez, con una constante
+// (lo que deja la propagación de constantes al plegar una comparación) o
+// con la copia de otro temporal así (lo que deja plegar un && o un ||)
+fn constant_value(function: &IRFunction, value: &IRValue) -> Option<i64> {
+    match value {
+        IRValue::Const(n) => Some(*n),
+        IRValue::Temp(_) => {
+            let mut definitions = function.instructions.iter().filter(|instr| instr.defined() == Some(value));
+            match (definitions.next(), definitions.next()) {
+                (Some(IROp::Assign(_, IRValue::Const(n))), None) => Some(*n),
+                (Some(IROp::Assign(_, source @ IRValue::Temp(_))), None) => constant_value(function, source),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Saltos condicionales con condición constante: o se toman siempre y pasan
+// a `goto`, o nunca y desaparecen. Después se quita lo que ya no se alcanza
+// desde la entrada y las etiquetas a las que no salta nadie.
+fn simplify_constant_branches(function: &mut IRFunction) {
+    let instructions: Vec<IROp> = function
+        .instructions
+        .iter()
+        .filter_map(|instr| match instr {
+            IROp::JumpIfZero(cond, label) | IROp::JumpIfNotZero(cond, label) => {
+                let Some(value) = constant_value(function, cond) else {
+                    return Some(instr.clone());
+                };
+                let taken = (value == 0) == matches!(instr, IROp::JumpIfZero(..));
+                taken.then(|| IROp::Jump(label.clone()))
+            }
+            _ => Some(instr.clone()),
+        })
+        .collect();
+    function.instructions = instructions;
+    if function.instructions.is_empty() {
+        return;
+    }
+
+    // Un bloque inalcanzable solo recibe saltos de otros inalcanzables,
+    // así que sus etiquetas se van junto con quien salta a ellas
+    let cfg = ControlFlowGraph::build(function);
+    let reachable = cfg.reachable();
+    let mut keep = vec![false; function.instructions.len()];
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        if reachable.contains(&b) {
+            keep[block.start..block.end].fill(true);
+        }
+    }
+    let mut keep = keep.into_iter();
+    function.instructions.retain(|_| keep.next().unwrap());
+    remove_jumps_to_next(function);
+
+    let targets: HashSet<String> = function
+        .instructions
+        .iter()
+        .filter_map(|instr| match instr {
+            IROp::Jump(label) | IROp::JumpIfZero(_, label) | IROp::JumpIfNotZero(_, label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect();
+    function.instructions.retain(|instr| match instr {
+        IROp::Label(name) => targets.contains(name),
+        _ => true,
+    });
+}
+
+// Clave de una operación conmutativa, con los operandos en orden fijo
+// para que a + b y b + a coincidan
+fn commutative_key(op: &'static str, left: &IRValue, right: &IRValue) -> (&'static str, IRValue, IRValue) {
+    if format!("{:?}", left) <= format!("{:?}", right) {
+        (op, left.clone(), right.clone())
+    } else {
+        (op, right.clone(), left.clone())
+    }
+}
+
+// Exponente k si `value` es 2^k con k >= 1
+fn power_of_two(value: i64) -> Option<i64> {
+    if value > 1 && value & (value - 1) == 0 {
+        Some(value.trailing_zeros() as i64)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remove_jumps_to_next, OptLevel, Optimizer};
+    use crate::ir::{IRFunction, IROp, IRProgram, IRValue, InlineHint, PrintKind};
+    use crate::testing::{build_ir, function, run};
+    use std::collections::HashMap;
+
+    fn optimized(source: &str, level: OptLevel) -> IRProgram {
+        let mut program = build_ir(source);
+        Optimizer::with_level(level).optimize(&mut program);
+        program
+    }
+
+    // `name` devuelve lo mismo, e imprime lo mismo, en todos los niveles
+    fn assert_same_results(source: &str, name: &str, inputs: &[Vec<i64>]) {
+        let unoptimized = optimized(source, OptLevel::O0);
+        for level in [OptLevel::O1, OptLevel::O2] {
+            let program = optimized(source, level);
+            for args in inputs {
+                assert_eq!(
+                    run(&program, name, args),
+                    run(&unoptimized, name, args),
+                    "{:?} con {:?}",
+                    level,
+                    args
+                );
+            }
+        }
+    }
+
+    fn count(program: &IRProgram, name: &str, matches: impl Fn(&IROp) -> bool) -> usize {
+        function(program, name).instructions.iter().filter(|instr| matches(instr)).count()
+    }
+
+    #[test]
+    fn multiplication_and_division_by_powers_of_two_become_shifts() {
+        let source = "fn mul(x: int) -> int { return x * 8 + 4 * x; } fn div(x: int) -> int { return x / 4; } \
+                      fn other(x: int) -> int { return x * 6 + x / 3 + x * 1 + x / -4; }";
+        let program = optimized(source, OptLevel::O1);
+        assert_eq!(count(&program, "mul", |instr| matches!(instr, IROp::Mul(..))), 0);
+        let shifts: Vec<_> = function(&program, "mul")
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Shl(_, _, IRValue::Const(shift)) => Some(*shift),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shifts, [3, 2]);
+        assert_eq!(count(&program, "div", |instr| matches!(instr, IROp::Div(..))), 0);
+        assert_eq!(count(&program, "other", |instr| matches!(instr, IROp::Shl(..) | IROp::Sar(..))), 0);
+
+        // La división trunca hacia cero también con dividendos negativos
+        let inputs = [-9, -8, -7, -1, 0, 1, 7, 8, 9, i64::MIN, i64::MAX].map(|x| vec![x]);
+        for name in ["mul", "div", "other"] {
+            assert_same_results(source, name, &inputs);
+        }
+        assert_eq!(run(&program, "div", &[-7]).0, Some(-1));
+    }
+
+    // Ejecuta un solo pase sobre la función `f` tal como sale del builder
+    fn with_pass(source: &str, pass: impl Fn(&mut Optimizer, &mut IRFunction)) -> IRProgram {
+        let mut program = build_ir(source);
+        let function = program.functions.iter_mut().find(|f| f.name == "f").unwrap();
+        pass(&mut Optimizer::with_level(OptLevel::O2), function);
+        program
+    }
+
+    #[test]
+    fn cse_reuses_every_arithmetic_operation_in_a_block() {
+        let source = "fn f(a: int, b: int) -> int { let s = a - b; let m = a * b; let d = a / b; let p = b + a; \
+                      return (a - b) + (b * a) + (a / b) + (a + b) + s + m + d + p; }";
+        let program = with_pass(source, Optimizer::common_subexpression_elimination);
+        let arithmetic = |instr: &IROp| matches!(instr, IROp::Sub(..) | IROp::Mul(..) | IROp::Div(..));
+        assert_eq!(count(&program, "f", arithmetic), 3);
+        let add_of_params = |instr: &IROp| matches!(instr, IROp::Add(_, IRValue::Local(_), IRValue::Local(_)));
+        assert_eq!(count(&program, "f", add_of_params), 1);
+        assert_same_results(source, "f", &[vec![7, 2], vec![-9, 4]]);
+    }
+
+    #[test]
+    fn cse_forgets_expressions_whose_operands_change() {
+        let source = "fn f(a: int, b: int) -> int { let x = a - b; a = a + 1; let y = a - b; \
+                      let z = b - a; let w = x * 2; x = 0; return w + x * 2 + y + z; }";
+        let program = with_pass(source, Optimizer::common_subexpression_elimination);
+        // a - b tras cambiar a, b - a y x * 2 tras cambiar x se recalculan
+        assert_eq!(count(&program, "f", |instr| matches!(instr, IROp::Sub(..))), 3);
+        assert_eq!(count(&program, "f", |instr| matches!(instr, IROp::Mul(..))), 2);
+        assert_same_results(source, "f", &[vec![7, 2], vec![0, 0]]);
+    }
+
+    #[test]
+    fn cse_does_not_reuse_across_labels() {
+        let source = "fn f(a: int, b: int) -> int { let x = a * b; if (a > 0) { a = 0; } let y = a * b; return x + y; }";
+        let program = with_pass(source, Optimizer::common_subexpression_elimination);
+        assert_eq!(count(&program, "f", |instr| matches!(instr, IROp::Mul(..))), 2);
+        assert_same_results(source, "f", &[vec![3, 4], vec![-3, 4]]);
+    }
+
+    // `t1 = x + 1; x = call g(); t2 = x + 1`: la llamada escribe x
+    #[test]
+    fn cse_forgets_expressions_whose_operands_a_call_writes() {
+        let temp = |name: &str| IRValue::Temp(name.to_string());
+        let add_one = |result: &str, operand: &IRValue| IROp::Add(temp(result), operand.clone(), IRValue::Const(1));
+        for written in [IRValue::Local("x".to_string()), IRValue::Global("x".to_string())] {
+            // Un global puede cambiar en la llamada aunque no sea su resultado
+            let result = matches!(written, IRValue::Local(_)).then(|| written.clone());
+            let mut function = IRFunction {
+                name: "f".to_string(),
+                params: vec!["x".to_string()],
+                instructions: vec![
+                    add_one("t1", &written),
+                    IROp::Call("g".to_string(), Vec::new(), result),
+                    add_one("t2", &written),
+                    IROp::Return(Some(temp("t2"))),
+                ],
+                locals: HashMap::new(),
+                inline: InlineHint::Auto,
+            };
+            Optimizer::with_level(OptLevel::O2).common_subexpression_elimination(&mut function);
+            let adds = function.instructions.iter().filter(|instr| matches!(instr, IROp::Add(..))).count();
+            assert_eq!(adds, 2, "{}", written);
+        }
+    }
+
+    // La condición de un bucle se vuelve a llamar en cada vuelta
+    #[test]
+    fn loop_condition_calls_run_on_every_iteration() {
+        let source = "@noinline fn hasNext(i: int) -> bool { print(i); return i < 3; } \
+                      fn f() -> int { let i = 0; while (hasNext(i)) { i = i + 1; } return i; }";
+        let program = optimized(source, OptLevel::O2);
+        let calls = |instr: &IROp| matches!(instr, IROp::Call(name, _, _) if name == "hasNext");
+        assert_eq!(count(&program, "f", calls), 1);
+        // La llamada sigue detrás de la etiqueta de inicio del bucle
+        let instructions = &function(&program, "f").instructions;
+        let call = instructions.iter().position(calls).unwrap();
+        assert!(instructions[..call].iter().any(|instr| matches!(instr, IROp::Label(_))));
+        assert_eq!(run(&program, "f", &[]), (Some(3), vec![0, 1, 2, 3]));
+        assert_same_results(source, "f", &[vec![]]);
+    }
+
+    #[test]
+    fn dce_keeps_values_read_by_calls_comparisons_and_branches() {
+        let source = "fn foo(x: int) { print(x); } fn f(a: int, b: int) -> int { foo(a + b); \
+                      if (a + b == 5) { print(1); } while (a * 2 < b) { a = a + 1; } return 0; }";
+        let program = with_pass(source, Optimizer::dead_code_elimination);
+        assert_eq!(
+            function(&program, "f").instructions.len(),
+            function(&build_ir(source), "f").instructions.len()
+        );
+        assert_same_results(source, "f", &[vec![2, 3], vec![1, 9]]);
+        assert_eq!(run(&optimized(source, OptLevel::O2), "f", &[2, 3]).1, [5, 1]);
+    }
+
+    #[test]
+    fn dce_removes_chains_of_unread_temporaries_but_not_calls() {
+        let source = "fn g() -> int { print(7); return 1; } \
+                      fn f(a: int) -> int { (a + 1) * (a - 1) / 2; g() + 1; return a; }";
+        let program = with_pass(source, Optimizer::dead_code_elimination);
+        let instructions = &function(&program, "f").instructions;
+        assert!(!instructions.iter().any(|instr| matches!(instr, IROp::Add(..) | IROp::Sub(..) | IROp::Mul(..) | IROp::Div(..))));
+        assert_eq!(count(&program, "f", |instr| matches!(instr, IROp::Call(..))), 1);
+        assert_eq!(run(&program, "f", &[4]), (Some(4), vec![7]));
+    }
+
+    #[test]
+    fn jumps_that_only_fall_through_are_removed() {
+        let label = |name: &str| IROp::Label(name.to_string());
+        let jump = |name: &str| IROp::Jump(name.to_string());
+        let x = IRValue::Local("x".to_string());
+        let mut function = IRFunction {
+            name: "f".to_string(),
+            params: vec!["x".to_string()],
+            instructions: vec![
+                IROp::JumpIfZero(x.clone(), "a".to_string()),
+                jump("a"),
+                label("a"),
+                jump("c"),
+                label("b"),
+                label("c"),
+                jump("a"),
+                IROp::Print(x.clone(), PrintKind::Int),
+                jump("d"),
+                IROp::Return(Some(x)),
+                label("d"),
+            ],
+            locals: HashMap::new(),
+            inline: InlineHint::Auto,
+        };
+        remove_jumps_to_next(&mut function);
+        // Solo quedan el salto hacia atrás y el que salta el return
+        let jumps = function.instructions.iter().filter(|instr| matches!(instr, IROp::Jump(_))).count();
+        assert_eq!(jumps, 2);
+        assert_eq!(function.instructions.len(), 9);
+        assert!(matches!(function.instructions[0], IROp::JumpIfZero(..)));
+    }
+
+    #[test]
+    fn if_else_keeps_only_the_jump_over_the_else() {
+        let source = "fn f(x: int) -> int { if (x > 0) { x = 1; } else { x = 2; } if (x > 5) { x = 3; } return x; }";
+        let program = optimized(source, OptLevel::O1);
+        assert_eq!(count(&program, "f", |instr| matches!(instr, IROp::Jump(_))), 1);
+        assert_same_results(source, "f", &[vec![1], vec![-1], vec![9]]);
+    }
+
+    #[test]
+    fn comparisons_of_constants_fold_to_zero_or_one() {
+        let source = "fn f() -> int { print(3 < 5); print(3 > 5); print(4 == 4); print(4 != 4); \
+                      print(5 <= 5); print(6 >= 7); return 0; }";
+        let program = with_pass(source, Optimizer::constant_propagation);
+        let comparisons = |instr: &IROp| {
+            matches!(
+                instr,
+                IROp::CmpEq(..) | IROp::CmpNe(..) | IROp::CmpLt(..) | IROp::CmpLe(..) | IROp::CmpGt(..) | IROp::CmpGe(..)
+            )
+        };
+        assert_eq!(count(&program, "f", comparisons), 0);
+        assert_eq!(run(&program, "f", &[]).1, [1, 0, 1, 0, 1, 0]);
+        assert_same_results(source, "f", &[vec![]]);
+    }
+
+    #[test]
+    fn constant_arithmetic_wraps_instead_of_panicking() {
+        let source = "fn f() -> int { print(9223372036854775807 + 1); print(0 - 9223372036854775807 - 2); return 0; }";
+        let program = optimized(source, OptLevel::O2);
+        assert_eq!(run(&program, "f", &[]).1, [i64::MIN, i64::MAX]);
+    }
+
+    #[test]
+    fn copies_are_propagated_until_either_side_changes() {
+        let temp = |name: &str| IRValue::Temp(name.to_string());
+        let x = IRValue::Local("x".to_string());
+        let mut function = IRFunction {
+            name: "f".to_string(),
+            params: vec!["x".to_string()],
+            instructions: vec![
+                IROp::Add(temp("t0"), x.clone(), IRValue::Const(1)),
+                IROp::Assign(temp("t1"), temp("t0")),
+                IROp::Print(temp("t1"), PrintKind::Int),
+                IROp::Add(temp("t0"), x.clone(), IRValue::Const(2)),
+                IROp::Print(temp("t1"), PrintKind::Int),
+                IROp::Assign(temp("t2"), temp("t1")),
+                IROp::Label("l".to_string()),
+                IROp::Print(temp("t2"), PrintKind::Int),
+                IROp::Return(None),
+            ],
+            locals: HashMap::new(),
+            inline: InlineHint::Auto,
+        };
+        Optimizer::with_level(OptLevel::O2).copy_propagation(&mut function);
+        let printed: Vec<&IRValue> = function
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                IROp::Print(value, _) => Some(value),
+                _ => None,
+            })
+            .collect();
+        // t1 deja de ser copia de t0 cuando t0 cambia; tras la etiqueta no
+        // se sabe por dónde se ha llegado
+        assert_eq!(printed, [&temp("t0"), &temp("t1"), &temp("t2")]);
+        assert!(matches!(
+            &function.instructions[5],
+            IROp::Assign(target, source) if *target == temp("t2") && *source == temp("t1")
+        ));
+    }
+
+    #[test]
+    fn cse_copies_disappear_at_o2() {
+        let source = "fn f(a: int, b: int) -> int { let x = (a + b) * (a + b); let y = (a - b) * (a - b); \
+                      return x + y; }";
+        let program = optimized(source, OptLevel::O2);
+        let copies = |instr: &IROp| matches!(instr, IROp::Assign(IRValue::Temp(_), IRValue::Temp(_)));
+        assert_eq!(count(&program, "f", copies), 0);
+        assert_eq!(count(&program, "f", |instr| matches!(instr, IROp::Add(..) | IROp::Sub(..))), 3);
+        assert_same_results(source, "f", &[vec![3, 4], vec![-2, 5]]);
+    }
+
+    // Los atributos deciden sobre el tamaño y el número de llamadas
+    #[test]
+    fn inline_attributes_override_the_inliner_heuristics_at_o2() {
+        let assignments: String = (0..20).map(|i| format!("x = x * 3 + {}; ", i)).collect();
+        let source = format!(
+            "@noinline fn one() -> int {{ return 1; }} \
+             @inline fn big(x: int) -> int {{ {} return x; }} \
+             fn main() -> int {{ print(one()); return big(input_int()); }}",
+            assignments
+        );
+        let calls = |level| {
+            let program = optimized(&source, level);
+            let main = function(&program, "main");
+            let mut calls: Vec<String> = main
+                .instructions
+                .iter()
+                .filter_map(|instr| match instr {
+                    IROp::Call(name, _, _) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect();
+            calls.sort();
+            calls
+        };
+        assert!(function(&build_ir(&source), "big").instructions.len() > 40);
+        assert_eq!(calls(OptLevel::O2), ["input_int", "one"]);
+        // Por debajo de -O2 no se expande nada
+        assert_eq!(calls(OptLevel::O1), ["big", "input_int", "one"]);
+    }
+
+    #[test]
+    fn constant_conditions_drop_the_branch_never_taken() {
+        let prints = |program: &IRProgram| -> Vec<IRValue> {
+            function(program, "f")
+                .instructions
+                .iter()
+                .filter_map(|instr| match instr {
+                    IROp::Print(value, _) => Some(value.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let conditional = |instr: &IROp| matches!(instr, IROp::JumpIfZero(..) | IROp::JumpIfNotZero(..));
+        let cases = [
+            ("if (false) { print(1); }", vec![]),
+            ("while (false) { print(1); }", vec![]),
+            ("if (2 < 3) { print(1); } else { print(2); }", vec![IRValue::Const(1)]),
+            ("if (true && 2 > 3) { print(1); } else { print(2); }", vec![IRValue::Const(2)]),
+        ];
+        for (body, expected) in cases {
+            let source = format!("fn f() -> int {{ {} return 0; }}", body);
+            let program = optimized(&source, OptLevel::O1);
+            assert_eq!(prints(&program), expected, "{}", body);
+            assert_eq!(count(&program, "f", conditional), 0, "{}", body);
+            assert!(function(&program, "f").instructions.iter().all(|instr| !matches!(instr, IROp::Label(_))), "{}", body);
+        }
+
+        // El bucle pierde su comprobación pero conserva la etiqueta a la que
+        // vuelve; lo que sigue al bucle es inalcanzable
+        let source = "fn f(x: int) -> int { while (true) { if (x > 3) { return x; } x = x + 1; } print(9); return 0; }";
+        let program = optimized(source, OptLevel::O1);
+        assert!(prints(&program).is_empty());
+        assert_eq!(count(&program, "f", conditional), 1);
+        let instructions = &function(&program, "f").instructions;
+        for instr in instructions {
+            if let IROp::Jump(target) = instr {
+                assert!(instructions.iter().any(|i| matches!(i, IROp::Label(name) if name == target)), "{}", target);
+            }
+        }
+        assert_same_results(source, "f", &[vec![0], vec![5], vec![-2]]);
     }
 }
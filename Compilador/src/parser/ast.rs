@@ -1,23 +1,92 @@
+use crate::lexer::token::Span;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Int,
+    Float,
     Bool,
     String,
+    // Un carácter ASCII; en runtime es su código, como un int
+    Char,
     Array(Box<Type>),
     Void,
+    // Referencia a un tipo por nombre; el análisis semántico la valida
+    Named(String),
+    // enum declarado por el usuario; sus variantes son enteros
+    Enum(String),
+    // Último parámetro de una función variádica (`...T`); dentro del
+    // cuerpo es un [T] con los argumentos sobrantes
+    Variadic(Box<Type>),
+    // (int, bool): en runtime un bloque con un elemento por posición
+    Tuple(Vec<Type>),
+}
+
+// Identificador estable de un nodo del AST, asignado por el parser en
+// orden de creación. Sirve para asociar información en tablas auxiliares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub id: NodeId,
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+// Tamaño máximo en bytes de una cadena calculada en compilación
+pub const MAX_CONSTANT_STRING: usize = 1 << 20;
+
+impl Expr {
+    // Valor de una expresión de cadena calculable en compilación: literales
+    // unidos con '+' y repetidos con '* n' (n literal no negativo). Si el
+    // resultado pasaría de MAX_CONSTANT_STRING no se calcula.
+    pub fn constant_string(&self) -> Option<String> {
+        match &self.kind {
+            ExprKind::String(s) => Some(s.clone()),
+            ExprKind::Grouped(inner) => inner.constant_string(),
+            ExprKind::Infix { left, op, right } => match (op.as_str(), &right.kind) {
+                ("+", _) => {
+                    let (left, right) = (left.constant_string()?, right.constant_string()?);
+                    (left.len() + right.len() <= MAX_CONSTANT_STRING).then(|| left + &right)
+                }
+                ("*", ExprKind::Number(n)) if *n >= 0 => {
+                    let s = left.constant_string()?;
+                    let count = usize::try_from(*n).ok()?;
+                    let length = s.len().checked_mul(count)?;
+                    (length <= MAX_CONSTANT_STRING).then(|| s.repeat(count))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Number(i64),
+    Float(f64),
     Boolean(bool),
     String(String),
+    Char(char),
     Ident(String),
     ArrayLiteral(Vec<Expr>),
     ArrayIndex {
         array: Box<Expr>,
         index: Box<Expr>,
     },
+    // (a, b), con al menos dos elementos
+    Tuple(Vec<Expr>),
+    // t.0
+    TupleIndex {
+        tuple: Box<Expr>,
+        index: usize,
+    },
+    // -x, !b
+    Prefix {
+        op: String,
+        operand: Box<Expr>,
+    },
     Infix {
         left: Box<Expr>,
         op: String,
@@ -27,12 +96,46 @@ pub enum Expr {
         function: String,
         args: Vec<Expr>,
     },
+    // Llamada sobre el resultado de otra expresión, p. ej. f()()
+    CallExpr {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
     Grouped(Box<Expr>),
+    // cond ? a : b, asociativo por la derecha
+    Ternary {
+        condition: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
+    // new [T](size): array de `size` elementos inicializados a cero
+    New {
+        type_: Type,
+        size: Box<Expr>,
+    },
+    // Color.Red; la forma sin calificar `Red` es un Ident
+    EnumVariant {
+        enum_name: String,
+        variant: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub id: NodeId,
+    pub kind: StmtKind,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
-pub enum Stmt {
-    Let { name: String, type_annotation: Option<Type>, value: Expr },
+pub enum StmtKind {
+    // const declara un Let con mutable = false
+    // Sin valor (`let x: T;`) la variable empieza con el valor por defecto de T
+    Let { name: String, type_annotation: Option<Type>, value: Option<Expr>, mutable: bool },
+    // let [a, b] = array;
+    LetArray { names: Vec<String>, value: Expr },
+    // let (a, b) = tupla;
+    LetTuple { names: Vec<String>, value: Expr },
     Assign { target: String, value: Expr },
     If {
         condition: Expr,
@@ -52,15 +155,50 @@ pub enum Stmt {
     Function {
         name: String,
         params: Vec<(String, Type)>,
-        return_type: Type,
+        // Valor por defecto de cada parámetro, en el orden de params; los
+        // que lo tienen van después de los obligatorios
+        defaults: Vec<Option<Expr>>,
+        // None si no se escribe `-> tipo`: el análisis lo deduce de los return
+        return_type: Option<Type>,
         body: Vec<Stmt>,
+        // Nombres de los atributos @x que preceden a 'fn'
+        attributes: Vec<String>,
     },
     Return(Option<Expr>),
     Expression(Expr),
     Print(Expr),
+    // enum Color { Red, Green, Blue }: variantes numeradas desde 0
+    Enum { name: String, variants: Vec<String> },
 }
 
 #[derive(Debug, Clone)]
 pub struct Program {
     pub statements: Vec<Stmt>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    // La expresión del primer print de `fn main() { print(...); }`
+    fn printed(expr: &str) -> Expr {
+        let program = parse(&format!("fn main() {{ print({}); }}", expr)).unwrap();
+        let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+        let StmtKind::Print(expr) = &body[0].kind else { unreachable!() };
+        expr.clone()
+    }
+
+    #[test]
+    fn constant_string_folds_concatenation_and_repetition() {
+        assert_eq!(printed("(\"ab\" + \"c\") * 2").constant_string().as_deref(), Some("abcabc"));
+        assert_eq!(printed("\"a\" * 0").constant_string().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn constant_string_refuses_results_over_the_limit() {
+        assert_eq!(printed("\"ab\" * 9223372036854775807").constant_string(), None);
+        assert_eq!(printed("\"ab\" * 524289").constant_string(), None);
+        assert_eq!(printed("\"ab\" * 524288").constant_string().map(|s| s.len()), Some(MAX_CONSTANT_STRING));
+    }
+}
@@ -0,0 +1,442 @@
+use crate::lexer::token::{Span, Token};
+use crate::lexer::{LexedToken, Lexer};
+use crate::parser::ast::{Expr, ExprKind, Program, Stmt, StmtKind};
+use crate::parser::Parser;
+use anyhow::{bail, Result};
+use std::ops::Range;
+use std::rc::Rc;
+
+// Reanálisis incremental, para un editor que recompila en cada pulsación.
+// Se guardan los tokens del fuente y las sentencias de nivel superior con
+// el rango de tokens que ocupa cada una. Tras una edición solo se vuelve a
+// pasar el lexer por el tramo de texto afectado y el parser por las
+// sentencias que lo tocan; el resto se reutiliza.
+pub struct IncrementalParser {
+    source: String,
+    // None si el fuente actual tiene errores: la siguiente edición lo
+    // analiza entero
+    state: Option<State>,
+}
+
+struct State {
+    tokens: Rc<[LexedToken]>,
+    items: Vec<Item>,
+    next_id: usize,
+}
+
+// Sentencia de nivel superior y sus tokens [start, end); end es el primer
+// token de la siguiente
+#[derive(Clone)]
+struct Item {
+    stmt: Stmt,
+    tokens: Range<usize>,
+}
+
+// Lo que una edición ha tenido que volver a analizar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reanalysis {
+    pub relexed_tokens: usize,
+    pub reparsed_items: usize,
+}
+
+impl IncrementalParser {
+    pub fn new(source: String) -> Result<Self> {
+        let state = analyze_all(&source)?;
+        Ok(IncrementalParser { source, state: Some(state) })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // None si la última edición dejó el fuente con errores
+    pub fn program(&self) -> Option<Program> {
+        let state = self.state.as_ref()?;
+        Some(Program {
+            statements: state.items.iter().map(|item| item.stmt.clone()).collect(),
+        })
+    }
+
+    // Sustituye los caracteres `range` del fuente por `text` y actualiza
+    // el análisis. Si el resultado tiene errores se devuelven y el fuente
+    // queda editado igualmente.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Result<Reanalysis> {
+        let length = self.source.chars().count();
+        if range.start > range.end || range.end > length {
+            bail!("edición fuera del fuente: {:?} con {} caracteres", range, length);
+        }
+        let byte = |i: usize| self.source.char_indices().nth(i).map_or(self.source.len(), |(b, _)| b);
+        let bytes = byte(range.start)..byte(range.end);
+        self.source.replace_range(bytes, text);
+
+        let result = match self.state.take() {
+            Some(state) => reanalyze(&self.source, state, range, text.chars().count()),
+            None => analyze_all(&self.source).map(|state| {
+                let reanalysis = Reanalysis {
+                    relexed_tokens: state.tokens.len(),
+                    reparsed_items: state.items.len(),
+                };
+                (state, reanalysis)
+            }),
+        };
+        let (state, reanalysis) = result?;
+        self.state = Some(state);
+        Ok(reanalysis)
+    }
+}
+
+fn analyze_all(source: &str) -> Result<State> {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut tokens = Vec::new();
+    loop {
+        let lexed = lexer.next_lexed()?;
+        let eof = lexed.token == Token::Eof;
+        tokens.push(lexed);
+        if eof {
+            break;
+        }
+    }
+    let tokens: Rc<[LexedToken]> = tokens.into();
+    let mut parser = Parser::from_tokens(tokens.clone(), 0, 0)?;
+    let mut items = Vec::new();
+    parse_items(&mut parser, &mut items, |_| false)?;
+    Ok(State { tokens, items, next_id: parser.next_id() })
+}
+
+// Añade a `items` las sentencias desde la posición del parser hasta el
+// final o hasta una posición donde `stop` diga que se puede reutilizar lo
+// que sigue. Devuelve cuántas ha analizado.
+fn parse_items(parser: &mut Parser, items: &mut Vec<Item>, mut stop: impl FnMut(usize) -> bool) -> Result<usize> {
+    let mut parsed = 0;
+    while !parser.at_eof() && !stop(parser.position()) {
+        let start = parser.position();
+        if let Some(stmt) = parser.parse_item()? {
+            items.push(Item { stmt, tokens: start..parser.position() });
+            parsed += 1;
+        }
+    }
+    Ok(parsed)
+}
+
+fn reanalyze(source: &str, old: State, edit: Range<usize>, inserted: usize) -> Result<(State, Reanalysis)> {
+    let delta = inserted as isize - edit.len() as isize;
+    let old_tokens = &old.tokens;
+
+    // El lexer mira hasta dos caracteres más allá del token que lee
+    // ('1.5', '...'), así que la edición puede cambiar el token anterior
+    // al que la contiene o la precede: se empieza uno antes.
+    let (first, start, span) = match old_tokens.iter().rposition(|t| t.start < edit.start) {
+        Some(i) => {
+            let first = i.saturating_sub(1);
+            (first, old_tokens[first].start, old_tokens[first].span)
+        }
+        None => (0, 0, Span::default()),
+    };
+
+    // Se analiza hasta que un token nuevo, ya pasada la edición, empiece en
+    // el mismo sitio y la misma columna que uno antiguo: desde ahí el texto
+    // es idéntico y los tokens también, salvo la línea.
+    let mut lexer = Lexer::new(source.to_string()).starting_at(start, span);
+    let edit_end = edit.start + inserted;
+    let mut relexed = Vec::new();
+    let resume = loop {
+        let lexed = lexer.next_lexed()?;
+        if lexed.start >= edit_end {
+            let old_start = (lexed.start as isize - delta) as usize;
+            if let Ok(i) = old_tokens.binary_search_by_key(&old_start, |t| t.start) {
+                if old_tokens[i].span.column == lexed.span.column {
+                    break Some((i, lexed.span.line as isize - old_tokens[i].span.line as isize));
+                }
+            }
+        }
+        let eof = lexed.token == Token::Eof;
+        relexed.push(lexed);
+        if eof {
+            break None;
+        }
+    };
+    let (resume, lines) = resume.unwrap_or((old_tokens.len(), 0));
+    let relexed_tokens = relexed.len();
+
+    let mut tokens = old_tokens[..first].to_vec();
+    tokens.extend(relexed);
+    let reused_from = tokens.len();
+    tokens.extend(old_tokens[resume..].iter().map(|t| LexedToken {
+        token: t.token.clone(),
+        span: shift_span(t.span, lines),
+        start: (t.start as isize + delta) as usize,
+        end: (t.end as isize + delta) as usize,
+    }));
+    let tokens: Rc<[LexedToken]> = tokens.into();
+    let new_index = |old_index: usize| old_index - resume + reused_from;
+
+    // Se conservan las sentencias anteriores cuyo último token y el
+    // siguiente (el parser lo mira para ver si hay un else) no han cambiado,
+    // y las posteriores que caen enteras en los tokens reutilizados
+    let mut items: Vec<Item> = Vec::with_capacity(old.items.len());
+    let mut after = Vec::new();
+    for item in old.items {
+        if item.tokens.end < first {
+            items.push(item);
+        } else if item.tokens.start >= resume {
+            after.push(item);
+        }
+    }
+    let start = items.last().map_or(0, |item| item.tokens.end);
+    let mut parser = Parser::from_tokens(tokens.clone(), start, old.next_id)?;
+    let mut after = after.into_iter().peekable();
+    let reparsed_items = parse_items(&mut parser, &mut items, |position| {
+        while after.peek().is_some_and(|item| new_index(item.tokens.start) < position) {
+            after.next();
+        }
+        after.peek().is_some_and(|item| new_index(item.tokens.start) == position)
+    })?;
+    // Si el análisis llegó al final, las que absorbió ya no están
+    let end = parser.position();
+    for mut item in after.filter(|item| new_index(item.tokens.start) >= end) {
+        shift_stmt(&mut item.stmt, lines);
+        item.tokens = new_index(item.tokens.start)..new_index(item.tokens.end);
+        items.push(item);
+    }
+
+    let state = State { tokens, items, next_id: parser.next_id() };
+    Ok((state, Reanalysis { relexed_tokens, reparsed_items }))
+}
+
+fn shift_span(span: Span, lines: isize) -> Span {
+    Span { line: span.line.saturating_add_signed(lines), ..span }
+}
+
+// Mueve `lines` líneas las posiciones de una sentencia reutilizada
+fn shift_stmt(stmt: &mut Stmt, lines: isize) {
+    stmt.span = shift_span(stmt.span, lines);
+    match &mut stmt.kind {
+        StmtKind::Let { value: Some(value), .. }
+        | StmtKind::LetArray { value, .. }
+        | StmtKind::LetTuple { value, .. }
+        | StmtKind::Assign { value, .. }
+        | StmtKind::Expression(value)
+        | StmtKind::Print(value)
+        | StmtKind::Return(Some(value)) => shift_expr(value, lines),
+        StmtKind::If { condition, then_block, else_block } => {
+            shift_expr(condition, lines);
+            for stmt in then_block.iter_mut().chain(else_block.iter_mut().flatten()) {
+                shift_stmt(stmt, lines);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            shift_expr(condition, lines);
+            body.iter_mut().for_each(|stmt| shift_stmt(stmt, lines));
+        }
+        StmtKind::For { init, condition, increment, body } => {
+            shift_stmt(init, lines);
+            shift_expr(condition, lines);
+            shift_stmt(increment, lines);
+            body.iter_mut().for_each(|stmt| shift_stmt(stmt, lines));
+        }
+        StmtKind::Function { defaults, body, .. } => {
+            defaults.iter_mut().flatten().for_each(|value| shift_expr(value, lines));
+            body.iter_mut().for_each(|stmt| shift_stmt(stmt, lines));
+        }
+        StmtKind::Let { value: None, .. } | StmtKind::Return(None) | StmtKind::Enum { .. } => {}
+    }
+}
+
+fn shift_expr(expr: &mut Expr, lines: isize) {
+    expr.span = shift_span(expr.span, lines);
+    match &mut expr.kind {
+        ExprKind::ArrayLiteral(exprs) | ExprKind::Tuple(exprs) | ExprKind::Call { args: exprs, .. } => {
+            exprs.iter_mut().for_each(|expr| shift_expr(expr, lines));
+        }
+        ExprKind::CallExpr { callee, args } => {
+            shift_expr(callee, lines);
+            args.iter_mut().for_each(|expr| shift_expr(expr, lines));
+        }
+        ExprKind::ArrayIndex { array: left, index: right } | ExprKind::Infix { left, right, .. } => {
+            shift_expr(left, lines);
+            shift_expr(right, lines);
+        }
+        ExprKind::Prefix { operand: inner, .. }
+        | ExprKind::Grouped(inner)
+        | ExprKind::New { size: inner, .. }
+        | ExprKind::TupleIndex { tuple: inner, .. } => shift_expr(inner, lines),
+        ExprKind::Ternary { condition, then_expr, else_expr } => {
+            shift_expr(condition, lines);
+            shift_expr(then_expr, lines);
+            shift_expr(else_expr, lines);
+        }
+        ExprKind::Number(_)
+        | ExprKind::Float(_)
+        | ExprKind::Boolean(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_)
+        | ExprKind::Ident(_)
+        | ExprKind::EnumVariant { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IncrementalParser, Reanalysis};
+    use crate::parser::ast::Program;
+    use crate::testing::parse;
+
+    const SOURCE: &str = "\
+fn add(a: int, b: int) -> int {
+    return a + b;
+}
+
+fn twice(x: int) -> int {
+    let y = x * 2;
+    return y;
+}
+
+/* comentario */
+fn main() -> int {
+    if (twice(1) > 1) { print(1); }
+    print(add(2, 3));
+    return 0;
+}
+";
+
+    // El AST sin los NodeId, que dependen del orden en que se analizó
+    fn without_ids(program: &Program) -> String {
+        let debug = format!("{:?}", program);
+        let mut parts = debug.split("NodeId(");
+        let mut result = parts.next().unwrap().to_string();
+        for part in parts {
+            result.push_str(part.split_once(')').unwrap().1);
+        }
+        result
+    }
+
+    fn offset_of(source: &str, pattern: &str) -> usize {
+        source[..source.find(pattern).unwrap()].chars().count()
+    }
+
+    // Aplica la edición y comprueba que el resultado coincide con analizar
+    // desde cero el fuente editado
+    fn edit(parser: &mut IncrementalParser, pattern: &str, replacement: &str) -> Reanalysis {
+        let start = offset_of(parser.source(), pattern);
+        let reanalysis = parser.edit(start..start + pattern.chars().count(), replacement).unwrap();
+        let full = parse(parser.source()).unwrap();
+        assert_eq!(without_ids(&parser.program().unwrap()), without_ids(&full));
+        reanalysis
+    }
+
+    #[test]
+    fn editing_a_body_relexes_only_that_region() {
+        let mut parser = IncrementalParser::new(SOURCE.to_string()).unwrap();
+        let reanalysis = edit(&mut parser, "x * 2", "x * 3 + 1");
+        // `y = x * 3 + 1 ;`: el ';' ha cambiado de columna, así que se
+        // enlaza con los tokens antiguos en la línea siguiente
+        assert_eq!(reanalysis, Reanalysis { relexed_tokens: 8, reparsed_items: 1 });
+        // Las sentencias que no cambian se reutilizan tal cual, ids incluidos
+        let before = IncrementalParser::new(SOURCE.to_string()).unwrap().program().unwrap();
+        let after = parser.program().unwrap();
+        assert_eq!(format!("{:?}", before.statements[0]), format!("{:?}", after.statements[0]));
+        assert_eq!(format!("{:?}", before.statements[2].id), format!("{:?}", after.statements[2].id));
+    }
+
+    #[test]
+    fn later_items_move_when_lines_are_added() {
+        let mut parser = IncrementalParser::new(SOURCE.to_string()).unwrap();
+        let reanalysis = edit(&mut parser, "    return a + b;\n", "    let c = a;\n\n    return c + b;\n");
+        assert_eq!(reanalysis.reparsed_items, 1);
+        assert_eq!(parser.program().unwrap().statements[2].span.line, 13);
+        edit(&mut parser, "\n\nfn twice", "fn twice");
+    }
+
+    // Ediciones que cambian tokens vecinos o el límite entre sentencias
+    #[test]
+    fn edits_that_reach_neighbouring_tokens() {
+        let mut parser = IncrementalParser::new(SOURCE.to_string()).unwrap();
+        edit(&mut parser, "add(2, 3)", "add(2, 3.5)");
+        edit(&mut parser, "3.5", "3");
+        edit(&mut parser, "return a", "return aa");
+        edit(&mut parser, "print(1); }", "print(1); } else { print(2); }");
+        edit(&mut parser, "}\n\n/* comentario */", "}\nfn three() -> int { return 3; }\n/* comentario */");
+        edit(&mut parser, "}\nfn three", "} fn three");
+        edit(&mut parser, "/* comentario */", "// comentario */");
+        edit(&mut parser, "// comentario */", "/* comentario */");
+    }
+
+    // Un comentario abierto se traga lo que sigue hasta cerrarse (los
+    // comentarios se anidan)
+    #[test]
+    fn opening_a_comment_swallows_what_follows() {
+        let mut parser = IncrementalParser::new(SOURCE.to_string()).unwrap();
+        let start = offset_of(SOURCE, "fn twice");
+        assert!(parser.edit(start..start, "/* ").is_err());
+        edit(&mut parser, "return y;\n}\n", "return y;\n} */\n");
+        assert_eq!(parser.program().unwrap().statements.len(), 2);
+        assert!(parser.edit(start..start + 3, "").is_err());
+        edit(&mut parser, "} */\n", "}\n");
+        assert_eq!(parser.source(), SOURCE);
+    }
+
+    // Una sentencia reanalizada puede absorber las que la seguían
+    #[test]
+    fn reparsed_items_can_swallow_the_following_ones() {
+        let mut parser = IncrementalParser::new("1;\n2;\n3;\n".to_string()).unwrap();
+        edit(&mut parser, "2;", "2 +");
+        assert_eq!(parser.program().unwrap().statements.len(), 2);
+        edit(&mut parser, "2 +", "2;");
+        edit(&mut parser, "1;", "1 +");
+    }
+
+    #[test]
+    fn errors_leave_the_source_edited_and_recover() {
+        let mut parser = IncrementalParser::new(SOURCE.to_string()).unwrap();
+        let start = offset_of(SOURCE, "x * 2");
+        assert!(parser.edit(start..start + 1, "\"x").is_err());
+        assert!(parser.program().is_none());
+        assert!(parser.edit(start..start + 2, "x").is_ok());
+        assert_eq!(parser.source(), SOURCE);
+        assert!(parser.edit(0..SOURCE.len() + 1, "").is_err());
+        let start = offset_of(SOURCE, "return y;");
+        assert!(parser.edit(start..start + 9, "return y").is_err());
+        assert!(parser.edit(start..start + 8, "return y;").is_ok());
+        assert_eq!(without_ids(&parser.program().unwrap()), without_ids(&parse(SOURCE).unwrap()));
+    }
+
+    // Ediciones pseudoaleatorias (xorshift con semilla fija) sobre el
+    // fuente válido, deshechas después: con o sin error, el resultado de
+    // cada paso es el mismo que analizando desde cero
+    #[test]
+    fn random_edits_match_a_full_parse() {
+        const PIECES: &[&str] = &[
+            "", " ", "\n", "\t", "x", "1", ".", "5", "+", ";", "(", ")", "{", "}", "/*", "*/", "//", "\"",
+            "fn f() {}", "let z = 1;", "else { }", "print(a);",
+        ];
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut random = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        let check = |parser: &IncrementalParser, edited: anyhow::Result<Reanalysis>| {
+            let full = parse(parser.source());
+            assert_eq!(edited.is_ok(), full.is_ok(), "{:?}", parser.source());
+            if let Ok(full) = full {
+                assert_eq!(without_ids(&parser.program().unwrap()), without_ids(&full), "{:?}", parser.source());
+            }
+        };
+        for source in [SOURCE, "let a = 1;\nif (a > 0) { print(3); }\nprint(a);\na = 4;\n2 + a;\na;\n"] {
+            let chars: Vec<char> = source.chars().collect();
+            let mut parser = IncrementalParser::new(source.to_string()).unwrap();
+            for _ in 0..2000 {
+                let start = random(chars.len() + 1);
+                let end = (start + random(4)).min(chars.len());
+                let text = PIECES[random(PIECES.len())];
+                let edited = parser.edit(start..end, text);
+                check(&parser, edited);
+                let removed: String = chars[start..end].iter().collect();
+                let undone = parser.edit(start..start + text.chars().count(), &removed);
+                assert_eq!(parser.source(), source);
+                check(&parser, undone);
+            }
+        }
+    }
+}
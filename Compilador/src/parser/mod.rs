@@ -1,62 +1,249 @@
-use crate::lexer::{token::Token, Lexer};
-use crate::parser::ast::{Expr, Program, Stmt, Type};
+pub mod ast;
+pub mod incremental;
+pub mod printer;
+
+use crate::lexer::{token::{Span, Token}, LexedToken, Lexer};
+use crate::parser::ast::{Expr, ExprKind, NodeId, Program, Stmt, StmtKind, Type};
 use anyhow::Result;
+use std::rc::Rc;
+
+// Límite de anidamiento del AST por defecto; evita desbordar la pila del
+// compilador con entradas como miles de '(' o de bloques seguidos. Las
+// fases siguientes recorren el árbol recursivamente, así que limitar aquí
+// su profundidad las protege también a ellas.
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+// Los operadores prefijos se aplican antes que cualquier binario
+const PREFIX_PRECEDENCE: u8 = 6;
+
+// De dónde saca el parser los tokens: del lexer a medida que avanza o de
+// una lista ya analizada, a partir de un índice (ver incremental)
+enum TokenSource {
+    Lexer(Lexer),
+    List(Rc<[LexedToken]>, usize),
+}
 
 pub struct Parser {
-    lexer: Lexer,
+    tokens: TokenSource,
     cur_token: Token,
+    cur_span: Span,
+    next_id: usize,
+    depth: usize,
+    max_depth: usize,
+    // Delimitadores abiertos y la línea donde se abrieron
+    delimiters: Vec<(Token, usize)>,
+    // Token siguiente ya leído del lexer por peek_token
+    peeked: Option<(Token, Span)>,
 }
 
 impl Parser {
-    pub fn new(lexer: Lexer) -> Self {
-        let cur_token = lexer.clone().next_token().unwrap();
-        Parser { lexer, cur_token }
+    pub fn new(lexer: Lexer) -> Result<Self> {
+        Self::with_source(TokenSource::Lexer(lexer), 0)
+    }
+
+    // Analiza `tokens` desde el índice `start`, que debe ser el principio
+    // de una sentencia de nivel superior; los NodeId empiezan en `first_id`
+    pub fn from_tokens(tokens: Rc<[LexedToken]>, start: usize, first_id: usize) -> Result<Self> {
+        Self::with_source(TokenSource::List(tokens, start), first_id)
+    }
+
+    fn with_source(tokens: TokenSource, first_id: usize) -> Result<Self> {
+        let mut parser = Parser {
+            tokens,
+            cur_token: Token::Eof,
+            cur_span: Span::default(),
+            next_id: first_id,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            delimiters: Vec::new(),
+            peeked: None,
+        };
+        parser.next_token()?;
+        Ok(parser)
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
+
+    // Índice del token actual en la lista de from_tokens
+    pub fn position(&self) -> usize {
+        match &self.tokens {
+            TokenSource::List(_, next) => next - 1 - self.peeked.is_some() as usize,
+            TokenSource::Lexer(_) => unreachable!("posición de un parser sin lista de tokens"),
+        }
+    }
+
+    pub fn at_eof(&self) -> bool {
+        self.cur_token == Token::Eof
+    }
+
+    fn new_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn expr(&mut self, kind: ExprKind, span: Span) -> Expr {
+        Expr { id: self.new_id(), kind, span }
+    }
+
+    fn stmt(&mut self, kind: StmtKind, span: Span) -> Stmt {
+        Stmt { id: self.new_id(), kind, span }
+    }
+
+    // Error situado en el token actual
+    fn error(&self, message: String) -> anyhow::Error {
+        anyhow::anyhow!("error en {}: {}", self.cur_span, message)
     }
 
     fn next_token(&mut self) -> Result<()> {
-        self.cur_token = self.lexer.next_token()?;
+        (self.cur_token, self.cur_span) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.lex_token()?,
+        };
+        self.track_delimiter()
+    }
+
+    // Mira el token siguiente sin avanzar
+    fn peek_token(&mut self) -> Result<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lex_token()?);
+        }
+        Ok(&self.peeked.as_ref().unwrap().0)
+    }
+
+    fn lex_token(&mut self) -> Result<(Token, Span)> {
+        let lexed = match &mut self.tokens {
+            TokenSource::Lexer(lexer) => lexer.next_lexed()?,
+            TokenSource::List(tokens, next) => {
+                // Tras el Eof final se sigue devolviendo Eof, como el lexer
+                let lexed = tokens[(*next).min(tokens.len() - 1)].clone();
+                *next += 1;
+                lexed
+            }
+        };
+        Ok((lexed.token, lexed.span))
+    }
+
+    // Comprueba que (), {} y [] estén equilibrados, para señalar el
+    // delimitador sin cerrar en vez de un error genérico al llegar a EOF.
+    fn track_delimiter(&mut self) -> Result<()> {
+        let line = self.cur_span.line;
+        match &self.cur_token {
+            Token::LParen | Token::LBrace | Token::LBracket => {
+                self.delimiters.push((self.cur_token.clone(), line));
+            }
+            Token::RParen | Token::RBrace | Token::RBracket => match self.delimiters.pop() {
+                Some((open, _)) if closing_delimiter(&open) == Some(&self.cur_token) => {}
+                Some((open, open_line)) => {
+                    return Err(anyhow::anyhow!(
+                        "'{}' sin cerrar abierto en línea {}",
+                        delimiter_char(&open),
+                        open_line
+                    ));
+                }
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "'{}' sin abrir en línea {}",
+                        delimiter_char(&self.cur_token),
+                        line
+                    ));
+                }
+            },
+            Token::Eof => {
+                if let Some((open, open_line)) = self.delimiters.last() {
+                    return Err(anyhow::anyhow!(
+                        "'{}' sin cerrar abierto en línea {}",
+                        delimiter_char(open),
+                        open_line
+                    ));
+                }
+            }
+            _ => {}
+        }
         Ok(())
     }
 
     pub fn parse_program(&mut self) -> Result<Program> {
         let mut statements = Vec::new();
 
-        while self.cur_token != Token::Eof {
-            if let Some(stmt) = self.parse_statement()? {
-                statements.push(stmt);
-            }
-            self.next_token()?;
+        while !self.at_eof() {
+            statements.extend(self.parse_item()?);
         }
 
         Ok(Program { statements })
     }
 
+    // Una sentencia de nivel superior; deja el parser en el primer token
+    // de la siguiente
+    pub fn parse_item(&mut self) -> Result<Option<Stmt>> {
+        let stmt = self.parse_statement()?;
+        self.next_token()?;
+        Ok(stmt)
+    }
+
     fn parse_statement(&mut self) -> Result<Option<Stmt>> {
+        self.nested(Self::parse_statement_inner)
+    }
+
+    fn parse_statement_inner(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         match &self.cur_token {
-            Token::Let => self.parse_let_statement(),
+            Token::Let | Token::Const => self.parse_let_statement(),
             Token::If => self.parse_if_statement(),
             Token::While => self.parse_while_statement(),
             Token::For => self.parse_for_statement(),
             Token::Fn => self.parse_function_statement(),
+            Token::Enum => self.parse_enum_statement(),
+            Token::At => self.parse_attributed_function(),
             Token::Return => self.parse_return_statement(),
             Token::Print => self.parse_print_statement(),
             Token::Ident(_) => {
-                // Podría ser asignación o expresión
-                let expr = self.parse_expression(0)?;
+                let stmt = self.parse_assign_or_expression()?;
                 self.expect_token(Token::Semicolon)?;
-                Ok(Some(Stmt::Expression(expr)))
+                Ok(Some(stmt))
             }
             Token::Eof => Ok(None),
             _ => {
                 let expr = self.parse_expression(0)?;
                 self.expect_token(Token::Semicolon)?;
-                Ok(Some(Stmt::Expression(expr)))
+                Ok(Some(self.stmt(StmtKind::Expression(expr), span)))
             }
         }
     }
 
+    // Asignación o expresión, sin el terminador: lo comprueba quien llama
+    // (';' en una sentencia, ')' en el incremento de un for)
+    fn parse_assign_or_expression(&mut self) -> Result<Stmt> {
+        let span = self.cur_span;
+        let expr = self.parse_expression(0)?;
+        if self.cur_token != Token::Eq {
+            return Ok(self.stmt(StmtKind::Expression(expr), span));
+        }
+        let target = match expr.kind {
+            ExprKind::Ident(name) => name,
+            _ => return Err(self.error("Destino de asignación no válido".to_string())),
+        };
+        self.next_token()?; // skip '='
+        let value = self.parse_expression(0)?;
+        Ok(self.stmt(StmtKind::Assign { target, value }, span))
+    }
+
     fn parse_let_statement(&mut self) -> Result<Option<Stmt>> {
-        self.next_token()?; // skip 'let'
+        let span = self.cur_span;
+        let mutable = self.cur_token == Token::Let;
+        let keyword = if mutable { "let" } else { "const" };
+        self.next_token()?; // skip 'let' / 'const'
+
+        if mutable && matches!(self.cur_token, Token::LBracket | Token::LParen) {
+            return self.parse_let_pattern(span);
+        }
         
         if let Token::Ident(name) = &self.cur_token {
             let var_name = name.clone();
@@ -69,22 +256,65 @@ impl Parser {
                 self.next_token()?;
             }
             
-            self.expect_token(Token::Eq)?;
-            self.next_token()?;
-            let expr = self.parse_expression(0)?;
-            self.expect_token(Token::Semicolon)?;
+            if !mutable && self.cur_token != Token::Eq {
+                return Err(self.error(format!("la constante '{}' necesita un valor inicial", var_name)));
+            }
+            // `let x: T;` toma el valor por defecto del tipo
+            let value = if type_annotation.is_some() && self.cur_token == Token::Semicolon {
+                None
+            } else {
+                self.expect_token(Token::Eq)?;
+                self.next_token()?;
+                let expr = self.parse_expression(0)?;
+                self.expect_token(Token::Semicolon)?;
+                Some(expr)
+            };
             
-            Ok(Some(Stmt::Let {
+            Ok(Some(self.stmt(StmtKind::Let {
                 name: var_name,
                 type_annotation,
-                value: expr,
-            }))
+                value,
+                mutable,
+            }, span)))
         } else {
-            Err(anyhow::anyhow!("Se esperaba identificador después de 'let'"))
+            Err(self.error(format!("Se esperaba identificador después de '{}'", keyword)))
+        }
+    }
+
+    // let [a, b, ...] = expr; o let (a, b, ...) = expr; con el token actual
+    // en '[' o '('
+    fn parse_let_pattern(&mut self, span: Span) -> Result<Option<Stmt>> {
+        let tuple = self.cur_token == Token::LParen;
+        let close = if tuple { Token::RParen } else { Token::RBracket };
+        self.next_token()?; // skip '[' / '('
+        let mut names = Vec::new();
+        while self.cur_token != close {
+            match &self.cur_token {
+                Token::Ident(name) => names.push(name.clone()),
+                _ => return Err(self.error("Se esperaba identificador en el patrón".to_string())),
+            }
+            self.next_token()?;
+            if self.cur_token == Token::Comma {
+                self.next_token()?;
+            }
         }
+        self.next_token()?; // skip ']' / ')'
+
+        self.expect_token(Token::Eq)?;
+        self.next_token()?;
+        let value = self.parse_expression(0)?;
+        self.expect_token(Token::Semicolon)?;
+
+        let kind = if tuple {
+            StmtKind::LetTuple { names, value }
+        } else {
+            StmtKind::LetArray { names, value }
+        };
+        Ok(Some(self.stmt(kind, span)))
     }
 
     fn parse_if_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         self.next_token()?; // skip 'if'
         self.expect_token(Token::LParen)?;
         self.next_token()?;
@@ -94,21 +324,29 @@ impl Parser {
         
         let then_block = self.parse_block()?;
         
-        let else_block = if self.cur_token == Token::Else {
+        // parse_block termina sobre la '}', así que el else hay que mirarlo
+        // por adelantado. `else if` se anida como única sentencia del else.
+        let else_block = if self.peek_token()? == &Token::Else {
             self.next_token()?;
-            Some(self.parse_block()?)
+            self.next_token()?;
+            if self.cur_token == Token::If {
+                Some(self.nested(Self::parse_if_statement)?.into_iter().collect())
+            } else {
+                Some(self.parse_block()?)
+            }
         } else {
             None
         };
         
-        Ok(Some(Stmt::If {
+        Ok(Some(self.stmt(StmtKind::If {
             condition,
             then_block,
             else_block,
-        }))
+        }, span)))
     }
 
     fn parse_while_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         self.next_token()?; // skip 'while'
         self.expect_token(Token::LParen)?;
         self.next_token()?;
@@ -118,36 +356,91 @@ impl Parser {
         
         let body = self.parse_block()?;
         
-        Ok(Some(Stmt::While { condition, body }))
+        Ok(Some(self.stmt(StmtKind::While { condition, body }, span)))
     }
 
     fn parse_for_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         self.next_token()?; // skip 'for'
         self.expect_token(Token::LParen)?;
         self.next_token()?;
         
-        let init = Box::new(self.parse_statement()?.unwrap());
+        let init = match self.parse_statement()? {
+            Some(init) => Box::new(init),
+            None => return Err(self.error("Se esperaba la inicialización del for".to_string())),
+        };
         self.next_token()?;
         
         let condition = self.parse_expression(0)?;
         self.expect_token(Token::Semicolon)?;
         self.next_token()?;
         
-        let increment = Box::new(self.parse_statement()?.unwrap());
+        let increment = Box::new(self.parse_assign_or_expression()?);
         self.expect_token(Token::RParen)?;
         self.next_token()?;
         
         let body = self.parse_block()?;
         
-        Ok(Some(Stmt::For {
+        Ok(Some(self.stmt(StmtKind::For {
             init,
             condition,
             increment,
             body,
-        }))
+        }, span)))
+    }
+
+    // @attr ... fn nombre(...)
+    fn parse_attributed_function(&mut self) -> Result<Option<Stmt>> {
+        let mut names = Vec::new();
+        while self.cur_token == Token::At {
+            self.next_token()?; // skip '@'
+            match &self.cur_token {
+                Token::Ident(name) => names.push(name.clone()),
+                _ => return Err(self.error("Se esperaba nombre de atributo después de '@'".to_string())),
+            }
+            self.next_token()?;
+        }
+        if self.cur_token != Token::Fn {
+            return Err(self.error("Los atributos solo pueden preceder a una función".to_string()));
+        }
+
+        let mut stmt = self.parse_function_statement()?;
+        if let Some(Stmt { kind: StmtKind::Function { attributes, .. }, .. }) = &mut stmt {
+            *attributes = names;
+        }
+        Ok(stmt)
+    }
+
+    fn parse_enum_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
+        self.next_token()?; // skip 'enum'
+        let name = match &self.cur_token {
+            Token::Ident(name) => name.clone(),
+            _ => return Err(self.error("Se esperaba el nombre del enum".to_string())),
+        };
+        self.next_token()?;
+        self.expect_token(Token::LBrace)?;
+        self.next_token()?;
+
+        let mut variants = Vec::new();
+        while self.cur_token != Token::RBrace {
+            match &self.cur_token {
+                Token::Ident(variant) => variants.push(variant.clone()),
+                _ => return Err(self.error("Se esperaba el nombre de una variante".to_string())),
+            }
+            self.next_token()?;
+            if self.cur_token == Token::Comma {
+                self.next_token()?;
+            } else {
+                self.expect_token(Token::RBrace)?;
+            }
+        }
+
+        Ok(Some(self.stmt(StmtKind::Enum { name, variants }, span)))
     }
 
     fn parse_function_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         self.next_token()?; // skip 'fn'
         
         if let Token::Ident(name) = &self.cur_token {
@@ -157,14 +450,35 @@ impl Parser {
             self.next_token()?;
             
             let mut params = Vec::new();
+            let mut defaults = Vec::new();
             while self.cur_token != Token::RParen {
                 if let Token::Ident(param_name) = &self.cur_token {
                     let name = param_name.clone();
                     self.next_token()?;
                     self.expect_token(Token::Colon)?;
                     self.next_token()?;
-                    let param_type = self.parse_type()?;
+                    let param_type = if self.cur_token == Token::Ellipsis {
+                        self.next_token()?; // skip '...'
+                        Type::Variadic(Box::new(self.parse_type()?))
+                    } else {
+                        self.parse_type()?
+                    };
+                    if params.last().is_some_and(|(_, t)| matches!(t, Type::Variadic(_))) {
+                        return Err(self.error("el parámetro variádico debe ser el último".to_string()));
+                    }
+                    self.next_token()?; // skip type
+
+                    let default = if self.cur_token == Token::Eq {
+                        self.next_token()?; // skip '='
+                        Some(self.parse_expression(0)?)
+                    } else {
+                        None
+                    };
+                    if default.is_none() && defaults.iter().any(Option::is_some) {
+                        return Err(self.error(format!("el parámetro '{}' necesita un valor por defecto", name)));
+                    }
                     params.push((name, param_type));
+                    defaults.push(default);
                     
                     if self.cur_token == Token::Comma {
                         self.next_token()?;
@@ -177,40 +491,46 @@ impl Parser {
             self.expect_token(Token::RParen)?;
             self.next_token()?;
             
-            let return_type = if self.cur_token == Token::Colon {
-                self.next_token()?;
-                self.next_token()?;
-                self.parse_type()?
+            // `-> tipo`; se sigue aceptando la forma antigua `: tipo`
+            let return_type = if self.cur_token == Token::Arrow || self.cur_token == Token::Colon {
+                self.next_token()?; // skip '->' / ':'
+                let return_type = self.parse_type()?;
+                self.next_token()?; // skip type
+                Some(return_type)
             } else {
-                Type::Void
+                None
             };
             
             let body = self.parse_block()?;
             
-            Ok(Some(Stmt::Function {
+            Ok(Some(self.stmt(StmtKind::Function {
                 name: func_name,
                 params,
+                defaults,
                 return_type,
                 body,
-            }))
+                attributes: Vec::new(),
+            }, span)))
         } else {
-            Err(anyhow::anyhow!("Se esperaba nombre de función"))
+            Err(self.error("Se esperaba nombre de función".to_string()))
         }
     }
 
     fn parse_return_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         self.next_token()?; // skip 'return'
         
         if self.cur_token == Token::Semicolon {
-            Ok(Some(Stmt::Return(None)))
+            Ok(Some(self.stmt(StmtKind::Return(None), span)))
         } else {
             let expr = self.parse_expression(0)?;
             self.expect_token(Token::Semicolon)?;
-            Ok(Some(Stmt::Return(Some(expr))))
+            Ok(Some(self.stmt(StmtKind::Return(Some(expr)), span)))
         }
     }
 
     fn parse_print_statement(&mut self) -> Result<Option<Stmt>> {
+        let span = self.cur_span;
         self.next_token()?; // skip 'print'
         self.expect_token(Token::LParen)?;
         self.next_token()?;
@@ -218,10 +538,14 @@ impl Parser {
         self.expect_token(Token::RParen)?;
         self.next_token()?;
         self.expect_token(Token::Semicolon)?;
-        Ok(Some(Stmt::Print(expr)))
+        Ok(Some(self.stmt(StmtKind::Print(expr), span)))
     }
 
     fn parse_block(&mut self) -> Result<Vec<Stmt>> {
+        self.nested(Self::parse_block_inner)
+    }
+
+    fn parse_block_inner(&mut self) -> Result<Vec<Stmt>> {
         self.expect_token(Token::LBrace)?;
         self.next_token()?;
         
@@ -238,113 +562,220 @@ impl Parser {
     }
 
     fn parse_type(&mut self) -> Result<Type> {
+        self.nested(Self::parse_type_inner)
+    }
+
+    fn parse_type_inner(&mut self) -> Result<Type> {
         match &self.cur_token {
             Token::Ident(name) => {
                 match name.as_str() {
                     "int" => Ok(Type::Int),
+                    "float" => Ok(Type::Float),
                     "bool" => Ok(Type::Bool),
                     "string" => Ok(Type::String),
+                    "char" => Ok(Type::Char),
                     "void" => Ok(Type::Void),
-                    _ => Err(anyhow::anyhow!("Tipo desconocido: {}", name)),
+                    _ => Ok(Type::Named(name.clone())),
                 }
             }
             Token::LBracket => {
                 self.next_token()?; // skip '['
                 let inner_type = self.parse_type()?;
+                self.next_token()?;
                 self.expect_token(Token::RBracket)?;
                 Ok(Type::Array(Box::new(inner_type)))
             }
-            _ => Err(anyhow::anyhow!("Se esperaba tipo")),
+            Token::LParen => {
+                self.next_token()?; // skip '('
+                let mut types = vec![self.parse_type()?];
+                self.next_token()?;
+                while self.cur_token == Token::Comma {
+                    self.next_token()?;
+                    types.push(self.parse_type()?);
+                    self.next_token()?;
+                }
+                self.expect_token(Token::RParen)?;
+                if types.len() < 2 {
+                    return Err(self.error("Una tupla necesita al menos dos elementos".to_string()));
+                }
+                Ok(Type::Tuple(types))
+            }
+            _ => Err(self.error("Se esperaba tipo".to_string())),
         }
     }
 
     fn parse_expression(&mut self, precedence: u8) -> Result<Expr> {
-        let mut left = self.parse_primary()?;
+        self.nested(|parser| parser.parse_expression_inner(precedence))
+    }
+
+    // Baja un nivel de anidamiento
+    fn nest(&mut self) -> Result<()> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(format!("anidamiento demasiado profundo (máximo {} niveles)", self.max_depth)));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Analiza un nivel más abajo. Al volver se restaura la profundidad de
+    // antes, que los bucles de operadores y sufijos también aumentan: cada
+    // vuelta envuelve el árbol en un nodo más.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let depth = self.depth;
+        let result = self.nest().and_then(|_| parse(self));
+        self.depth = depth;
+        result
+    }
+
+    fn parse_expression_inner(&mut self, precedence: u8) -> Result<Expr> {
+        let mut left = self.parse_postfix()?;
         
         while precedence < self.current_precedence() {
-            left = match self.cur_token {
-                Token::Plus | Token::Minus | Token::Star | Token::Slash |
-                Token::EqEq | Token::NotEq | Token::Lt | Token::Gt |
-                Token::LtEq | Token::GtEq => {
-                    let op = self.current_op().unwrap();
-                    self.next_token()?;
-                    let right = self.parse_expression(self.current_precedence())?;
-                    Expr::Infix {
-                        left: Box::new(left),
-                        op,
-                        right: Box::new(right),
-                    }
-                }
+            // Asociatividad por la izquierda: el operando derecho solo
+            // agrupa operadores de mayor precedencia que este
+            let op_precedence = self.current_precedence();
+            let op = self.current_op().unwrap();
+            self.nest()?;
+            self.next_token()?;
+            let right = self.parse_expression(op_precedence)?;
+            let span = left.span;
+            left = self.expr(ExprKind::Infix {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            }, span);
+        }
+
+        // El ternario tiene la menor precedencia de todas; la rama else se
+        // analiza con precedencia 0, así que a ? b : c ? d : e agrupa por
+        // la derecha.
+        if precedence == 0 && self.cur_token == Token::Question {
+            self.nest()?;
+            self.next_token()?; // skip '?'
+            let then_expr = self.parse_expression(0)?;
+            self.expect_token(Token::Colon)?;
+            self.next_token()?;
+            let else_expr = self.parse_expression(0)?;
+            let span = left.span;
+            left = self.expr(ExprKind::Ternary {
+                condition: Box::new(left),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            }, span);
+        }
+        
+        Ok(left)
+    }
+
+    // Llamadas e indexación se aplican a cualquier expresión: f()(), g()[0].
+    // Si el resultado es invocable lo decide el análisis semántico.
+    fn parse_postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            let span = expr.span;
+            if matches!(self.cur_token, Token::LParen | Token::LBracket | Token::Dot) {
+                self.nest()?;
+            }
+            expr = match self.cur_token {
                 Token::LParen => {
-                    self.next_token()?; // skip '('
-                    let mut args = Vec::new();
-                    while self.cur_token != Token::RParen {
-                        args.push(self.parse_expression(0)?);
-                        if self.cur_token == Token::Comma {
-                            self.next_token()?;
-                        }
-                    }
-                    self.expect_token(Token::RParen)?;
-                    if let Expr::Ident(name) = left {
-                        Expr::Call { function: name, args }
-                    } else {
-                        return Err(anyhow::anyhow!("Solo se pueden llamar funciones"));
+                    let args = self.parse_call_args()?;
+                    match expr.kind {
+                        ExprKind::Ident(name) => self.expr(ExprKind::Call { function: name, args }, span),
+                        _ => self.expr(ExprKind::CallExpr {
+                            callee: Box::new(expr),
+                            args,
+                        }, span),
                     }
                 }
                 Token::LBracket => {
                     self.next_token()?; // skip '['
                     let index = self.parse_expression(0)?;
                     self.expect_token(Token::RBracket)?;
-                    Expr::ArrayIndex {
-                        array: Box::new(left),
+                    self.next_token()?;
+                    self.expr(ExprKind::ArrayIndex {
+                        array: Box::new(expr),
                         index: Box::new(index),
+                    }, span)
+                }
+                Token::Dot => {
+                    self.next_token()?; // skip '.'
+                    if let Token::Number(index) = self.cur_token {
+                        let index = usize::try_from(index)
+                            .map_err(|_| self.error("Índice de tupla no válido".to_string()))?;
+                        self.next_token()?;
+                        expr = self.expr(ExprKind::TupleIndex { tuple: Box::new(expr), index }, span);
+                        continue;
                     }
+                    let enum_name = match expr.kind {
+                        ExprKind::Ident(name) => name,
+                        _ => return Err(self.error("Solo se puede usar '.' tras el nombre de un enum".to_string())),
+                    };
+                    let variant = match &self.cur_token {
+                        Token::Ident(variant) => variant.clone(),
+                        _ => return Err(self.error("Se esperaba el nombre de una variante tras '.'".to_string())),
+                    };
+                    self.next_token()?;
+                    self.expr(ExprKind::EnumVariant { enum_name, variant }, span)
                 }
-                _ => break,
+                _ => return Ok(expr),
             };
         }
-        
-        Ok(left)
+    }
+
+    fn parse_call_args(&mut self) -> Result<Vec<Expr>> {
+        self.next_token()?; // skip '('
+        let mut args = Vec::new();
+        while self.cur_token != Token::RParen {
+            args.push(self.parse_expression(0)?);
+            if self.cur_token == Token::Comma {
+                self.next_token()?;
+            }
+        }
+        self.expect_token(Token::RParen)?;
+        self.next_token()?;
+        Ok(args)
     }
 
     fn parse_primary(&mut self) -> Result<Expr> {
-        match &self.cur_token {
+        let span = self.cur_span;
+        match self.cur_token.clone() {
             Token::Number(n) => {
                 self.next_token()?;
-                Ok(Expr::Number(*n))
+                Ok(self.expr(ExprKind::Number(n), span))
+            }
+            Token::Float(f) => {
+                self.next_token()?;
+                Ok(self.expr(ExprKind::Float(f), span))
             }
             Token::True => {
                 self.next_token()?;
-                Ok(Expr::Boolean(true))
+                Ok(self.expr(ExprKind::Boolean(true), span))
             }
             Token::False => {
                 self.next_token()?;
-                Ok(Expr::Boolean(false))
+                Ok(self.expr(ExprKind::Boolean(false), span))
             }
             Token::String(s) => {
-                let str_val = s.clone();
                 self.next_token()?;
-                Ok(Expr::String(str_val))
+                Ok(self.expr(ExprKind::String(s), span))
             }
-            Token::Ident(s) => {
-                let ident = s.clone();
+            Token::Char(c) => {
                 self.next_token()?;
-                if self.cur_token == Token::LParen {
-                    // Es una llamada a función
-                    self.next_token()?; // skip '('
-                    let mut args = Vec::new();
-                    while self.cur_token != Token::RParen {
-                        args.push(self.parse_expression(0)?);
-                        if self.cur_token == Token::Comma {
-                            self.next_token()?;
-                        }
-                    }
-                    self.expect_token(Token::RParen)?;
-                    self.next_token()?;
-                    Ok(Expr::Call { function: ident, args })
-                } else {
-                    Ok(Expr::Ident(ident))
-                }
+                Ok(self.expr(ExprKind::Char(c), span))
+            }
+            Token::Ident(ident) => {
+                self.next_token()?;
+                Ok(self.expr(ExprKind::Ident(ident), span))
+            }
+            Token::Minus | Token::Bang => {
+                let op = if self.cur_token == Token::Minus { "-" } else { "!" };
+                self.next_token()?;
+                let operand = self.parse_expression(PREFIX_PRECEDENCE)?;
+                Ok(self.expr(ExprKind::Prefix {
+                    op: op.to_string(),
+                    operand: Box::new(operand),
+                }, span))
             }
             Token::LBracket => {
                 self.next_token()?; // skip '['
@@ -357,16 +788,40 @@ impl Parser {
                 }
                 self.expect_token(Token::RBracket)?;
                 self.next_token()?;
-                Ok(Expr::ArrayLiteral(elements))
+                Ok(self.expr(ExprKind::ArrayLiteral(elements), span))
             }
             Token::LParen => {
                 self.next_token()?; // skip '('
                 let expr = self.parse_expression(0)?;
+                if self.cur_token == Token::Comma {
+                    let mut elements = vec![expr];
+                    while self.cur_token == Token::Comma {
+                        self.next_token()?;
+                        elements.push(self.parse_expression(0)?);
+                    }
+                    self.expect_token(Token::RParen)?;
+                    self.next_token()?;
+                    return Ok(self.expr(ExprKind::Tuple(elements), span));
+                }
                 self.expect_token(Token::RParen)?;
                 self.next_token()?;
-                Ok(Expr::Grouped(Box::new(expr)))
+                Ok(self.expr(ExprKind::Grouped(Box::new(expr)), span))
             }
-            _ => Err(anyhow::anyhow!("Expresión no válida: {:?}", self.cur_token)),
+            Token::New => {
+                self.next_token()?; // skip 'new'
+                let type_ = self.parse_type()?;
+                self.next_token()?;
+                self.expect_token(Token::LParen)?;
+                self.next_token()?;
+                let size = self.parse_expression(0)?;
+                self.expect_token(Token::RParen)?;
+                self.next_token()?;
+                Ok(self.expr(ExprKind::New {
+                    type_,
+                    size: Box::new(size),
+                }, span))
+            }
+            _ => Err(self.error(format!("Expresión no válida: {:?}", self.cur_token))),
         }
     }
 
@@ -374,11 +829,11 @@ impl Parser {
         if self.cur_token == expected {
             Ok(())
         } else {
-            Err(anyhow::anyhow!(
+            Err(self.error(format!(
                 "Se esperaba {:?}, encontrado {:?}",
                 expected,
                 self.cur_token
-            ))
+            )))
         }
     }
 
@@ -388,24 +843,284 @@ impl Parser {
             Token::Minus => Some("-".to_string()),
             Token::Star => Some("*".to_string()),
             Token::Slash => Some("/".to_string()),
+            Token::Percent => Some("%".to_string()),
             Token::EqEq => Some("==".to_string()),
             Token::NotEq => Some("!=".to_string()),
             Token::Lt => Some("<".to_string()),
             Token::Gt => Some(">".to_string()),
             Token::LtEq => Some("<=".to_string()),
             Token::GtEq => Some(">=".to_string()),
+            Token::AndAnd => Some("&&".to_string()),
+            Token::OrOr => Some("||".to_string()),
             _ => None,
         }
     }
 
     fn current_precedence(&self) -> u8 {
         match &self.cur_token {
-            Token::EqEq | Token::NotEq | Token::Lt | Token::Gt | Token::LtEq | Token::GtEq => 1,
-            Token::Plus | Token::Minus => 2,
-            Token::Star | Token::Slash => 3,
+            Token::OrOr => 1,
+            Token::AndAnd => 2,
+            Token::EqEq | Token::NotEq | Token::Lt | Token::Gt | Token::LtEq | Token::GtEq => 3,
+            Token::Plus | Token::Minus => 4,
+            Token::Star | Token::Slash | Token::Percent => 5,
             _ => 0,
         }
     }
 }
 
+fn closing_delimiter(open: &Token) -> Option<&'static Token> {
+    match open {
+        Token::LParen => Some(&Token::RParen),
+        Token::LBrace => Some(&Token::RBrace),
+        Token::LBracket => Some(&Token::RBracket),
+        _ => None,
+    }
+}
+
+fn delimiter_char(token: &Token) -> char {
+    match token {
+        Token::LParen => '(',
+        Token::RParen => ')',
+        Token::LBrace => '{',
+        Token::RBrace => '}',
+        Token::LBracket => '[',
+        Token::RBracket => ']',
+        _ => '?',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use crate::lexer::Lexer;
+    use crate::parser::ast::{Expr, ExprKind, StmtKind, Type};
+    use crate::optimizer::{OptLevel, Optimizer};
+    use crate::parser::printer::format_program;
+    use crate::testing::{build_ir, parse, with_compiler_stack};
+
+    // Ids de todos los nodos, en el orden en que aparecen en el AST
+    fn node_ids(source: &str) -> Vec<usize> {
+        let debug = format!("{:?}", parse(source).unwrap());
+        debug
+            .split("NodeId(")
+            .skip(1)
+            .map(|rest| rest[..rest.find(')').unwrap()].parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn node_ids_are_unique_and_repeatable() {
+        let source = "fn f(a: int = 1) -> int { return a * 2; } \
+                      fn main() { let x = f(f()); for (let i = 0; i < x; i = i + 1) { print(i); } }";
+        let ids = node_ids(source);
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+        assert_eq!(node_ids(source), ids);
+    }
+
+    fn nesting_error(source: &str) -> bool {
+        parse(source).is_err_and(|err| err.to_string().contains("anidamiento demasiado profundo"))
+    }
 
+    #[test]
+    fn deep_nesting_is_an_error_not_a_stack_overflow() {
+        let sources = [
+            format!("fn main() {{ let a = {}1{}; }}", "(".repeat(50_000), ")".repeat(50_000)),
+            format!("fn main() {{ let a = 1{}; }}", "[0]".repeat(50_000)),
+            format!("fn main() {{ let a = f{}; }}", "()".repeat(50_000)),
+            format!("fn main() {{ let a = 1{}; }}", " + 1".repeat(50_000)),
+            format!("fn main() {{ let a = {}0; }}", "true ? 1 : ".repeat(50_000)),
+            format!("fn main() {{ let a = {}1; }}", "-".repeat(50_000)),
+            format!("fn main() {{ {}{} }}", "if (true) { ".repeat(20_000), "}".repeat(20_000)),
+            format!("fn main() {{ if (true) {{ }} {} }}", "else if (true) { } ".repeat(20_000)),
+            format!("fn main() {{ let a: {}int{} = 1; }}", "[".repeat(50_000), "]".repeat(50_000)),
+        ];
+        with_compiler_stack(move || {
+            for source in &sources {
+                assert!(nesting_error(source), "{}", &source[..40]);
+            }
+        });
+    }
+
+    // Diez mil '(' dan el error de anidamiento con el límite por defecto y
+    // con uno mayor; por debajo del límite elegido se acepta
+    #[test]
+    fn nesting_limit_is_configurable() {
+        let parens = |depth: usize| format!("fn main() {{ let a = {}1{}; }}", "(".repeat(depth), ")".repeat(depth));
+        let parse_with = |source: &str, max_depth: usize| {
+            Parser::new(Lexer::new(source.to_string()))
+                .map(|parser| parser.with_max_depth(max_depth))
+                .and_then(|mut parser| parser.parse_program())
+        };
+        with_compiler_stack(move || {
+            let deep = parens(10_000);
+            assert!(nesting_error(&deep));
+            let err = parse_with(&deep, 1_000).unwrap_err().to_string();
+            assert!(err.contains("anidamiento demasiado profundo (máximo 1000 niveles)"), "{}", err);
+
+            assert!(parse_with(&parens(5), 10).is_ok());
+            assert!(parse_with(&parens(5), 6).is_err());
+            assert!(parse(&parens(500)).is_err());
+            assert!(parse_with(&parens(500), 1_000).is_ok());
+        });
+    }
+
+    // Lo que el parser acepta no puede desbordar las fases siguientes
+    #[test]
+    fn accepted_nesting_goes_through_every_phase() {
+        let sources = [
+            format!("fn main() -> int {{ let a = {}1{}; return a; }}", "(".repeat(240), ")".repeat(240)),
+            format!("fn main() -> int {{ let a = 1{}; return a; }}", " + 1".repeat(240)),
+            format!("fn main() -> int {{ {}print(7);{} return 0; }}", "if (true) { ".repeat(120), "}".repeat(120)),
+            format!("fn main() -> int {{ let a = [{}5{}]{}; return a; }}", "[".repeat(100), "]".repeat(100), "[0]".repeat(101)),
+        ];
+        with_compiler_stack(move || {
+            for source in &sources {
+                format_program(&parse(source).unwrap());
+                let mut program = build_ir(source);
+                Optimizer::with_level(OptLevel::O2).optimize(&mut program);
+            }
+        });
+    }
+
+    #[test]
+    fn let_destructures_tuples_and_arrays() {
+        let program = parse(
+            "fn main() { let (q, r) = divmod(7, 2); let [x, y] = pair; \
+             let t: ((int, bool), char) = ((1, true), 'a'); print(t.0.1); print((q)); }",
+        )
+        .unwrap();
+        let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+        assert!(matches!(&body[0].kind, StmtKind::LetTuple { names, .. } if names == &["q", "r"]));
+        assert!(matches!(&body[1].kind, StmtKind::LetArray { names, .. } if names == &["x", "y"]));
+        let StmtKind::Let { type_annotation: Some(type_), value: Some(value), .. } = &body[2].kind else {
+            unreachable!()
+        };
+        assert_eq!(type_, &Type::Tuple(vec![Type::Tuple(vec![Type::Int, Type::Bool]), Type::Char]));
+        assert!(matches!(&value.kind, ExprKind::Tuple(elements) if elements.len() == 2));
+        let StmtKind::Print(access) = &body[3].kind else { unreachable!() };
+        let ExprKind::TupleIndex { tuple, index: 1 } = &access.kind else { panic!("{:?}", access) };
+        assert!(matches!(&tuple.kind, ExprKind::TupleIndex { index: 0, .. }));
+        // Sin coma los paréntesis solo agrupan
+        let StmtKind::Print(grouped) = &body[4].kind else { unreachable!() };
+        assert!(matches!(&grouped.kind, ExprKind::Grouped(_)));
+
+        assert!(parse("fn f(p: (int)) {}").is_err());
+    }
+
+    // Los nombres de tipo se validan en el análisis semántico, no aquí
+    #[test]
+    fn unknown_type_names_parse_as_named_types() {
+        let program = parse("fn main() { let p: Point = 1; }").unwrap();
+        let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+        assert!(matches!(&body[0].kind, StmtKind::Let { type_annotation: Some(Type::Named(name)), .. } if name == "Point"));
+    }
+
+    #[test]
+    fn unclosed_delimiters_point_at_their_opening_line() {
+        let cases = [
+            ("fn main() {\n    print(1);\n", "'{' sin cerrar abierto en línea 1"),
+            ("fn main() {\n    print(1);\n}\nfn f() {\n    if (true) {\n        print(2);\n    }\n", "'{' sin cerrar abierto en línea 4"),
+            ("fn main() {\n    let a = [1,\n        2", "'[' sin cerrar abierto en línea 2"),
+            ("fn main() {\n    print(1);\n}\n}\n", "'}' sin abrir en línea 4"),
+        ];
+        for (source, message) in cases {
+            let err = parse(source).unwrap_err().to_string();
+            assert!(err.contains(message), "{:?}: {}", source, err);
+        }
+    }
+
+    #[test]
+    fn calls_and_indexing_apply_to_any_expression() {
+        let program = parse("fn main() { f()(1)(); print(g()[0]); }").unwrap();
+        let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+        let StmtKind::Expression(call) = &body[0].kind else { unreachable!() };
+        let ExprKind::CallExpr { callee, args } = &call.kind else { panic!("{:?}", call) };
+        assert!(args.is_empty());
+        let ExprKind::CallExpr { callee, args } = &callee.kind else { panic!("{:?}", callee) };
+        assert_eq!(args.len(), 1);
+        assert!(matches!(&callee.kind, ExprKind::Call { function, args } if function == "f" && args.is_empty()));
+
+        let StmtKind::Print(index) = &body[1].kind else { unreachable!() };
+        let ExprKind::ArrayIndex { array, .. } = &index.kind else { panic!("{:?}", index) };
+        assert!(matches!(&array.kind, ExprKind::Call { function, .. } if function == "g"));
+    }
+
+    #[test]
+    fn ternaries_associate_to_the_right() {
+        let arms = |expr: &str| {
+            let program = parse(&format!("fn main() {{ print({}); }}", expr)).unwrap();
+            let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+            let StmtKind::Print(expr) = &body[0].kind else { unreachable!() };
+            let ExprKind::Ternary { condition, then_expr, else_expr } = &expr.kind else { panic!("{:?}", expr) };
+            let shape = |expr: &Expr| match &expr.kind {
+                ExprKind::Ternary { .. } => "?:",
+                _ => "valor",
+            };
+            [shape(condition), shape(then_expr), shape(else_expr)]
+        };
+        assert_eq!(arms("a ? b : c ? d : e"), ["valor", "valor", "?:"]);
+        // La rama central se analiza completa hasta su ':'
+        assert_eq!(arms("a ? b ? c : d : e"), ["valor", "?:", "valor"]);
+        assert_eq!(arms("a || b ? c + 1 : d"), ["valor", "valor", "valor"]);
+    }
+
+    #[test]
+    fn prefix_operators_wrap_their_operand() {
+        let program = parse("fn main() { let y = -5; let z = !true; let w = --y; }").unwrap();
+        let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+        // Operador y operando del valor de cada let
+        let prefix = |index: usize| {
+            let StmtKind::Let { value: Some(value), .. } = &body[index].kind else { unreachable!() };
+            let ExprKind::Prefix { op, operand } = &value.kind else { panic!("{:?}", value) };
+            (op.clone(), operand.kind.clone())
+        };
+        assert!(matches!(prefix(0), (op, ExprKind::Number(5)) if op == "-"));
+        assert!(matches!(prefix(1), (op, ExprKind::Boolean(true)) if op == "!"));
+        assert!(matches!(prefix(2), (op, ExprKind::Prefix { op: inner, .. }) if op == "-" && inner == "-"));
+    }
+
+    #[test]
+    fn else_if_chains_nest_in_the_else_block() {
+        let program =
+            parse("fn main() { if (a) { print(1); } else if (b) { print(2); } else { print(3); } }").unwrap();
+        let StmtKind::Function { body, .. } = &program.statements[0].kind else { unreachable!() };
+        let StmtKind::If { then_block, else_block: Some(else_block), .. } = &body[0].kind else { unreachable!() };
+        assert_eq!(then_block.len(), 1);
+        let [inner] = else_block.as_slice() else { panic!("{:?}", else_block) };
+        let StmtKind::If { condition, then_block, else_block: Some(last) } = &inner.kind else { panic!("{:?}", inner) };
+        assert!(matches!(&condition.kind, ExprKind::Ident(name) if name == "b"));
+        assert_eq!(then_block.len(), 1);
+        assert!(matches!(last.as_slice(), [stmt] if matches!(stmt.kind, StmtKind::Print(_))));
+    }
+
+    #[test]
+    fn enum_declares_its_variants_in_order() {
+        let program = parse("enum Color { Red, Green, Blue } fn main() -> int { return Color.Green; }").unwrap();
+        let StmtKind::Enum { name, variants } = &program.statements[0].kind else {
+            panic!("se esperaba un enum: {:?}", program.statements[0]);
+        };
+        assert_eq!(name, "Color");
+        assert_eq!(variants, &["Red", "Green", "Blue"]);
+        let StmtKind::Function { body, .. } = &program.statements[1].kind else { unreachable!() };
+        let StmtKind::Return(Some(value)) = &body[0].kind else { unreachable!() };
+        assert!(matches!(
+            &value.kind,
+            ExprKind::EnumVariant { enum_name, variant } if enum_name == "Color" && variant == "Green"
+        ));
+    }
+
+    #[test]
+    fn defaults_must_follow_required_parameters() {
+        let program = parse("fn f(a: int, b: int = 2, c: bool = true) -> int { return a; }").unwrap();
+        let StmtKind::Function { defaults, .. } = &program.statements[0].kind else { unreachable!() };
+        assert!(defaults[0].is_none());
+        assert!(matches!(defaults[1].as_ref().map(|d| &d.kind), Some(ExprKind::Number(2))));
+        assert!(matches!(defaults[2].as_ref().map(|d| &d.kind), Some(ExprKind::Boolean(true))));
+
+        let err = parse("fn f(a: int = 1, b: int) -> int { return a; }").unwrap_err();
+        assert!(err.to_string().contains("el parámetro 'b' necesita un valor por defecto"), "{}", err);
+    }
+}
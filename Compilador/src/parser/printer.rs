@@ -0,0 +1,277 @@
+use crate::parser::ast::{Expr, ExprKind, Program, Stmt, StmtKind, Type};
+
+const INDENT: &str = "    ";
+
+// Re-emite el programa como código fuente con formato canónico.
+// La salida es una entrada válida para el compilador.
+pub fn format_program(program: &Program) -> String {
+    let mut output = String::new();
+    let mut prev_was_function = false;
+
+    for (i, stmt) in program.statements.iter().enumerate() {
+        let is_function = matches!(stmt.kind, StmtKind::Function { .. });
+        if i > 0 && (is_function || prev_was_function) {
+            output.push('\n');
+        }
+        format_statement(stmt, 0, &mut output);
+        prev_was_function = is_function;
+    }
+
+    output
+}
+
+fn format_statement(stmt: &Stmt, level: usize, output: &mut String) {
+    let indent = INDENT.repeat(level);
+    match &stmt.kind {
+        StmtKind::Let { .. }
+        | StmtKind::LetArray { .. }
+        | StmtKind::LetTuple { .. }
+        | StmtKind::Assign { .. }
+        | StmtKind::Expression(_) => {
+            output.push_str(&format!("{}{};\n", indent, format_simple_statement(stmt)));
+        }
+        StmtKind::If { condition, then_block, else_block } => {
+            output.push_str(&indent);
+            format_if(condition, then_block, else_block.as_deref(), level, output);
+            output.push('\n');
+        }
+        StmtKind::While { condition, body } => {
+            output.push_str(&format!("{}while ({}) ", indent, format_expression(condition)));
+            format_block(body, level, output);
+            output.push('\n');
+        }
+        StmtKind::For { init, condition, increment, body } => {
+            output.push_str(&format!(
+                "{}for ({}; {}; {}) ",
+                indent,
+                format_simple_statement(init),
+                format_expression(condition),
+                format_simple_statement(increment)
+            ));
+            format_block(body, level, output);
+            output.push('\n');
+        }
+        StmtKind::Function { name, params, defaults, return_type, body, attributes } => {
+            for attribute in attributes {
+                output.push_str(&format!("{}@{}\n", indent, attribute));
+            }
+            let params: Vec<String> = params
+                .iter()
+                .zip(defaults)
+                .map(|((param_name, param_type), default)| match default {
+                    Some(value) => format!(
+                        "{}: {} = {}",
+                        param_name,
+                        format_type(param_type),
+                        format_expression(value)
+                    ),
+                    None => format!("{}: {}", param_name, format_type(param_type)),
+                })
+                .collect();
+            output.push_str(&format!("{}fn {}({})", indent, name, params.join(", ")));
+            if let Some(return_type) = return_type.as_ref().filter(|t| **t != Type::Void) {
+                output.push_str(&format!(" -> {}", format_type(return_type)));
+            }
+            output.push(' ');
+            format_block(body, level, output);
+            output.push('\n');
+        }
+        StmtKind::Enum { name, variants } => {
+            output.push_str(&format!("{}enum {} {{ {} }}\n", indent, name, variants.join(", ")));
+        }
+        StmtKind::Return(Some(expr)) => {
+            output.push_str(&format!("{}return {};\n", indent, format_expression(expr)));
+        }
+        StmtKind::Return(None) => {
+            output.push_str(&format!("{}return;\n", indent));
+        }
+        StmtKind::Print(expr) => {
+            output.push_str(&format!("{}print({});\n", indent, format_expression(expr)));
+        }
+    }
+}
+
+// Sentencias que pueden aparecer en la cabecera de un for (sin ';' final)
+// Un else cuyo único contenido es otro if se escribe como `else if`
+fn format_if(condition: &Expr, then_block: &[Stmt], else_block: Option<&[Stmt]>, level: usize, output: &mut String) {
+    output.push_str(&format!("if ({}) ", format_expression(condition)));
+    format_block(then_block, level, output);
+    match else_block {
+        Some([Stmt { kind: StmtKind::If { condition, then_block, else_block }, .. }]) => {
+            output.push_str(" else ");
+            format_if(condition, then_block, else_block.as_deref(), level, output);
+        }
+        Some(else_stmts) => {
+            output.push_str(" else ");
+            format_block(else_stmts, level, output);
+        }
+        None => {}
+    }
+}
+
+fn format_simple_statement(stmt: &Stmt) -> String {
+    match &stmt.kind {
+        StmtKind::Let { name, type_annotation, value, mutable } => {
+            let keyword = if *mutable { "let" } else { "const" };
+            let value = value.as_ref().map(|value| format!(" = {}", format_expression(value))).unwrap_or_default();
+            match type_annotation {
+                Some(type_) => format!("{} {}: {}{}", keyword, name, format_type(type_), value),
+                None => format!("{} {}{}", keyword, name, value),
+            }
+        }
+        StmtKind::LetArray { names, value } => {
+            format!("let [{}] = {}", names.join(", "), format_expression(value))
+        }
+        StmtKind::LetTuple { names, value } => {
+            format!("let ({}) = {}", names.join(", "), format_expression(value))
+        }
+        StmtKind::Assign { target, value } => format!("{} = {}", target, format_expression(value)),
+        StmtKind::Expression(expr) => format_expression(expr),
+        _ => {
+            let mut output = String::new();
+            format_statement(stmt, 0, &mut output);
+            output.trim_end().trim_end_matches(';').to_string()
+        }
+    }
+}
+
+fn format_block(stmts: &[Stmt], level: usize, output: &mut String) {
+    output.push_str("{\n");
+    for stmt in stmts {
+        format_statement(stmt, level + 1, output);
+    }
+    output.push_str(&format!("{}}}", INDENT.repeat(level)));
+}
+
+pub fn format_expression(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Number(n) => n.to_string(),
+        // {:?} conserva el '.0' de los valores enteros
+        ExprKind::Float(f) => format!("{:?}", f),
+        ExprKind::Boolean(b) => b.to_string(),
+        ExprKind::String(s) => format!("\"{}\"", escape_string(s)),
+        ExprKind::Char('\'') => "'\\''".to_string(),
+        ExprKind::Char('"') => "'\"'".to_string(),
+        ExprKind::Char(c) => format!("'{}'", escape_string(&c.to_string())),
+        ExprKind::Ident(name) => name.clone(),
+        ExprKind::ArrayLiteral(elements) => format!("[{}]", format_list(elements)),
+        ExprKind::Tuple(elements) => format!("({})", format_list(elements)),
+        ExprKind::TupleIndex { tuple, index } => format!("{}.{}", format_expression(tuple), index),
+        ExprKind::ArrayIndex { array, index } => {
+            format!("{}[{}]", format_expression(array), format_expression(index))
+        }
+        ExprKind::Prefix { op, operand } => format!("{}{}", op, format_expression(operand)),
+        ExprKind::Infix { left, op, right } => {
+            format!("{} {} {}", format_expression(left), op, format_expression(right))
+        }
+        ExprKind::Call { function, args } => format!("{}({})", function, format_list(args)),
+        ExprKind::CallExpr { callee, args } => {
+            format!("{}({})", format_expression(callee), format_list(args))
+        }
+        ExprKind::Grouped(inner) => format!("({})", format_expression(inner)),
+        ExprKind::Ternary { condition, then_expr, else_expr } => format!(
+            "{} ? {} : {}",
+            format_expression(condition),
+            format_expression(then_expr),
+            format_expression(else_expr)
+        ),
+        ExprKind::New { type_, size } => {
+            format!("new {}({})", format_type(type_), format_expression(size))
+        }
+        ExprKind::EnumVariant { enum_name, variant } => format!("{}.{}", enum_name, variant),
+    }
+}
+
+// Inversa de las secuencias de escape que acepta el lexer
+fn escape_string(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_list(exprs: &[Expr]) -> String {
+    exprs.iter().map(format_expression).collect::<Vec<_>>().join(", ")
+}
+
+pub fn format_type(type_: &Type) -> String {
+    match type_ {
+        Type::Int => "int".to_string(),
+        Type::Float => "float".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::String => "string".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Array(inner) => format!("[{}]", format_type(inner)),
+        Type::Void => "void".to_string(),
+        Type::Named(name) | Type::Enum(name) => name.clone(),
+        Type::Variadic(inner) => format!("...{}", format_type(inner)),
+        Type::Tuple(types) => {
+            format!("({})", types.iter().map(format_type).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_program;
+    use crate::testing::parse;
+
+    fn formatted(source: &str) -> String {
+        format_program(&parse(source).unwrap())
+    }
+
+    #[test]
+    fn formats_to_the_canonical_layout() {
+        let source = "enum Color{Red,Green}\n@inline fn add(a:int,b:int=2)->int{return a+b;}\n\
+                      fn main(){let x:int=add(1);if(x>2){print(x);}else if(x<0){print(0);}else{print(-x);}\
+                      for(let i=0;i<3;i=i+1){print(i);} const s=\"a\\n\\\"b\\\"\"; let c='\\''; let a=new [int](4);}";
+        let expected = "\
+enum Color { Red, Green }
+
+@inline
+fn add(a: int, b: int = 2) -> int {
+    return a + b;
+}
+
+fn main() {
+    let x: int = add(1);
+    if (x > 2) {
+        print(x);
+    } else if (x < 0) {
+        print(0);
+    } else {
+        print(-x);
+    }
+    for (let i = 0; i < 3; i = i + 1) {
+        print(i);
+    }
+    const s = \"a\\n\\\"b\\\"\";
+    let c = '\\'';
+    let a = new [int](4);
+}
+";
+        assert_eq!(formatted(source), expected);
+    }
+
+    // La salida vuelve a ser una entrada válida y formatearla no la cambia
+    #[test]
+    fn formatting_is_idempotent() {
+        let source = "fn sum(nums: ...int) -> int { let t = 0; for (let i = 0; i < len(nums); i = i + 1) \
+                      { t = t + nums[i]; } return t; } fn main() -> int { let f = 1.0; let b = (1 + 2) * 3; \
+                      let m = b > 3 ? [1, 2][0] : sum(1, 2); while (m > 0) { m = m - 1; } return m; }";
+        let once = formatted(source);
+        assert_eq!(formatted(&once), once);
+        assert!(once.contains("let f = 1.0;"));
+        assert!(once.contains("let b = (1 + 2) * 3;"));
+        assert!(once.contains("fn sum(nums: ...int) -> int {"));
+    }
+}
@@ -9,3 +9,42 @@ pub fn generate_runtime(os: OperatingSystem) -> String {
         _ => unix::get_runtime(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::generate_runtime;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use target_lexicon::OperatingSystem;
+
+    // Los valores del compilador ocupan 64 bits, pero long solo mide 32 en
+    // Windows (LLP64): todo entero del runtime tiene que ser long long
+    #[test]
+    fn runtime_integers_are_long_long() {
+        for os in [OperatingSystem::Linux, OperatingSystem::Windows] {
+            let runtime = generate_runtime(os);
+            let code: String =
+                runtime.lines().filter(|line| !line.trim_start().starts_with("//")).collect::<Vec<_>>().join("\n");
+            assert!(code.contains("void print_int(long long n)"), "{:?}", os);
+            assert!(code.contains("%lld"), "{:?}", os);
+            assert!(!code.contains("%ld") && !code.contains("%li"), "{:?}", os);
+            assert!(!code.replace("long long", "").contains("long"), "{:?}", os);
+        }
+    }
+
+    // Con un compilador de C a mano, que los formatos casen con los tipos
+    #[test]
+    fn unix_runtime_formats_match_their_arguments() {
+        let child = Command::new("cc")
+            .args(["-fsyntax-only", "-Wformat", "-Werror=format", "-x", "c", "-"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let Ok(mut child) = child else {
+            return;
+        };
+        child.stdin.take().unwrap().write_all(generate_runtime(OperatingSystem::Linux).as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -2,25 +2,84 @@ pub fn get_runtime() -> String {
     r#"
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
 
-void print_int(long n) {
-    printf("%ld\n", n);
+// Los valores del compilador son de 64 bits: se usa long long en vez de
+// long para no depender de que long lo sea en la plataforma.
+
+void print_int(long long n) {
+    printf("%lld\n", n);
 }
 
 void print_string(const char* s) {
     printf("%s\n", s);
 }
 
-long string_length(const char* s) {
+void print_bool(long long b) {
+    printf("%s\n", b ? "true" : "false");
+}
+
+void print_char(long long c) {
+    printf("%c\n", (int)c);
+}
+
+// Variantes sin salto de línea, para construir una línea por partes
+void print_int_no_nl(long long n) {
+    printf("%lld", n);
+}
+
+void print_string_no_nl(const char* s) {
+    printf("%s", s);
+}
+
+// Lee un entero de la entrada estándar; si no hay uno (fin de la entrada
+// o texto que no es un número) devuelve 0
+long long input_int(void) {
+    long long n;
+    if (scanf("%lld", &n) != 1) {
+        return 0;
+    }
+    return n;
+}
+
+long long string_length(const char* s) {
     return strlen(s);
 }
 
+// Arrays: bloque de (size + 1) palabras; la primera guarda la longitud
+// y los elementos empiezan en el desplazamiento 8, inicializados a cero.
+long long* array_new(long long size) {
+    long long* array = calloc(size + 1, sizeof(long long));
+    array[0] = size;
+    return array;
+}
+
+long long array_length(const long long* array) {
+    return array[0];
+}
+
 char* string_concat(const char* s1, const char* s2) {
     char* result = malloc(strlen(s1) + strlen(s2) + 1);
     strcpy(result, s1);
     strcat(result, s2);
     return result;
 }
+
+long long string_equals(const char* s1, const char* s2) {
+    return strcmp(s1, s2) == 0;
+}
+
+// assert_eq fallido: muestra los dos valores y termina con error. exit
+// vacía antes la salida estándar, así que se ve todo lo impreso hasta aquí.
+void panic_assert_eq(long long left, long long right) {
+    fprintf(stderr, "assert_eq falló: izquierda = %lld, derecha = %lld\n", left, right);
+    exit(1);
+}
+
+void panic_assert_eq_string(const char* left, const char* right) {
+    fprintf(stderr, "assert_eq falló: izquierda = \"%s\", derecha = \"%s\"\n", left, right);
+    exit(1);
+}
 "#
     .to_string()
 }
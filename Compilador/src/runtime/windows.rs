@@ -12,16 +12,71 @@ __declspec(dllexport) void print_string(const char* s) {
     printf("%s\n", s);
 }
 
+__declspec(dllexport) void print_bool(long long b) {
+    printf("%s\n", b ? "true" : "false");
+}
+
+__declspec(dllexport) void print_char(long long c) {
+    printf("%c\n", (int)c);
+}
+
+// Variantes sin salto de línea, para construir una línea por partes
+__declspec(dllexport) void print_int_no_nl(long long n) {
+    printf("%lld", n);
+}
+
+__declspec(dllexport) void print_string_no_nl(const char* s) {
+    printf("%s", s);
+}
+
+// Lee un entero de la entrada estándar; si no hay uno (fin de la entrada
+// o texto que no es un número) devuelve 0
+__declspec(dllexport) long long input_int(void) {
+    long long n;
+    if (scanf("%lld", &n) != 1) {
+        return 0;
+    }
+    return n;
+}
+
 __declspec(dllexport) long long string_length(const char* s) {
     return strlen(s);
 }
 
+// Arrays: bloque de (size + 1) palabras; la primera guarda la longitud
+// y los elementos empiezan en el desplazamiento 8, inicializados a cero.
+__declspec(dllexport) long long* array_new(long long size) {
+    long long* array = calloc(size + 1, sizeof(long long));
+    array[0] = size;
+    return array;
+}
+
+__declspec(dllexport) long long array_length(const long long* array) {
+    return array[0];
+}
+
 __declspec(dllexport) char* string_concat(const char* s1, const char* s2) {
     char* result = malloc(strlen(s1) + strlen(s2) + 1);
     strcpy(result, s1);
     strcat(result, s2);
     return result;
 }
+
+__declspec(dllexport) long long string_equals(const char* s1, const char* s2) {
+    return strcmp(s1, s2) == 0;
+}
+
+// assert_eq fallido: muestra los dos valores y termina con error. exit
+// vacía antes la salida estándar, así que se ve todo lo impreso hasta aquí.
+__declspec(dllexport) void panic_assert_eq(long long left, long long right) {
+    fprintf(stderr, "assert_eq falló: izquierda = %lld, derecha = %lld\n", left, right);
+    exit(1);
+}
+
+__declspec(dllexport) void panic_assert_eq_string(const char* left, const char* right) {
+    fprintf(stderr, "assert_eq falló: izquierda = \"%s\", derecha = \"%s\"\n", left, right);
+    exit(1);
+}
 "#
     .to_string()
 }
@@ -0,0 +1,109 @@
+use crate::parser::ast::{Expr, ExprKind, Program, Stmt, StmtKind};
+
+// Complejidad ciclomática de cada función: 1 + número de puntos de
+// decisión (if, while, for, ?:, && y ||), en orden de declaración.
+pub fn function_complexity(program: &Program) -> Vec<(String, usize)> {
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::Function { name, body, .. } => Some((name.clone(), 1 + count_block(body))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn count_block(stmts: &[Stmt]) -> usize {
+    stmts.iter().map(count_statement).sum()
+}
+
+fn count_statement(stmt: &Stmt) -> usize {
+    match &stmt.kind {
+        StmtKind::Let { value: Some(value), .. }
+        | StmtKind::LetArray { value, .. }
+        | StmtKind::LetTuple { value, .. }
+        | StmtKind::Assign { value, .. }
+        | StmtKind::Expression(value)
+        | StmtKind::Print(value)
+        | StmtKind::Return(Some(value)) => count_expression(value),
+        StmtKind::Let { value: None, .. } | StmtKind::Return(None) => 0,
+        StmtKind::If { condition, then_block, else_block } => {
+            1 + count_expression(condition)
+                + count_block(then_block)
+                + else_block.as_deref().map_or(0, count_block)
+        }
+        StmtKind::While { condition, body } => 1 + count_expression(condition) + count_block(body),
+        StmtKind::For { init, condition, increment, body } => {
+            1 + count_statement(init)
+                + count_expression(condition)
+                + count_statement(increment)
+                + count_block(body)
+        }
+        // Las funciones anidadas se rechazan en el análisis semántico
+        StmtKind::Function { .. } | StmtKind::Enum { .. } => 0,
+    }
+}
+
+fn count_expression(expr: &Expr) -> usize {
+    match &expr.kind {
+        ExprKind::Number(_)
+        | ExprKind::Float(_)
+        | ExprKind::Boolean(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_)
+        | ExprKind::Ident(_)
+        | ExprKind::EnumVariant { .. } => 0,
+        ExprKind::ArrayLiteral(elements) | ExprKind::Tuple(elements) => elements.iter().map(count_expression).sum(),
+        ExprKind::ArrayIndex { array, index } => count_expression(array) + count_expression(index),
+        ExprKind::Prefix { operand, .. } => count_expression(operand),
+        ExprKind::Infix { left, op, right } => {
+            let decision = usize::from(op == "&&" || op == "||");
+            decision + count_expression(left) + count_expression(right)
+        }
+        ExprKind::Call { args, .. } => args.iter().map(count_expression).sum(),
+        ExprKind::CallExpr { callee, args } => {
+            count_expression(callee) + args.iter().map(count_expression).sum::<usize>()
+        }
+        ExprKind::Grouped(inner) | ExprKind::TupleIndex { tuple: inner, .. } => count_expression(inner),
+        ExprKind::Ternary { condition, then_expr, else_expr } => {
+            1 + count_expression(condition) + count_expression(then_expr) + count_expression(else_expr)
+        }
+        ExprKind::New { size, .. } => count_expression(size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::function_complexity;
+    use crate::testing::parse;
+
+    fn complexity(body: &str) -> usize {
+        let program = parse(&format!("fn f(a: bool, b: bool, n: int) -> int {{ {} return 0; }}", body)).unwrap();
+        function_complexity(&program)[0].1
+    }
+
+    #[test]
+    fn two_ifs_and_a_while_give_four() {
+        assert_eq!(complexity("if (a) { print(1); } if (b) { print(2); } while (n > 0) { n = n - 1; }"), 4);
+    }
+
+    #[test]
+    fn each_decision_point_adds_one() {
+        assert_eq!(complexity(""), 1);
+        let cases = [
+            "if (a) { print(1); }",
+            "if (a) { print(1); } else { print(2); }",
+            "while (a) { print(1); }",
+            "for (let i = 0; i < n; i = i + 1) { print(i); }",
+            "print(a ? 1 : 2);",
+            "print(a && b);",
+            "print(a || b);",
+        ];
+        for body in cases {
+            assert_eq!(complexity(body), 2, "{}", body);
+        }
+        // Los operadores cuentan también dentro de las condiciones
+        assert_eq!(complexity("if (a && b || n > 0) { print(1); }"), 4);
+        assert_eq!(complexity("if (a) { if (b) { print(1); } else { print(2); } }"), 3);
+    }
+}
@@ -1,6 +1,9 @@
-use crate::parser::ast::{Expr, Program, Stmt, Type};
+pub mod complexity;
+
+use crate::lexer::token::Span;
+use crate::parser::ast::{Expr, ExprKind, NodeId, Program, Stmt, StmtKind, Type, MAX_CONSTANT_STRING};
 use crate::types::TypeSystem;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use anyhow::{Result, bail};
 
 #[derive(Debug)]
@@ -9,56 +12,195 @@ pub struct Symbol {
     pub type_: Type,
     pub is_function: bool,
     pub params: Option<Vec<Type>>,
+    pub mutable: bool,
+}
+
+// Error ya asociado a la posición del nodo más interno que lo produjo;
+// los nodos que lo contienen no vuelven a situarlo.
+#[derive(Debug)]
+struct Located {
+    span: Span,
+    message: String,
+}
+
+impl std::fmt::Display for Located {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "error en {}: {}", self.span, self.message)
+    }
+}
+
+impl std::error::Error for Located {}
+
+fn locate(err: anyhow::Error, span: Span) -> anyhow::Error {
+    if err.is::<Located>() {
+        err
+    } else {
+        anyhow::Error::new(Located {
+            span,
+            message: err.to_string(),
+        })
+    }
 }
 
 pub struct SemanticAnalyzer {
-    symbols: HashMap<String, Symbol>,
+    // Pila de ámbitos: el primero es el global (funciones y builtins)
+    scopes: Vec<HashMap<String, Symbol>>,
+    // Tipos declarados por el usuario, por nombre
+    types: HashMap<String, Type>,
+    // Variantes de cada enum, en orden de declaración
+    enums: HashMap<String, Vec<String>>,
+    // Parámetros finales con valor por defecto de cada función
+    default_params: HashMap<String, usize>,
     type_system: TypeSystem,
     current_function: Option<String>,
     current_return_type: Option<Type>,
+    // Si es false, redeclarar una variable en el mismo ámbito es un error
+    allow_shadowing: bool,
+    // Avisar cuando un parámetro oculta una función o variable global
+    warn_param_shadowing: bool,
+    // Si es true, un entero vale como condición (distinto de cero es cierto)
+    truthiness: bool,
+    // Tipo de cada expresión analizada, para las fases posteriores
+    expression_types: HashMap<NodeId, Type>,
+    // Declaraciones de las funciones sin `-> tipo` cuyo tipo de retorno
+    // falta por deducir
+    pending_returns: HashMap<String, Stmt>,
+    // Funciones cuyo tipo de retorno todavía no se conoce: pendientes o a
+    // medio deducir sin haber visto aún un return con valor
+    unknown_returns: HashSet<String>,
+    // Tipos de los return mientras se deduce un tipo de retorno
+    inferred_returns: Option<Vec<Type>>,
+    warnings: Vec<String>,
 }
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
         let mut analyzer = SemanticAnalyzer {
-            symbols: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            types: HashMap::new(),
+            enums: HashMap::new(),
+            default_params: HashMap::new(),
             type_system: TypeSystem::new(),
             current_function: None,
             current_return_type: None,
+            allow_shadowing: true,
+            warn_param_shadowing: false,
+            truthiness: false,
+            expression_types: HashMap::new(),
+            pending_returns: HashMap::new(),
+            unknown_returns: HashSet::new(),
+            inferred_returns: None,
+            warnings: Vec::new(),
         };
         
         // Built-in functions
         analyzer.add_builtin_function("print", vec![Type::Int], Type::Void);
         analyzer.add_builtin_function("print_string", vec![Type::String], Type::Void);
-        analyzer.add_builtin_function("len", vec![Type::String], Type::Int);
+        analyzer.add_builtin_function("write_string", vec![Type::String], Type::Void);
+        analyzer.add_builtin_function("input_int", vec![], Type::Int);
+        // Aceptan varios tipos de argumento, así que su firma se comprueba
+        // aparte en analyze_untyped_builtin
+        analyzer.add_untyped_builtin("len", Type::Int);
+        analyzer.add_untyped_builtin("is_empty", Type::Bool);
+        analyzer.add_untyped_builtin("assert_eq", Type::Void);
+        analyzer.add_untyped_builtin("write", Type::Void);
         
         analyzer
     }
 
+    pub fn with_shadowing(mut self, allow: bool) -> Self {
+        self.allow_shadowing = allow;
+        self
+    }
+
+    pub fn with_shadow_warnings(mut self, enabled: bool) -> Self {
+        self.warn_param_shadowing = enabled;
+        self
+    }
+
+    pub fn with_truthiness(mut self, enabled: bool) -> Self {
+        self.truthiness = enabled;
+        self
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn expression_types(&self) -> &HashMap<NodeId, Type> {
+        &self.expression_types
+    }
+
     fn add_builtin_function(&mut self, name: &str, params: Vec<Type>, return_type: Type) {
-        self.symbols.insert(
+        self.scopes[0].insert(
             name.to_string(),
             Symbol {
                 name: name.to_string(),
                 type_: return_type,
                 is_function: true,
                 params: Some(params),
+                mutable: false,
+            },
+        );
+    }
+
+    fn add_untyped_builtin(&mut self, name: &str, return_type: Type) {
+        self.scopes[0].insert(
+            name.to_string(),
+            Symbol {
+                name: name.to_string(),
+                type_: return_type,
+                is_function: true,
+                params: None,
+                mutable: false,
             },
         );
     }
 
     pub fn analyze(&mut self, program: &Program) -> Result<()> {
+        // Los enums van antes que las firmas, que pueden usarlos como tipo
+        for stmt in &program.statements {
+            if let StmtKind::Enum { name, variants } = &stmt.kind {
+                self.declare_enum(name, variants).map_err(|err| locate(err, stmt.span))?;
+            }
+        }
+
         // First pass: collect function declarations
         for stmt in &program.statements {
-            if let Stmt::Function { name, params, return_type, .. } = stmt {
-                let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
-                self.symbols.insert(
+            if let StmtKind::Function { name, params, defaults, return_type, body, .. } = &stmt.kind {
+                let default_count = defaults.iter().filter(|d| d.is_some()).count();
+                if default_count > 0 {
+                    if let Some((_, Type::Variadic(_))) = params.last() {
+                        let err = anyhow::anyhow!(
+                            "'{}' no puede tener a la vez parámetros por defecto y variádicos",
+                            name
+                        );
+                        return Err(locate(err, stmt.span));
+                    }
+                    self.default_params.insert(name.clone(), default_count);
+                }
+                let param_types = params
+                    .iter()
+                    .map(|(_, t)| self.resolve_type(t))
+                    .collect::<Result<Vec<Type>>>()?;
+                let return_type = match return_type {
+                    Some(return_type) => self.resolve_type(return_type)?,
+                    // Sin ningún `return valor` es void sin mirar más
+                    None if !returns_value(body) => Type::Void,
+                    None => {
+                        self.pending_returns.insert(name.clone(), stmt.clone());
+                        self.unknown_returns.insert(name.clone());
+                        Type::Void
+                    }
+                };
+                self.scopes[0].insert(
                     name.clone(),
                     Symbol {
                         name: name.clone(),
-                        type_: return_type.clone(),
+                        type_: return_type,
                         is_function: true,
                         params: Some(param_types),
+                        mutable: false,
                     },
                 );
             }
@@ -72,156 +214,572 @@ impl SemanticAnalyzer {
         Ok(())
     }
 
+    // Registra el tipo y cada variante como constante global de ese tipo
+    fn declare_enum(&mut self, name: &str, variants: &[String]) -> Result<()> {
+        if self.types.contains_key(name) {
+            bail!("el tipo '{}' ya está declarado", name);
+        }
+        for (i, variant) in variants.iter().enumerate() {
+            if variants[..i].contains(variant) {
+                bail!("variante '{}' repetida en el enum '{}'", variant, name);
+            }
+            if self.scopes[0].contains_key(variant) {
+                bail!("la variante '{}' choca con otro nombre global", variant);
+            }
+            self.scopes[0].insert(
+                variant.clone(),
+                Symbol {
+                    name: variant.clone(),
+                    type_: Type::Enum(name.to_string()),
+                    is_function: false,
+                    params: None,
+                    mutable: false,
+                },
+            );
+        }
+        self.types.insert(name.to_string(), Type::Enum(name.to_string()));
+        self.enums.insert(name.to_string(), variants.to_vec());
+        Ok(())
+    }
+
+    // Los tipos con nombre se resuelven contra el registro de tipos declarados
+    fn resolve_type(&self, type_: &Type) -> Result<Type> {
+        match type_ {
+            Type::Named(name) => match self.types.get(name) {
+                Some(resolved) => Ok(resolved.clone()),
+                None => bail!("Tipo desconocido: {}", name),
+            },
+            Type::Array(inner) => Ok(Type::Array(Box::new(self.resolve_type(inner)?))),
+            Type::Variadic(inner) => Ok(Type::Variadic(Box::new(self.resolve_type(inner)?))),
+            Type::Tuple(types) => {
+                Ok(Type::Tuple(types.iter().map(|type_| self.resolve_type(type_)).collect::<Result<_>>()?))
+            }
+            _ => Ok(type_.clone()),
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // Sugiere la variable visible más parecida, si dista 2 ediciones o menos
+    fn undeclared_variable(&self, name: &str) -> anyhow::Error {
+        let suggestion = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.values())
+            .filter(|symbol| !symbol.is_function)
+            .map(|symbol| (levenshtein(name, &symbol.name), &symbol.name))
+            .filter(|(distance, _)| *distance <= 2)
+            .min();
+
+        match suggestion {
+            Some((_, candidate)) => {
+                anyhow::anyhow!("Variable '{}' no declarada; ¿quisiste decir '{}'?", name, candidate)
+            }
+            None => anyhow::anyhow!("Variable '{}' no declarada", name),
+        }
+    }
+
+    fn declare(&mut self, symbol: Symbol) -> Result<()> {
+        let scope = self.scopes.last_mut().expect("siempre existe el ámbito global");
+        if !self.allow_shadowing && scope.contains_key(&symbol.name) {
+            bail!("variable '{}' ya declarada en este ámbito", symbol.name);
+        }
+        scope.insert(symbol.name.clone(), symbol);
+        Ok(())
+    }
+
+    fn analyze_block(&mut self, stmts: &[Stmt]) -> Result<()> {
+        self.enter_scope();
+        let result = stmts.iter().try_for_each(|stmt| self.analyze_statement(stmt));
+        self.exit_scope();
+        result
+    }
+
     fn analyze_statement(&mut self, stmt: &Stmt) -> Result<()> {
-        match stmt {
-            Stmt::Let { name, type_annotation, value } => {
-                let expr_type = self.analyze_expression(value)?;
+        self.analyze_statement_inner(stmt).map_err(|err| locate(err, stmt.span))
+    }
+
+    fn analyze_statement_inner(&mut self, stmt: &Stmt) -> Result<()> {
+        match &stmt.kind {
+            StmtKind::Let { name, type_annotation, value, mutable } => {
+                let type_annotation = match type_annotation {
+                    Some(annotated) => Some(self.resolve_type(annotated)?),
+                    None => None,
+                };
+                let declared_type = match value {
+                    Some(value) => {
+                        let expr_type = self.analyze_value(value)?;
+                        // Una constante global se sustituye por su valor en cada uso
+                        // (ver IRBuilder), así que no puede tener efectos ni leer
+                        // variables
+                        if !*mutable && self.scopes.len() == 1 && !self.is_constant_expression(value) {
+                            bail!(
+                                "El valor de la constante global '{}' debe poder calcularse en compilación",
+                                name
+                            );
+                        }
+
+                        if let Some(annotated_type) = &type_annotation {
+                            if !self.type_system.can_coerce(&expr_type, annotated_type) {
+                                bail!("Tipo incompatible en declaración de variable '{}'", name);
+                            }
+                        }
+                        type_annotation.unwrap_or(expr_type)
+                    }
+                    // `let x: T;`, que el parser solo acepta con tipo
+                    None => {
+                        let type_ = type_annotation.expect("let sin valor ni tipo");
+                        if self.type_system.get_default_value(&type_).is_none() {
+                            bail!("La variable '{}' necesita un valor inicial: {:?} no tiene valor por defecto", name, type_);
+                        }
+                        type_
+                    }
+                };
                 
-                if let Some(annotated_type) = type_annotation {
-                    if !self.type_system.is_compatible(&expr_type, annotated_type) {
-                        bail!("Tipo incompatible en declaración de variable '{}'", name);
+                self.declare(Symbol {
+                    name: name.clone(),
+                    type_: declared_type,
+                    is_function: false,
+                    params: None,
+                    mutable: *mutable,
+                })?;
+            }
+            StmtKind::LetArray { names, value } => {
+                let element_type = match self.analyze_value(value)? {
+                    Type::Array(element_type) => *element_type,
+                    other => bail!("Solo se puede desestructurar un array, encontrado {:?}", other),
+                };
+                // La longitud solo se conoce si el valor es un literal
+                if let ExprKind::ArrayLiteral(elements) = &value.kind {
+                    if elements.len() != names.len() {
+                        bail!(
+                            "El patrón tiene {} elementos pero el array tiene {}",
+                            names.len(),
+                            elements.len()
+                        );
                     }
                 }
-                
-                self.symbols.insert(
-                    name.clone(),
-                    Symbol {
+                for name in names {
+                    self.declare(Symbol {
                         name: name.clone(),
-                        type_: type_annotation.clone().unwrap_or(expr_type),
+                        type_: element_type.clone(),
                         is_function: false,
                         params: None,
-                    },
-                );
+                        mutable: true,
+                    })?;
+                }
+            }
+            StmtKind::LetTuple { names, value } => {
+                let element_types = match self.analyze_value(value)? {
+                    Type::Tuple(element_types) => element_types,
+                    other => bail!("Solo se puede desestructurar una tupla, encontrado {:?}", other),
+                };
+                if element_types.len() != names.len() {
+                    bail!(
+                        "El patrón tiene {} elementos pero la tupla tiene {}",
+                        names.len(),
+                        element_types.len()
+                    );
+                }
+                for (name, type_) in names.iter().zip(element_types) {
+                    self.declare(Symbol {
+                        name: name.clone(),
+                        type_,
+                        is_function: false,
+                        params: None,
+                        mutable: true,
+                    })?;
+                }
             }
-            Stmt::Assign { target, value } => {
-                if let Some(symbol) = self.symbols.get(target) {
-                    let value_type = self.analyze_expression(value)?;
-                    if !self.type_system.is_compatible(&value_type, &symbol.type_) {
+            StmtKind::Assign { target, value } => {
+                if self.lookup(target).is_some_and(|s| !s.is_function && !s.mutable) {
+                    bail!("no se puede asignar a la constante '{}'", target);
+                }
+                if let Some(target_type) = self.lookup(target).map(|s| s.type_.clone()) {
+                    let value_type = self.analyze_value(value)?;
+                    if !self.type_system.is_compatible(&value_type, &target_type) {
                         bail!("Tipo incompatible en asignación a '{}'", target);
                     }
                 } else {
-                    bail!("Variable '{}' no declarada", target);
+                    return Err(self.undeclared_variable(target));
                 }
             }
-            Stmt::If { condition, then_block, else_block } => {
-                let cond_type = self.analyze_expression(condition)?;
-                if cond_type != Type::Bool {
-                    bail!("Condición del if debe ser booleana");
-                }
+            StmtKind::If { condition, then_block, else_block } => {
+                self.analyze_condition(condition, "if")?;
                 
-                for stmt in then_block {
-                    self.analyze_statement(stmt)?;
-                }
+                self.analyze_block(then_block)?;
                 
                 if let Some(else_stmts) = else_block {
-                    for stmt in else_stmts {
-                        self.analyze_statement(stmt)?;
-                    }
+                    self.analyze_block(else_stmts)?;
                 }
             }
-            Stmt::While { condition, body } => {
-                let cond_type = self.analyze_expression(condition)?;
-                if cond_type != Type::Bool {
-                    bail!("Condición del while debe ser booleana");
-                }
+            StmtKind::While { condition, body } => {
+                self.analyze_condition(condition, "while")?;
                 
-                for stmt in body {
-                    self.analyze_statement(stmt)?;
-                }
+                self.analyze_block(body)?;
+            }
+            StmtKind::For { init, condition, increment, body } => {
+                // La variable de inicialización vive en su propio ámbito
+                self.enter_scope();
+                let result = self.analyze_for(init, condition, increment, body);
+                self.exit_scope();
+                result?;
             }
-            Stmt::For { init, condition, increment, body } => {
-                self.analyze_statement(init)?;
-                let cond_type = self.analyze_expression(condition)?;
-                if cond_type != Type::Bool {
-                    bail!("Condición del for debe ser booleana");
+            StmtKind::Function { name, params, defaults, return_type, body, attributes } => {
+                for attribute in attributes {
+                    if attribute != "inline" && attribute != "noinline" {
+                        bail!("Atributo desconocido: @{}", attribute);
+                    }
                 }
-                self.analyze_statement(increment)?;
-                
-                for stmt in body {
-                    self.analyze_statement(stmt)?;
+                if attributes.iter().any(|a| a == "inline") && attributes.iter().any(|a| a == "noinline") {
+                    bail!("@inline y @noinline no se pueden combinar en '{}'", name);
                 }
-            }
-            Stmt::Function { name, params, return_type, body } => {
-                self.current_function = Some(name.clone());
-                self.current_return_type = Some(return_type.clone());
-                
-                // Add parameters to symbol table
-                for (param_name, param_type) in params {
-                    self.symbols.insert(
-                        param_name.clone(),
-                        Symbol {
-                            name: param_name.clone(),
-                            type_: param_type.clone(),
-                            is_function: false,
-                            params: None,
-                        },
-                    );
+                // El IR solo genera funciones de nivel superior
+                if self.scopes.len() > 1 {
+                    bail!("funciones anidadas no soportadas: '{}'", name);
                 }
+                // Los valores por defecto se evalúan en cada llamada, así que
+                // solo pueden usar lo que es visible desde cualquier sitio
+                for ((param_name, param_type), default) in params.iter().zip(defaults) {
+                    if let Some(default) = default {
+                        let default_type = self.analyze_value(default)?;
+                        if !self.type_system.is_compatible(&default_type, &self.resolve_type(param_type)?) {
+                            bail!(
+                                "El valor por defecto de '{}' es {:?}, se esperaba {:?}",
+                                param_name,
+                                default_type,
+                                param_type
+                            );
+                        }
+                    }
+                }
+
+                let return_type = match return_type {
+                    Some(return_type) => self.resolve_type(return_type)?,
+                    None => {
+                        self.infer_return_type(name)?;
+                        self.lookup(name).map(|symbol| symbol.type_.clone()).unwrap_or(Type::Void)
+                    }
+                };
+                self.current_function = Some(name.clone());
+                self.current_return_type = Some(return_type);
                 
-                for stmt in body {
-                    self.analyze_statement(stmt)?;
+                // Parameters and body share the function scope
+                self.enter_scope();
+                let result = self.analyze_function_body(params, body);
+                self.exit_scope();
+                result?;
+
+                if self.current_return_type != Some(Type::Void) && !always_returns(body) {
+                    bail!("la función '{}' puede terminar sin retornar un valor", name);
                 }
                 
                 self.current_function = None;
                 self.current_return_type = None;
             }
-            Stmt::Return(Some(expr)) => {
-                let expr_type = self.analyze_expression(expr)?;
-                if let Some(expected_type) = &self.current_return_type {
+            StmtKind::Return(Some(expr)) => {
+                let expr_type = self.analyze_value(expr)?;
+                if let Some(returns) = &mut self.inferred_returns {
+                    returns.push(expr_type.clone());
+                    // Desde aquí una llamada recursiva ya tiene tipo
+                    if let Some(name) = &self.current_function {
+                        if self.unknown_returns.remove(name) {
+                            if let Some(symbol) = self.scopes[0].get_mut(name) {
+                                symbol.type_ = expr_type;
+                            }
+                        }
+                    }
+                } else if let Some(expected_type) = &self.current_return_type {
                     if !self.type_system.is_compatible(&expr_type, expected_type) {
                         bail!("Tipo de retorno incompatible");
                     }
                 }
             }
-            Stmt::Return(None) => {
-                if let Some(Type::Void) = &self.current_return_type {
+            StmtKind::Return(None) => {
+                if let Some(returns) = &mut self.inferred_returns {
+                    returns.push(Type::Void);
+                } else if let Some(Type::Void) = &self.current_return_type {
                     // OK
                 } else {
                     bail!("Función debe retornar un valor");
                 }
             }
-            Stmt::Expression(expr) => {
+            StmtKind::Expression(expr) => {
                 self.analyze_expression(expr)?;
             }
-            Stmt::Print(expr) => {
-                self.analyze_expression(expr)?;
+            StmtKind::Print(expr) => {
+                // print muestra strings, enums y lo que se pueda convertir a int
+                let type_ = self.analyze_value(expr)?;
+                let printable = type_ == Type::String
+                    || matches!(type_, Type::Enum(_))
+                    || self.type_system.can_coerce(&type_, &Type::Int);
+                if !printable {
+                    bail!("print no admite valores de tipo {:?}", type_);
+                }
+            }
+            // Ya registrado antes del análisis; solo se admite en el nivel superior
+            StmtKind::Enum { name, .. } => {
+                if self.scopes.len() > 1 {
+                    bail!("enums anidados no soportados: '{}'", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Builtins registrados sin firma porque aceptan argumentos de varios tipos
+    fn analyze_untyped_builtin(&mut self, function: &str, args: &[Expr]) -> Result<Type> {
+        match function {
+            // Dentro de una función variádica el parámetro ya es un array
+            "len" => {
+                let [arg] = args else {
+                    bail!("Número incorrecto de argumentos para '{}'", function);
+                };
+                let arg_type = self.analyze_value(arg)?;
+                if !matches!(arg_type, Type::String | Type::Array(_)) {
+                    bail!("len requiere un string o un array, encontrado {:?}", arg_type);
+                }
+                Ok(Type::Int)
+            }
+            "is_empty" => {
+                let [arg] = args else {
+                    bail!("Número incorrecto de argumentos para '{}'", function);
+                };
+                let arg_type = self.analyze_value(arg)?;
+                if !matches!(arg_type, Type::String | Type::Array(_)) {
+                    bail!("is_empty requiere un string o un array, encontrado {:?}", arg_type);
+                }
+                Ok(Type::Bool)
+            }
+            "assert_eq" => {
+                let [left, right] = args else {
+                    bail!("Número incorrecto de argumentos para '{}'", function);
+                };
+                let left_type = self.analyze_value(left)?;
+                let right_type = self.analyze_value(right)?;
+                match (&left_type, &right_type) {
+                    (Type::Int, Type::Int) | (Type::String, Type::String) => Ok(Type::Void),
+                    _ => bail!(
+                        "assert_eq requiere dos enteros o dos strings, encontrado {:?} y {:?}",
+                        left_type,
+                        right_type
+                    ),
+                }
+            }
+            // Según el tipo del argumento llama a la versión entera o a la de strings
+            "write" => {
+                let [arg] = args else {
+                    bail!("Número incorrecto de argumentos para '{}'", function);
+                };
+                let arg_type = self.analyze_value(arg)?;
+                if !matches!(arg_type, Type::Int | Type::String) {
+                    bail!("write requiere un entero o un string, encontrado {:?}", arg_type);
+                }
+                Ok(Type::Void)
+            }
+            _ => bail!("Builtin sin firma: '{}'", function),
+        }
+    }
+
+    fn analyze_condition(&mut self, condition: &Expr, construct: &str) -> Result<()> {
+        match self.analyze_value(condition)? {
+            Type::Bool => Ok(()),
+            Type::Int if self.truthiness => Ok(()),
+            Type::Int => bail!(
+                "Condición del {} debe ser booleana; compara con 0 (x != 0) o compila con --truthy",
+                construct
+            ),
+            _ => bail!("Condición del {} debe ser booleana", construct),
+        }
+    }
+
+    fn analyze_for(&mut self, init: &Stmt, condition: &Expr, increment: &Stmt, body: &[Stmt]) -> Result<()> {
+        self.analyze_statement(init)?;
+        self.analyze_condition(condition, "for")?;
+        self.analyze_statement(increment)?;
+        self.analyze_block(body)
+    }
+
+    fn analyze_function_body(&mut self, params: &[(String, Type)], body: &[Stmt]) -> Result<()> {
+        for (param_name, param_type) in params {
+            if self.warn_param_shadowing && self.lookup(param_name).is_some() {
+                self.warnings.push(format!(
+                    "el parámetro '{}' oculta una función o variable global",
+                    param_name
+                ));
+            }
+            let param_type = match self.resolve_type(param_type)? {
+                Type::Variadic(element_type) => Type::Array(element_type),
+                param_type => param_type,
+            };
+            self.declare(Symbol {
+                name: param_name.clone(),
+                type_: param_type,
+                is_function: false,
+                params: None,
+                mutable: true,
+            })?;
+        }
+
+        for stmt in body {
+            self.analyze_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    // Literales y operaciones sobre ellos, variantes de enum y otras
+    // constantes globales
+    fn is_constant_expression(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Number(_)
+            | ExprKind::Float(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::String(_)
+            | ExprKind::Char(_)
+            | ExprKind::EnumVariant { .. } => true,
+            ExprKind::Ident(name) => {
+                self.scopes[0].get(name).is_some_and(|symbol| !symbol.mutable && !symbol.is_function)
+            }
+            ExprKind::Grouped(inner) | ExprKind::Prefix { operand: inner, .. } => self.is_constant_expression(inner),
+            ExprKind::Infix { left, right, .. } => {
+                self.is_constant_expression(left) && self.is_constant_expression(right)
             }
+            ExprKind::Ternary { condition, then_expr, else_expr } => {
+                self.is_constant_expression(condition)
+                    && self.is_constant_expression(then_expr)
+                    && self.is_constant_expression(else_expr)
+            }
+            _ => false,
+        }
+    }
+
+    // Deduce el tipo de retorno de una función sin `-> tipo` la primera vez
+    // que se necesita: todos los return con valor deben coincidir. El cuerpo
+    // se analiza aparte, viendo solo el ámbito global; el análisis normal lo
+    // repite después con el tipo ya fijado.
+    fn infer_return_type(&mut self, name: &str) -> Result<()> {
+        let Some(Stmt { kind: StmtKind::Function { params, body, .. }, .. }) = self.pending_returns.remove(name) else {
+            return Ok(());
+        };
+        let outer_scopes = self.scopes.split_off(1);
+        let outer_function = self.current_function.replace(name.to_string());
+        let outer_return_type = self.current_return_type.take();
+        let outer_returns = self.inferred_returns.replace(Vec::new());
+        let warning_count = self.warnings.len();
+
+        self.enter_scope();
+        let result = self.analyze_function_body(&params, &body);
+        self.exit_scope();
+        let returns = self.inferred_returns.take().unwrap_or_default();
+
+        self.warnings.truncate(warning_count);
+        self.inferred_returns = outer_returns;
+        self.current_return_type = outer_return_type;
+        self.current_function = outer_function;
+        self.scopes.extend(outer_scopes);
+        result?;
+
+        let return_type = returns.first().cloned().unwrap_or(Type::Void);
+        if let Some(other) = returns.iter().find(|t| **t != return_type) {
+            bail!(
+                "los return de '{}' no coinciden: {:?} y {:?}; indica el tipo con -> tipo",
+                name,
+                return_type,
+                other
+            );
+        }
+        self.unknown_returns.remove(name);
+        if let Some(symbol) = self.scopes[0].get_mut(name) {
+            symbol.type_ = return_type;
         }
         Ok(())
     }
 
+    // Expresión cuyo valor se usa: operandos, argumentos, inicializadores...
+    // Solo una sentencia de expresión puede descartar un resultado void.
+    fn analyze_value(&mut self, expr: &Expr) -> Result<Type> {
+        let type_ = self.analyze_expression(expr)?;
+        if type_ == Type::Void {
+            let err = anyhow::anyhow!("no se puede usar un valor de tipo void en una expresión");
+            return Err(locate(err, expr.span));
+        }
+        Ok(type_)
+    }
+
     fn analyze_expression(&mut self, expr: &Expr) -> Result<Type> {
-        match expr {
-            Expr::Number(_) => Ok(Type::Int),
-            Expr::Boolean(_) => Ok(Type::Bool),
-            Expr::String(_) => Ok(Type::String),
-            Expr::Ident(name) => {
-                if let Some(symbol) = self.symbols.get(name) {
+        let type_ = self.analyze_expression_inner(expr).map_err(|err| locate(err, expr.span))?;
+        self.expression_types.insert(expr.id, type_.clone());
+        Ok(type_)
+    }
+
+    fn analyze_expression_inner(&mut self, expr: &Expr) -> Result<Type> {
+        match &expr.kind {
+            ExprKind::Number(_) => Ok(Type::Int),
+            ExprKind::Float(_) => Ok(Type::Float),
+            ExprKind::Boolean(_) => Ok(Type::Bool),
+            ExprKind::String(_) => Ok(Type::String),
+            ExprKind::Char(_) => Ok(Type::Char),
+            ExprKind::Ident(name) => {
+                if let Some(symbol) = self.lookup(name) {
                     if symbol.is_function {
                         bail!("'{}' es una función, no una variable", name);
                     }
                     Ok(symbol.type_.clone())
                 } else {
-                    bail!("Variable '{}' no declarada", name);
+                    Err(self.undeclared_variable(name))
                 }
             }
-            Expr::ArrayLiteral(elements) => {
+            ExprKind::ArrayLiteral(elements) => {
                 if elements.is_empty() {
                     return Ok(Type::Array(Box::new(Type::Int))); // Default
                 }
                 
-                let first_type = self.analyze_expression(&elements[0])?;
+                let first_type = self.analyze_value(&elements[0])?;
                 for element in &elements[1..] {
-                    let element_type = self.analyze_expression(element)?;
+                    let element_type = self.analyze_value(element)?;
                     if !self.type_system.is_compatible(&element_type, &first_type) {
                         bail!("Elementos del array deben tener el mismo tipo");
                     }
                 }
+
+                // Una matriz literal tiene que ser rectangular: todas las
+                // filas escritas como literal con la misma longitud
+                let row_lengths: Vec<usize> = elements
+                    .iter()
+                    .filter_map(|element| match &element.kind {
+                        ExprKind::ArrayLiteral(row) => Some(row.len()),
+                        _ => None,
+                    })
+                    .collect();
+                if row_lengths.windows(2).any(|pair| pair[0] != pair[1]) {
+                    bail!("Las filas del array deben tener la misma longitud: {:?}", row_lengths);
+                }
                 Ok(Type::Array(Box::new(first_type)))
             }
-            Expr::ArrayIndex { array, index } => {
-                let array_type = self.analyze_expression(array)?;
-                let index_type = self.analyze_expression(index)?;
+            ExprKind::Tuple(elements) => {
+                let types = elements.iter().map(|element| self.analyze_value(element)).collect::<Result<_>>()?;
+                Ok(Type::Tuple(types))
+            }
+            ExprKind::TupleIndex { tuple, index } => match self.analyze_value(tuple)? {
+                Type::Tuple(types) => match types.get(*index) {
+                    Some(type_) => Ok(type_.clone()),
+                    None => bail!("La tupla tiene {} elementos, no existe el índice {}", types.len(), index),
+                },
+                other => bail!("Solo se puede usar '.{}' sobre una tupla, encontrado {:?}", index, other),
+            },
+            ExprKind::ArrayIndex { array, index } => {
+                let array_type = self.analyze_value(array)?;
+                let index_type = self.analyze_value(index)?;
                 
                 if index_type != Type::Int {
                     bail!("Índice de array debe ser entero");
@@ -232,20 +790,60 @@ impl SemanticAnalyzer {
                     _ => bail!("No es un array"),
                 }
             }
-            Expr::Infix { left, op, right } => {
-                let left_type = self.analyze_expression(left)?;
-                let right_type = self.analyze_expression(right)?;
+            ExprKind::Prefix { op, operand } => {
+                let operand_type = self.analyze_value(operand)?;
+                match (op.as_str(), &operand_type) {
+                    ("-", Type::Int) | ("-", Type::Float) | ("!", Type::Bool) => Ok(operand_type),
+                    _ => bail!("Operador {} no aplicable a {:?}", op, operand_type),
+                }
+            }
+            ExprKind::Infix { left, op, right } => {
+                let left_type = self.analyze_value(left)?;
+                let right_type = self.analyze_value(right)?;
                 
                 match op.as_str() {
                     "+" | "-" | "*" | "/" => {
+                        // No hay conversión implícita entre int y float
                         if left_type == Type::Int && right_type == Type::Int {
                             Ok(Type::Int)
-                        } else if left_type == Type::String && op == "+" {
+                        } else if matches!(left_type, Type::Int | Type::Char)
+                            && matches!(right_type, Type::Int | Type::Char)
+                        {
+                            // Un char opera con su código: 'a' + 1, c - '0'
+                            Ok(Type::Int)
+                        } else if left_type == Type::Float && right_type == Type::Float {
+                            Ok(Type::Float)
+                        } else if left_type == Type::String && right_type == Type::String && op == "+" {
+                            Ok(Type::String)
+                        } else if left_type == Type::String && right_type == Type::Int && op == "*" {
+                            // El runtime no sabe repetir cadenas: solo se admite
+                            // si se puede resolver en compilación
+                            if expr.constant_string().is_none() {
+                                bail!(
+                                    "La repetición de cadenas solo admite un literal y un entero literal, \
+                                     con un resultado de hasta {} bytes",
+                                    MAX_CONSTANT_STRING
+                                );
+                            }
                             Ok(Type::String)
                         } else {
                             bail!("Operación aritmética inválida entre {:?} y {:?}", left_type, right_type)
                         }
                     }
+                    "&&" | "||" => {
+                        if left_type == Type::Bool && right_type == Type::Bool {
+                            Ok(Type::Bool)
+                        } else {
+                            bail!("El operador {} requiere operandos booleanos, encontrado {:?} y {:?}", op, left_type, right_type)
+                        }
+                    }
+                    "%" => {
+                        if left_type == Type::Int && right_type == Type::Int {
+                            Ok(Type::Int)
+                        } else {
+                            bail!("El operador % requiere operandos enteros, encontrado {:?} y {:?}", left_type, right_type)
+                        }
+                    }
                     "==" | "!=" | "<" | ">" | "<=" | ">=" => {
                         if self.type_system.is_comparable(&left_type, &right_type) {
                             Ok(Type::Bool)
@@ -256,31 +854,499 @@ impl SemanticAnalyzer {
                     _ => bail!("Operador desconocido: {}", op),
                 }
             }
-            Expr::Call { function, args } => {
-                if let Some(symbol) = self.symbols.get(function) {
+            ExprKind::Call { function, args } => {
+                self.infer_return_type(function)?;
+                if self.unknown_returns.contains(function) {
+                    bail!(
+                        "no se puede deducir el tipo de retorno de '{}' porque depende de sí misma; indícalo con -> tipo",
+                        function
+                    );
+                }
+                if let Some(symbol) = self.lookup(function) {
                     if !symbol.is_function {
                         bail!("'{}' no es una función", function);
                     }
+                    let return_type = symbol.type_.clone();
                     
-                    if let Some(expected_params) = &symbol.params {
-                        if args.len() != expected_params.len() {
-                            bail!("Número incorrecto de argumentos para '{}'", function);
+                    let Some(mut expected_params) = symbol.params.clone() else {
+                        return self.analyze_untyped_builtin(function, args);
+                    };
+                    // Los argumentos que sobran tras los fijos van al
+                    // parámetro variádico, cada uno con su tipo de elemento
+                    if let Some(Type::Variadic(element_type)) = expected_params.last().cloned() {
+                        expected_params.pop();
+                        if args.len() < expected_params.len() {
+                            bail!(
+                                "'{}' espera al menos {} argumento(s)",
+                                function,
+                                expected_params.len()
+                            );
                         }
-                        
-                        for (arg, expected_type) in args.iter().zip(expected_params.iter()) {
-                            let arg_type = self.analyze_expression(arg)?;
-                            if !self.type_system.is_compatible(&arg_type, expected_type) {
-                                bail!("Tipo de argumento incorrecto");
-                            }
+                        expected_params.resize(args.len(), *element_type);
+                    }
+                    let optional = self.default_params.get(function).copied().unwrap_or(0);
+                    if args.len() > expected_params.len() || args.len() + optional < expected_params.len() {
+                        bail!("Número incorrecto de argumentos para '{}'", function);
+                    }
+                    
+                    for (arg, expected_type) in args.iter().zip(expected_params.iter()) {
+                        let arg_type = self.analyze_value(arg)?;
+                        if !self.type_system.is_compatible(&arg_type, expected_type) {
+                            bail!("Tipo de argumento incorrecto");
                         }
                     }
                     
-                    Ok(symbol.type_.clone())
+                    Ok(return_type)
                 } else {
                     bail!("Función '{}' no declarada", function);
                 }
             }
-            Expr::Grouped(expr) => self.analyze_expression(expr),
+            ExprKind::CallExpr { callee, .. } => {
+                // Todavía no hay valores de tipo función
+                let callee_type = self.analyze_value(callee)?;
+                bail!("No se puede llamar a un valor de tipo {:?}", callee_type);
+            }
+            ExprKind::Grouped(expr) => self.analyze_expression(expr),
+            ExprKind::Ternary { condition, then_expr, else_expr } => {
+                self.analyze_condition(condition, "operador ternario")?;
+                let then_type = self.analyze_value(then_expr)?;
+                let else_type = self.analyze_value(else_expr)?;
+                if !self.type_system.is_compatible(&else_type, &then_type) {
+                    bail!(
+                        "Las ramas del operador ternario tienen tipos distintos: {:?} y {:?}",
+                        then_type,
+                        else_type
+                    );
+                }
+                Ok(then_type)
+            }
+            ExprKind::New { type_, size } => {
+                let type_ = self.resolve_type(type_)?;
+                if !matches!(type_, Type::Array(_)) {
+                    bail!("'new' requiere un tipo array, encontrado {:?}", type_);
+                }
+                if self.analyze_value(size)? != Type::Int {
+                    bail!("El tamaño de 'new' debe ser entero");
+                }
+                Ok(type_)
+            }
+            ExprKind::EnumVariant { enum_name, variant } => match self.enums.get(enum_name) {
+                Some(variants) if variants.contains(variant) => Ok(Type::Enum(enum_name.clone())),
+                Some(_) => bail!("el enum '{}' no tiene la variante '{}'", enum_name, variant),
+                None => bail!("'{}' no es un enum", enum_name),
+            },
+        }
+    }
+}
+
+// Un bloque retorna siempre si alguna de sus sentencias lo hace; los
+// bucles no cuentan porque su cuerpo puede no ejecutarse.
+fn always_returns(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If { then_block, else_block: Some(else_block), .. } => {
+            always_returns(then_block) && always_returns(else_block)
+        }
+        _ => false,
+    })
+}
+
+// Si algún return del cuerpo, a cualquier profundidad, lleva valor
+fn returns_value(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return(value) => value.is_some(),
+        StmtKind::If { then_block, else_block, .. } => {
+            returns_value(then_block) || else_block.as_deref().is_some_and(returns_value)
+        }
+        StmtKind::While { body, .. } | StmtKind::For { body, .. } => returns_value(body),
+        _ => false,
+    })
+}
+
+// Distancia de edición entre dos nombres (inserciones, borrados y
+// sustituciones de caracteres)
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{levenshtein, SemanticAnalyzer};
+    use crate::testing::{analyze, parse};
+
+    fn error(source: &str) -> String {
+        analyze(source).err().expect("se esperaba un error").to_string()
+    }
+
+    #[test]
+    fn string_concatenation_needs_two_strings() {
+        assert!(analyze("fn main() -> int { let s = \"a\" + \"b\"; print(s); return 0; }").is_ok());
+        assert!(error("fn main() -> int { let s = \"a\" + 5; return 0; }").contains("Operación aritmética inválida"));
+        assert!(error("fn main() -> int { let s = \"a\" + true; return 0; }").contains("Operación aritmética inválida"));
+    }
+
+    #[test]
+    fn huge_string_repetition_is_an_error_not_a_panic() {
+        assert!(analyze("fn main() -> int { print(\"ab\" * 3); return 0; }").is_ok());
+        for count in ["9223372036854775807", "1000000"] {
+            let source = format!("fn main() -> int {{ print(\"ab\" * {}); return 0; }}", count);
+            assert!(error(&source).contains("bytes"));
+        }
+    }
+
+    #[test]
+    fn global_constant_needs_a_compile_time_value() {
+        assert!(analyze("const K = 3; const L = K * 2 + 1; fn main() -> int { return L; }").is_ok());
+        assert!(error("fn g() -> int { return 1; } const K = g(); fn main() -> int { return 0; }")
+            .contains("constante global 'K'"));
+        assert!(error("let v = 1; const K = v; fn main() -> int { return 0; }").contains("constante global 'K'"));
+        // Dentro de una función el valor puede ser cualquiera
+        assert!(analyze("fn g() -> int { return 1; } fn main() -> int { const K = g(); return K; }").is_ok());
+    }
+
+    #[test]
+    fn declarations_without_value_need_a_type_with_a_default() {
+        assert!(analyze("fn main() -> int { let n: int; let b: bool; n = 1; return n; }").is_ok());
+        let source = "fn main() -> int { let x: int; let y: bool = x; return 0; }";
+        assert!(error(source).contains("Tipo incompatible en declaración de variable 'y'"));
+        assert!(error("fn main() -> int { let v: void; return 0; }").contains("'v' necesita un valor inicial"));
+        // Sin tipo no hay de dónde sacar el valor, y una constante siempre lo necesita
+        assert!(analyze("fn main() -> int { let x; return 0; }").is_err());
+        assert!(error("fn main() -> int { const K: int; return 0; }").contains("necesita un valor inicial"));
+    }
+
+    #[test]
+    fn len_accepts_strings_and_arrays() {
+        assert!(analyze("fn count(nums: ...int) -> int { return len(nums); } fn main() -> int { return len([1, 2]); }").is_ok());
+        assert!(error("fn main() -> int { return len(5); }").contains("len requiere un string o un array"));
+        assert!(error("fn main() -> int { return len(\"a\", \"b\"); }").contains("Número incorrecto"));
+    }
+
+    #[test]
+    fn new_needs_an_array_type_and_an_int_size() {
+        assert!(analyze("fn main() -> int { let a: [int] = new [int](5); return a[0]; }").is_ok());
+        assert!(error("fn main() -> int { let a = new int(5); return 0; }").contains("'new' requiere un tipo array"));
+        assert!(error("fn main() -> int { let a = new [int](true); return 0; }").contains("debe ser entero"));
+        assert!(error("fn main() -> int { let a: [bool] = new [int](2); return 0; }").contains("Tipo incompatible"));
+    }
+
+    #[test]
+    fn tuples_are_typed_by_position() {
+        let divmod = "fn divmod(a: int, b: int) -> (int, int) { return (a / b, a % b); }";
+        let ok = [
+            "fn main() -> int { let (q, r) = divmod(7, 2); return q + r; }",
+            "fn main() -> int { let t: (int, bool) = (1, true); if (t.1) { return t.0; } return 0; }",
+            "fn main() -> int { let [x, y] = [1, 2]; return x + y; }",
+        ];
+        for main in ok {
+            assert!(analyze(&format!("{} {}", divmod, main)).is_ok(), "{}", main);
+        }
+        let errors = [
+            ("fn main() -> int { let (a, b, c) = divmod(7, 2); return a; }", "El patrón tiene 3 elementos pero la tupla tiene 2"),
+            ("fn main() -> int { let [a, b, c] = [1, 2]; return a; }", "El patrón tiene 3 elementos pero el array tiene 2"),
+            ("fn main() -> int { let (a, b) = [1, 2]; return a; }", "Solo se puede desestructurar una tupla"),
+            ("fn main() -> int { return divmod(7, 2).2; }", "no existe el índice 2"),
+            ("fn main() -> int { let t: (int, int) = (1, true); return 0; }", "Tipo incompatible"),
+            ("fn main() -> int { let t = (1, 2); return t; }", "Tipo de retorno incompatible"),
+            ("fn main() -> int { print((1, 2)); return 0; }", "print no admite valores de tipo Tuple"),
+        ];
+        for (main, message) in errors {
+            let err = error(&format!("{} {}", divmod, main));
+            assert!(err.contains(message), "{}: {}", main, err);
+        }
+    }
+
+    #[test]
+    fn annotated_let_coerces_bool_to_int_but_not_back() {
+        assert!(analyze("fn main() -> int { let x: int = true; let c: int = 'a'; return x + c; }").is_ok());
+        assert!(error("fn main() -> int { let b: bool = 1; return 0; }")
+            .contains("Tipo incompatible en declaración de variable 'b'"));
+        assert!(error("fn main() -> int { let c: char = 65; return 0; }")
+            .contains("Tipo incompatible en declaración de variable 'c'"));
+    }
+
+    #[test]
+    fn int_main_must_return_on_every_path() {
+        for body in ["print(1);", "if (true) { return 1; }", "while (true) { return 0; }"] {
+            let source = format!("fn main() -> int {{ {} }}", body);
+            assert!(error(&source).contains("la función 'main' puede terminar sin retornar un valor"), "{}", body);
+        }
+        assert!(analyze("fn main() -> int { if (true) { return 1; } else { return 0; } }").is_ok());
+        assert!(analyze("fn main() { print(1); }").is_ok());
+    }
+
+    #[test]
+    fn undeclared_type_names_are_a_semantic_error() {
+        assert!(error("fn main() -> int { let p: Point = 1; return 0; }").contains("Tipo desconocido: Point"));
+        assert!(error("fn f(p: Point) {} fn main() -> int { return 0; }").contains("Tipo desconocido: Point"));
+        assert!(analyze("enum Color { Red } fn main() -> int { let c: Color = Red; return 0; }").is_ok());
+    }
+
+    #[test]
+    fn parameters_shadowing_builtins_or_globals_warn_when_asked() {
+        let warnings = |source: &str, enabled: bool| {
+            let mut analyzer = SemanticAnalyzer::new().with_shadow_warnings(enabled);
+            analyzer.analyze(&parse(source).unwrap()).unwrap();
+            analyzer.warnings().to_vec()
+        };
+        let source = "let total = 0; fn f(len: int, total: int, n: int) -> int { return len + total + n; }";
+        assert_eq!(
+            warnings(source, true),
+            [
+                "el parámetro 'len' oculta una función o variable global",
+                "el parámetro 'total' oculta una función o variable global",
+            ]
+        );
+        assert!(warnings(source, false).is_empty());
+    }
+
+    #[test]
+    fn write_takes_an_int_or_a_string() {
+        assert!(analyze("fn main() -> int { write(\"a\"); write(\"b\"); write(1); write_string(\"c\"); return 0; }").is_ok());
+        assert!(error("fn main() -> int { write(true); return 0; }").contains("write requiere un entero o un string"));
+        assert!(error("fn main() -> int { write(1, 2); return 0; }").contains("Número incorrecto de argumentos"));
+    }
+
+    // f()() se acepta al analizar y se rechaza aquí mientras no haya
+    // valores de tipo función
+    #[test]
+    fn calling_a_non_function_value_is_a_semantic_error() {
+        let source = "fn f() -> int { return 1; } fn main() -> int { return f()(); }";
+        assert!(error(source).contains("No se puede llamar a un valor de tipo Int"));
+    }
+
+    #[test]
+    fn nested_functions_are_rejected() {
+        for body in ["if (true) { fn g() {} }", "while (false) { fn g() {} }", "fn g() {}"] {
+            let source = format!("fn main() -> int {{ {} return 0; }}", body);
+            assert!(error(&source).contains("funciones anidadas no soportadas: 'g'"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn void_values_cannot_be_used_as_values() {
+        let bodies = [
+            "if (v() == 0) { return 1; }",
+            "let x = v();",
+            "let y = 1 + v();",
+            "print(v());",
+        ];
+        for body in bodies {
+            let source = format!("fn v() {{}} fn main() -> int {{ {} return 0; }}", body);
+            assert!(error(&source).contains("no se puede usar un valor de tipo void en una expresión"), "{}", body);
+        }
+        // Como sentencia sí se puede llamar
+        assert!(analyze("fn v() {} fn main() -> int { v(); return 0; }").is_ok());
+    }
+
+    #[test]
+    fn ternary_arms_must_share_a_type() {
+        assert!(analyze("fn main() -> int { let a = true; let x = a ? 1 : a ? 2 : 3; return x; }").is_ok());
+        let mismatched = [
+            ("a ? 1 : \"s\"", "Int y String"),
+            ("a ? 1 : a ? 2 : \"s\"", "Int y String"),
+            ("a ? a ? 1 : 2 : true", "Int y Bool"),
+        ];
+        for (expr, types) in mismatched {
+            let source = format!("fn main() -> int {{ let a = true; let x = {}; return 0; }}", expr);
+            let err = error(&source);
+            assert!(err.contains("Las ramas del operador ternario tienen tipos distintos") && err.contains(types), "{}", err);
+        }
+    }
+
+    #[test]
+    fn negation_needs_a_number_and_not_a_bool() {
+        assert!(analyze("fn main() -> int { let y = -5; let z = !true; let f = -1.5; if (z) { return y; } return 0; }").is_ok());
+        assert!(error("fn main() -> int { let y: bool = -5; return 0; }").contains("Tipo incompatible"));
+        assert!(error("fn main() -> int { let z = -true; return 0; }").contains("Operador - no aplicable a Bool"));
+        assert!(error("fn main() -> int { let z = !1; return 0; }").contains("Operador ! no aplicable a Int"));
+    }
+
+    #[test]
+    fn only_known_function_attributes_are_accepted() {
+        assert!(analyze("@inline fn f() {} @noinline fn g() {} fn main() -> int { f(); g(); return 0; }").is_ok());
+        assert!(error("@hot fn f() {} fn main() -> int { return 0; }").contains("Atributo desconocido: @hot"));
+        assert!(error("@inline @noinline fn f() {} fn main() -> int { return 0; }")
+            .contains("@inline y @noinline no se pueden combinar en 'f'"));
+    }
+
+    #[test]
+    fn undeclared_variables_are_located_and_get_a_suggestion() {
+        let source = "fn main() -> int {\n    let count = 1;\n    return cout + 1;\n}";
+        assert_eq!(
+            error(source),
+            "error en línea 3, columna 12: Variable 'cout' no declarada; ¿quisiste decir 'count'?"
+        );
+        // Más de dos ediciones de distancia no se sugiere; las funciones tampoco
+        let err = error("fn main() -> int { let count = 1; return total; }");
+        assert!(err.ends_with("Variable 'total' no declarada"), "{}", err);
+        let err = error("fn main() -> int { return mainn; }");
+        assert!(err.ends_with("Variable 'mainn' no declarada"), "{}", err);
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("cout", "count"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("año", "ano"), 1);
+    }
+
+    #[test]
+    fn bools_are_not_numbers() {
+        for expr in ["true + 1", "(1 < 2) + 1", "1 * false"] {
+            let err = error(&format!("fn main() -> int {{ let x = {}; return 0; }}", expr));
+            assert!(err.contains("Operación aritmética inválida"), "{}: {}", expr, err);
+        }
+    }
+
+    #[test]
+    fn int_conditions_need_truthy_mode() {
+        let cases = [
+            ("if", "if (x + 1) { print(1); }"),
+            ("while", "while (x) { x = x - 1; }"),
+            ("for", "for (let i = 3; i; i = i - 1) { print(i); }"),
+            ("operador ternario", "print(x ? 1 : 2);"),
+        ];
+        for (construct, body) in cases {
+            let source = format!("fn main() -> int {{ let x = 2; {} return 0; }}", body);
+            let hint = format!("Condición del {} debe ser booleana; compara con 0 (x != 0) o compila con --truthy", construct);
+            assert!(error(&source).contains(&hint), "{}", body);
+            let mut truthy = SemanticAnalyzer::new().with_truthiness(true);
+            assert!(truthy.analyze(&parse(&source).unwrap()).is_ok(), "{}", body);
+        }
+        // Otros tipos no son condiciones ni con --truthy, y no llevan la pista
+        let source = "fn main() -> int { if (\"s\") { print(1); } return 0; }";
+        let err = SemanticAnalyzer::new().with_truthiness(true).analyze(&parse(source).unwrap()).unwrap_err();
+        assert!(err.to_string().ends_with("Condición del if debe ser booleana"), "{}", err);
+    }
+
+    #[test]
+    fn enum_variants_compare_only_with_their_own_enum() {
+        let enums = "enum Color { Red, Green } enum Size { Small, Large } ";
+        let ok = format!(
+            "{}fn same(c: Color) -> bool {{ return c == Color.Red || Green != c; }} \
+             fn main() -> int {{ let c: Color = Red; print(same(c)); return 0; }}",
+            enums
+        );
+        assert!(analyze(&ok).is_ok());
+        let cases = [
+            ("Red == Small", "No se pueden comparar"),
+            ("Red == 0", "No se pueden comparar"),
+            ("Color.Blue == Red", "el enum 'Color' no tiene la variante 'Blue'"),
+            ("Shape.Red == Red", "'Shape' no es un enum"),
+        ];
+        for (comparison, expected) in cases {
+            let source = format!("{}fn main() -> int {{ let b = {}; return 0; }}", enums, comparison);
+            assert!(error(&source).contains(expected), "{}: {}", comparison, error(&source));
+        }
+        assert!(error("enum Color { Red, Red } fn main() -> int { return 0; }").contains("repetida"));
+        assert!(error("fn main() -> int { let c: Color = Red; Red = Red; return 0; } enum Color { Red }")
+            .contains("no se puede asignar a la constante 'Red'"));
+    }
+
+    #[test]
+    fn default_parameters_are_checked_where_declared_and_at_calls() {
+        let greet = "fn greet(name: string, greeting: string = \"Hello\") { write(greeting); print(name); } ";
+        let ok = format!("{}fn main() -> int {{ greet(\"x\"); greet(\"x\", \"Hi\"); return 0; }}", greet);
+        assert!(analyze(&ok).is_ok());
+        let cases = [
+            (format!("{}fn main() -> int {{ greet(); return 0; }}", greet), "Número incorrecto de argumentos para 'greet'"),
+            (format!("{}fn main() -> int {{ greet(\"x\", \"Hi\", \"!\"); return 0; }}", greet), "Número incorrecto"),
+            (format!("{}fn main() -> int {{ greet(\"x\", 1); return 0; }}", greet), "Tipo"),
+            (
+                "fn f(x: int = true) -> int { return x; } fn main() -> int { return f(); }".to_string(),
+                "El valor por defecto de 'x' es Bool, se esperaba Int",
+            ),
+            (
+                "fn f(x: int, y: int = x) -> int { return y; } fn main() -> int { return f(1); }".to_string(),
+                "'x'",
+            ),
+            (
+                "fn f(x: int = 1, ys: ...int = 2) -> int { return x; } fn main() -> int { return f(); }".to_string(),
+                "no puede tener a la vez parámetros por defecto y variádicos",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert!(error(&source).contains(expected), "{}: {}", source, error(&source));
+        }
+    }
+
+    #[test]
+    fn is_empty_takes_strings_or_arrays_and_is_bool() {
+        let source = "fn main() -> int { let s = \"\"; let a: bool = is_empty(\"\"); \
+                      let b: bool = is_empty(s) || is_empty([1, 2]); return 0; }";
+        assert!(analyze(source).is_ok());
+        let cases = [
+            ("let n: string = is_empty(\"\");", "Tipo incompatible en declaración de variable 'n'"),
+            ("let b = is_empty(1);", "is_empty requiere un string o un array, encontrado Int"),
+            ("let b = is_empty(\"a\", \"b\");", "Número incorrecto de argumentos para 'is_empty'"),
+        ];
+        for (stmt, expected) in cases {
+            let err = error(&format!("fn main() -> int {{ {} return 0; }}", stmt));
+            assert!(err.contains(expected), "{}: {}", stmt, err);
+        }
+    }
+
+    #[test]
+    fn print_rejects_values_it_cannot_show() {
+        assert!(analyze("fn main() -> int { print(\"hi\"); print(42); print(true); return 0; }").is_ok());
+        let err = error("fn main() -> int { let a = [1, 2]; print(a); return 0; }");
+        assert!(err.contains("print no admite valores de tipo Array(Int)"), "{}", err);
+        assert!(error("fn main() -> int { print((1, 2)); return 0; }").contains("print no admite valores de tipo Tuple"));
+    }
+
+    #[test]
+    fn assert_eq_takes_two_ints_or_two_strings() {
+        assert!(analyze("fn main() -> int { assert_eq(1, 2); assert_eq(\"a\", \"b\"); return 0; }").is_ok());
+        let cases = [
+            ("assert_eq(1, \"a\");", "assert_eq requiere dos enteros o dos strings, encontrado Int y String"),
+            ("assert_eq(true, true);", "encontrado Bool y Bool"),
+            ("assert_eq(1);", "Número incorrecto de argumentos para 'assert_eq'"),
+        ];
+        for (stmt, expected) in cases {
+            let err = error(&format!("fn main() -> int {{ {} return 0; }}", stmt));
+            assert!(err.contains(expected), "{}: {}", stmt, err);
+        }
+    }
+
+    #[test]
+    fn omitted_return_types_are_inferred_from_the_returns() {
+        let sources = [
+            "fn f() { return 5; } fn main() -> int { let x: int = f(); return x; }",
+            "fn main() -> int { return f() + 1; } fn f() { return 5; }",
+            "fn greet() { return \"hola\"; } fn main() -> int { let s: string = greet(); return 0; }",
+            "fn fact(n: int) { if (n <= 1) { return 1; } return n * fact(n - 1); } fn main() -> int { return fact(5); }",
+            "fn countdown(n: int) { if (n > 0) { countdown(n - 1); } } fn main() -> int { countdown(3); return 0; }",
+        ];
+        for source in sources {
+            assert!(analyze(source).is_ok(), "{}: {:?}", source, analyze(source).err());
+        }
+        let cases = [
+            ("fn f() { return 5; } fn main() -> int { let s: string = f(); return 0; }", "Tipo incompatible en declaración de variable 's'"),
+            (
+                "fn f(x: int) { if (x > 0) { return 1; } return \"no\"; } fn main() -> int { return 0; }",
+                "los return de 'f' no coinciden: Int y String; indica el tipo con -> tipo",
+            ),
+            ("fn f(x: int) { if (x > 0) { return; } return 2; } fn main() -> int { return 0; }", "no coinciden: Void y Int"),
+            ("fn g() { print(1); } fn main() -> int { let x = g(); return 0; }", "void"),
+            (
+                "fn loop(n: int) { return loop(n - 1); } fn main() -> int { return 0; }",
+                "no se puede deducir el tipo de retorno de 'loop' porque depende de sí misma",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert!(error(source).contains(expected), "{}: {}", source, error(source));
         }
     }
 }
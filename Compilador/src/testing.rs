@@ -0,0 +1,132 @@
+// Atajos para los tests: llevan un fuente por las fases del compilador
+// igual que main, parando en la que interesa a cada test.
+use crate::ir::builder::IRBuilder;
+use crate::ir::{IRFunction, IROp, IRProgram, IRValue};
+use crate::lexer::Lexer;
+use crate::parser::ast::Program;
+use crate::parser::Parser;
+use crate::semantic::SemanticAnalyzer;
+use anyhow::Result;
+use std::collections::HashMap;
+
+pub fn parse(source: &str) -> Result<Program> {
+    Parser::new(Lexer::new(source.to_string()))?.parse_program()
+}
+
+pub fn analyze(source: &str) -> Result<SemanticAnalyzer> {
+    let program = parse(source)?;
+    let mut analyzer = SemanticAnalyzer::new();
+    analyzer.analyze(&program)?;
+    Ok(analyzer)
+}
+
+pub fn build_ir(source: &str) -> IRProgram {
+    let program = parse(source).unwrap();
+    let mut analyzer = SemanticAnalyzer::new();
+    analyzer.analyze(&program).unwrap();
+    IRBuilder::new()
+        .with_expression_types(analyzer.expression_types().clone())
+        .build(&program)
+        .unwrap()
+}
+
+// Ejecuta `test` con la misma pila que el compilador (ver main)
+pub fn with_compiler_stack(test: impl FnOnce() + Send + 'static) {
+    std::thread::Builder::new()
+        .stack_size(crate::COMPILER_STACK_SIZE)
+        .spawn(test)
+        .unwrap()
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+}
+
+pub fn function<'a>(program: &'a IRProgram, name: &str) -> &'a IRFunction {
+    program.functions.iter().find(|f| f.name == name).unwrap()
+}
+
+// Intérprete del IR para comprobar que una optimización no cambia el
+// resultado. Cubre la aritmética, los saltos, print y las llamadas entre
+// funciones del programa; no las del runtime ni los arrays.
+pub fn run(program: &IRProgram, name: &str, args: &[i64]) -> (Option<i64>, Vec<i64>) {
+    let mut printed = Vec::new();
+    let mut steps = 0;
+    let result = call(program, name, args, &mut printed, &mut steps);
+    (result, printed)
+}
+
+fn call(program: &IRProgram, name: &str, args: &[i64], printed: &mut Vec<i64>, steps: &mut usize) -> Option<i64> {
+    let function = function(program, name);
+    let mut values: HashMap<IRValue, i64> = function
+        .params
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| (IRValue::Local(param.clone()), *arg))
+        .collect();
+    let labels: HashMap<&str, usize> = function
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instr)| match instr {
+            IROp::Label(label) => Some((label.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut pc = 0;
+    while let Some(instr) = function.instructions.get(pc) {
+        *steps += 1;
+        assert!(*steps < 1_000_000, "el programa no termina");
+        pc += 1;
+        let get = |value: &IRValue| match value {
+            IRValue::Const(n) => *n,
+            other => *values.get(other).unwrap_or_else(|| panic!("{} sin valor en {}", other, name)),
+        };
+        let (target, value) = match instr {
+            IROp::Add(r, a, b) => (r, get(a).wrapping_add(get(b))),
+            IROp::Sub(r, a, b) => (r, get(a).wrapping_sub(get(b))),
+            IROp::Mul(r, a, b) => (r, get(a).wrapping_mul(get(b))),
+            IROp::Div(r, a, b) => (r, get(a).wrapping_div(get(b))),
+            IROp::Mod(r, a, b) => (r, get(a).wrapping_rem(get(b))),
+            IROp::Shl(r, a, b) => (r, get(a) << (get(b) & 63)),
+            IROp::Sar(r, a, b) => (r, get(a) >> (get(b) & 63)),
+            IROp::Shr(r, a, b) => (r, ((get(a) as u64) >> (get(b) & 63)) as i64),
+            IROp::And(r, a, b) => (r, get(a) & get(b)),
+            IROp::Or(r, a, b) => (r, get(a) | get(b)),
+            IROp::CmpEq(r, a, b) => (r, (get(a) == get(b)) as i64),
+            IROp::CmpNe(r, a, b) => (r, (get(a) != get(b)) as i64),
+            IROp::CmpLt(r, a, b) => (r, (get(a) < get(b)) as i64),
+            IROp::CmpLe(r, a, b) => (r, (get(a) <= get(b)) as i64),
+            IROp::CmpGt(r, a, b) => (r, (get(a) > get(b)) as i64),
+            IROp::CmpGe(r, a, b) => (r, (get(a) >= get(b)) as i64),
+            IROp::Assign(r, a) => (r, get(a)),
+            IROp::Select(r, cond, a, b) => (r, if get(cond) != 0 { get(a) } else { get(b) }),
+            IROp::Call(callee, call_args, result) => {
+                let call_args: Vec<i64> = call_args.iter().map(get).collect();
+                let value = call(program, callee, &call_args, printed, steps);
+                match (result, value) {
+                    (Some(result), Some(value)) => (result, value),
+                    _ => continue,
+                }
+            }
+            IROp::Label(_) => continue,
+            IROp::Jump(label) => {
+                pc = labels[label.as_str()];
+                continue;
+            }
+            IROp::JumpIfZero(cond, label) | IROp::JumpIfNotZero(cond, label) => {
+                if (get(cond) == 0) == matches!(instr, IROp::JumpIfZero(..)) {
+                    pc = labels[label.as_str()];
+                }
+                continue;
+            }
+            IROp::Return(value) => return value.as_ref().map(get),
+            IROp::Print(value, _) => {
+                printed.push(get(value));
+                continue;
+            }
+            other => panic!("instrucción no soportada: {}", other),
+        };
+        values.insert(target.clone(), value);
+    }
+    None
+}
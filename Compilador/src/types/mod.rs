@@ -1,5 +1,18 @@
 use crate::parser::ast::Type;
 
+// Valor inicial de un tipo en `let x: T;`, con el tipo explícito para que
+// cada backend emita la inicialización correcta (un bool por defecto no es
+// un int).
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    EmptyArray,
+}
+
 #[derive(Debug)]
 pub struct TypeSystem;
 
@@ -11,30 +24,82 @@ impl TypeSystem {
     pub fn is_compatible(&self, from: &Type, to: &Type) -> bool {
         match (from, to) {
             (Type::Int, Type::Int) => true,
+            (Type::Float, Type::Float) => true,
             (Type::Bool, Type::Bool) => true,
             (Type::String, Type::String) => true,
+            (Type::Char, Type::Char) => true,
             (Type::Array(a), Type::Array(b)) => self.is_compatible(a, b),
+            (Type::Tuple(a), Type::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.is_compatible(a, b))
+            }
             (Type::Void, Type::Void) => true,
+            (Type::Enum(a), Type::Enum(b)) => a == b,
             _ => false,
         }
     }
 
+    // Conversión implícita, más permisiva que is_compatible: como un bool
+    // vale 0 o 1 y un char su código, ambos se aceptan donde se espera un
+    // int en print y en un let con tipo, mientras que la asignación y los
+    // argumentos siguen usando is_compatible.
+    pub fn can_coerce(&self, from: &Type, to: &Type) -> bool {
+        self.is_compatible(from, to) || matches!((from, to), (Type::Bool | Type::Char, Type::Int))
+    }
+
     pub fn is_comparable(&self, left: &Type, right: &Type) -> bool {
-        match (left, right) {
-            (Type::Int, Type::Int) => true,
-            (Type::Bool, Type::Bool) => true,
-            (Type::String, Type::String) => true,
-            _ => false,
-        }
+        matches!(
+            (left, right),
+            (Type::Int, Type::Int)
+                | (Type::Float, Type::Float)
+                | (Type::Bool, Type::Bool)
+                | (Type::String, Type::String)
+                | (Type::Char, Type::Char)
+        ) || matches!((left, right), (Type::Enum(a), Type::Enum(b)) if a == b)
     }
 
-    pub fn get_default_value(&self, type_: &Type) -> String {
+    // void no tiene valor y los tipos con nombre deben resolverse antes
+    pub fn get_default_value(&self, type_: &Type) -> Option<DefaultValue> {
         match type_ {
-            Type::Int => "0".to_string(),
-            Type::Bool => "0".to_string(), // false
-            Type::String => "\"\"".to_string(),
-            Type::Array(_) => "[]".to_string(),
-            Type::Void => "void".to_string(),
+            Type::Int => Some(DefaultValue::Int(0)),
+            Type::Float => Some(DefaultValue::Float(0.0)),
+            Type::Bool => Some(DefaultValue::Bool(false)),
+            Type::String => Some(DefaultValue::String(String::new())),
+            Type::Char => Some(DefaultValue::Char('\0')),
+            Type::Array(_) => Some(DefaultValue::EmptyArray),
+            // La primera variante
+            Type::Enum(_) => Some(DefaultValue::Int(0)),
+            Type::Void | Type::Named(_) | Type::Variadic(_) | Type::Tuple(_) => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultValue, TypeSystem};
+    use crate::parser::ast::Type;
+
+    #[test]
+    fn each_type_has_its_own_default() {
+        let types = TypeSystem::new();
+        let bool_default = types.get_default_value(&Type::Bool).unwrap();
+        let int_default = types.get_default_value(&Type::Int).unwrap();
+        assert_eq!(bool_default, DefaultValue::Bool(false));
+        assert_eq!(int_default, DefaultValue::Int(0));
+        assert_ne!(bool_default, int_default);
+        assert_eq!(types.get_default_value(&Type::Char), Some(DefaultValue::Char('\0')));
+        assert_eq!(types.get_default_value(&Type::String), Some(DefaultValue::String(String::new())));
+        assert_eq!(types.get_default_value(&Type::Array(Box::new(Type::Bool))), Some(DefaultValue::EmptyArray));
+        assert_eq!(types.get_default_value(&Type::Void), None);
+        assert_eq!(types.get_default_value(&Type::Named("Color".to_string())), None);
+    }
+
+    #[test]
+    fn bool_and_char_coerce_to_int_but_not_back() {
+        let types = TypeSystem::new();
+        assert!(types.can_coerce(&Type::Bool, &Type::Int));
+        assert!(types.can_coerce(&Type::Char, &Type::Int));
+        assert!(!types.can_coerce(&Type::Int, &Type::Bool));
+        assert!(!types.can_coerce(&Type::Int, &Type::Char));
+        assert!(!types.is_compatible(&Type::Bool, &Type::Int));
+    }
+}